@@ -9,56 +9,143 @@ use std::{
     thread,
     time::Duration,
 };
-
-const SAVE_PATH: &'static str = "state.ron";
+use tracing::{error, info, warn};
+
+const SAVE_PATH: &str = "state.ron";
+/// How much longer than expected `/transition?action=preview&wait` waits before giving up.
+const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(30);
+/// Strengths closer than this are considered the same when saving the strength.
+const STRENGTH_EPSILON: f64 = 0.001;
+/// How long the fade back to the saved strength takes, see [`config::Config::restore_strength`].
+const RESTORE_FADE: Duration = Duration::from_secs(2);
+/// The number of samples `/preview-transition` returns if none are given.
+const PREVIEW_SAMPLES: usize = 100;
+/// The most samples `/preview-transition` returns, so a request can't make us allocate forever.
+const PREVIEW_SAMPLES_MAX: usize = 10_000;
+/// The longest scene name `/scene/save` accepts, in characters.
+const SCENE_NAME_MAX: usize = 64;
+
+/// Logs to stderr, filtered by the `RUST_LOG` environment variable, e.g. `RUST_LOG=httpwm=debug`.
+/// Everything at the info level and above is logged by default.
+fn init_logging() {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+}
 
 fn main() {
-    #[cfg(not(feature = "test"))]
-    let pwm = rppal::pwm::Pwm::with_period(
-        rppal::pwm::Channel::Pwm0,
-        Duration::from_millis(1),
-        Duration::from_millis(0),
-        rppal::pwm::Polarity::Normal,
-        true,
-    )
-    .expect("failed to get PWM");
-
-    #[cfg(feature = "test")]
-    let pwm = PrintOut;
-
-    let time = chrono::NaiveTime::from_hms(07, 00, 00);
-    let day_transition = Transition::default();
-
-    let startup_multiplier = Some(0.5);
-    let startup_duration = 1.0;
-    let startup_transition = Transition {
-        from: Strength::new(0.0),
-        to: Strength::new(1.0),
-        time: Duration::from_secs_f64(startup_duration),
-        interpolation: TransitionInterpolation::SineToAndBack(startup_multiplier.unwrap()),
+    let args = match args::Args::parse(std::env::args_os().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, args::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", args::USAGE);
+        return;
+    }
+    init_logging();
+    let config = config::Config::load(&args);
+    #[cfg(feature = "web")]
+    let auth = auth::Auth::from_config(&config);
+    #[cfg(feature = "web")]
+    let server = match config::ServerConfig::new(&config, &args) {
+        Ok(server) => server,
+        Err(err) => {
+            error!("Invalid server config: {}", err);
+            std::process::exit(2);
+        }
     };
 
-    let scheduler = scheduler::WeekScheduler::same(time, day_transition);
-
-    let (saved_state, week_scheduler) = {
-        let saved_state = save_state::Data::read_from_file(SAVE_PATH, &scheduler);
+    let clock = match config::clock(&config) {
+        Ok(clock) => clock,
+        Err(err) => {
+            error!("Invalid time zone config: {}", err);
+            std::process::exit(2);
+        }
+    };
+    let restore_strength = config.restore_strength;
 
-        match saved_state.ok().and_then(|state| {
-            state
-                .ref_week_scheduler()
-                .to_scheduler()
-                .map(|scheduler| (scheduler, state))
-        }) {
-            Some((scheduler, data)) => (data, scheduler),
-            None => {
-                eprintln!("Failed to parse state file. Using defaults.");
-                (save_state::Data::from_week_scheduler(&scheduler), scheduler)
-            }
+    let period = match config.pwm_frequency {
+        Some(frequency) if frequency > 0.0 && frequency.is_finite() => {
+            Duration::from_secs_f64(1.0 / frequency)
+        }
+        Some(frequency) => {
+            warn!("Invalid PWM frequency {}. Using default.", frequency);
+            DEFAULT_PWM_PERIOD
+        }
+        None => DEFAULT_PWM_PERIOD,
+    };
+    let pwm: Box<dyn VariableOut + Send> = match args.output {
+        args::OutputKind::Pwm => {
+            let pwm = rppal::pwm::Pwm::with_period(
+                rppal::pwm::Channel::Pwm0,
+                period,
+                Duration::from_millis(0),
+                rppal::pwm::Polarity::Normal,
+                true,
+            )
+            .expect("failed to get PWM");
+            Box::new(output::PwmOutput::new(pwm, period))
+        }
+        args::OutputKind::SoftPwm => {
+            let pin = rppal::gpio::Gpio::new()
+                .and_then(|gpio| gpio.get(args.pin))
+                .expect("failed to get GPIO pin")
+                .into_output();
+            Box::new(output::SoftPwm::new(pin, 1.0 / period.as_secs_f64()))
         }
+        args::OutputKind::Print => Box::new(PrintOut),
     };
-    let controller = Controller::new(pwm, week_scheduler);
+    info!(output = %args.output, "Using output");
+
+    let pwm = config::limit_output(pwm, &config);
+
+    let scheduler = config.week_scheduler.unwrap_or_else(|| {
+        let time = chrono::NaiveTime::from_hms(07, 00, 00);
+        let day_transition = Transition::default();
+        scheduler::WeekScheduler::same(time, day_transition)
+    });
+
+    let startup_transition = config.startup_transition.unwrap_or_else(|| Transition {
+        from: Strength::off(),
+        to: Strength::full(),
+        time: Duration::from_secs_f64(1.0),
+        interpolation: TransitionInterpolation::SineToAndBack(0.5),
+    });
+    let startup_duration = startup_transition.total_duration();
+
+    let (saved_state, week_scheduler) =
+        match save_state::Data::read_from_file(SAVE_PATH, &scheduler) {
+            Ok(data) => {
+                let scheduler = data.ref_week_scheduler().clone();
+                (data, scheduler)
+            }
+            Err(err) => {
+                match err.kind() {
+                    io::ErrorKind::NotFound => {
+                        info!("No state file found. Using defaults.")
+                    }
+                    _ => warn!("Failed to read state file: {}. Using defaults.", err),
+                }
+                (save_state::Data::from_week_scheduler(&scheduler), scheduler)
+            }
+        };
+    let controller = Controller::with_clock(pwm, week_scheduler, clock);
+    controller.set_rate_limit(config.set_rate_limit_ms.map(Duration::from_millis));
+    if args.dry_run {
+        controller
+            .send(Command::SetDryRun(true))
+            .expect("the controller stopped right after starting");
+    }
 
-    controller.send(Command::SetTransition(startup_transition));
+    controller
+        .send(Command::SetTransition(startup_transition))
+        .expect("the controller stopped right after starting");
 
     let shared = controller.get_state();
 
@@ -69,15 +156,18 @@ fn main() {
         let saved = Arc::clone(&saved_state);
         let controller = Arc::clone(&controller);
         thread::spawn(move || {
-            thread::sleep(Duration::from_secs_f64(
-                startup_duration * (startup_multiplier.unwrap_or(0.0) + 1.0),
-            ));
-            saved
+            thread::sleep(startup_duration);
+            let applied = saved
                 .lock()
                 .unwrap()
                 .get_ref()
-                .apply(&*controller.lock().unwrap());
+                .apply(&*controller.lock().unwrap(), restore_strength);
+            if applied.is_err() {
+                error!("Failed to apply saved state; the controller has stopped.");
+            }
 
+            // Also catches changes not made through the web interface, such as by schedulers.
+            let mut config_version = shared.lock().unwrap().config_version();
             thread::spawn(move || loop {
                 thread::sleep(Duration::from_millis(1000));
                 let mut saved = saved.lock().unwrap();
@@ -97,6 +187,14 @@ fn main() {
                 }
                 {
                     let shared = shared.lock().unwrap();
+                    if shared.config_version() != config_version {
+                        config_version = shared.config_version();
+                        saved
+                            .no_save_mut()
+                            .set_week_scheduler(shared.ref_week_schedule());
+                        saved.no_save_mut().set_scenes(shared.ref_scenes());
+                        changed = true;
+                    }
                     match saved.get_ref().eq_transition(shared.get_transition()) {
                         // Do nothing; they match
                         true => {}
@@ -105,10 +203,22 @@ fn main() {
                             changed = true;
                         }
                     }
+                    // Only the strength it settles on, not every step of a transition.
+                    let strength = shared.get_strength().into_inner();
+                    let saved_strength = saved.get_ref().ref_strength();
+                    if shared.get_transition().is_none()
+                        && saved_strength
+                            .is_none_or(|saved| (saved - strength).abs() > STRENGTH_EPSILON)
+                    {
+                        saved
+                            .no_save_mut()
+                            .set_strength(Strength::new_clamped(strength));
+                        changed = true;
+                    }
                 }
 
                 if saved.save() || changed {
-                    println!("Saving state!");
+                    info!("Saving state!");
 
                     let data = {
                         let config = ron::ser::PrettyConfig::default()
@@ -117,7 +227,7 @@ fn main() {
                             .with_extensions(ron::extensions::Extensions::IMPLICIT_SOME);
                         match ron::ser::to_string_pretty(saved.get_ref(), config) {
                             Err(err) => {
-                                eprintln!("Failed to save state {}", err);
+                                error!("Failed to save state {}", err);
                                 continue;
                             }
                             Ok(s) => s,
@@ -127,13 +237,13 @@ fn main() {
 
                     let mut file = match std::fs::File::create(SAVE_PATH) {
                         Err(err) => {
-                            eprintln!("Failed to create file {}", err);
+                            error!("Failed to create file {}", err);
                             continue;
                         }
                         Ok(f) => f,
                     };
                     if let Err(err) = file.write_all(data.as_bytes()) {
-                        eprintln!("Failed to write data to file {}", err);
+                        error!("Failed to write data to file {}", err);
                     }
                 }
             });
@@ -141,7 +251,7 @@ fn main() {
     }
 
     #[cfg(feature = "web")]
-    run(controller, saved_state, shared);
+    run(controller, saved_state, shared, auth, server);
 }
 
 #[cfg(feature = "web")]
@@ -158,8 +268,12 @@ async fn run<T: VariableOut + Send>(
     controller: Arc<Mutex<Controller<T>>>,
     save_state: Arc<Mutex<save_state::DataWrapper>>,
     shared: Arc<Mutex<SharedState>>,
+    auth: auth::Auth,
+    server: config::ServerConfig,
 ) {
-    create_server(controller, save_state, shared).run().await
+    create_server(controller, save_state, shared, auth, server)
+        .run()
+        .await
 }
 
 #[cfg(feature = "web")]
@@ -167,14 +281,22 @@ fn create_server<T: VariableOut + Send>(
     controller: Arc<Mutex<Controller<T>>>,
     save_state: Arc<Mutex<save_state::DataWrapper>>,
     shared: Arc<Mutex<SharedState>>,
+    auth: auth::Auth,
+    server: config::ServerConfig,
 ) -> kvarn::Config {
     let mut extensions = Extensions::new();
 
+    let clock = controller.lock().unwrap().clock();
+    let time = move || Arc::clone(&clock);
+
     let state = { move || Arc::clone(&shared) };
     let ctl = move || Arc::clone(&controller);
 
     let saved = move || Arc::clone(&save_state);
 
+    let auth = Arc::new(auth);
+    let authentication = move || Arc::clone(&auth);
+
     fn r200() -> FatResponse {
         (
             Response::new(Bytes::new()),
@@ -183,39 +305,106 @@ fn create_server<T: VariableOut + Send>(
             CompressPreference::None,
         )
     }
+    fn r400(message: String) -> FatResponse {
+        let mut response = Response::new(Bytes::from(message));
+        *response.status_mut() = StatusCode::BAD_REQUEST;
+        (
+            response,
+            ClientCachePreference::None,
+            ServerCachePreference::None,
+            CompressPreference::None,
+        )
+    }
+    fn r401() -> FatResponse {
+        let mut response = Response::new(Bytes::new());
+        *response.status_mut() = StatusCode::UNAUTHORIZED;
+        response
+            .headers_mut()
+            .insert("www-authenticate", http::HeaderValue::from_static("Bearer"));
+        (
+            response,
+            ClientCachePreference::None,
+            ServerCachePreference::None,
+            CompressPreference::None,
+        )
+    }
+    use handlers::{answer, unresponsive};
     async fn read_body(request: &mut FatRequest) -> io::Result<Bytes> {
         request.body_mut().read_to_bytes().await
     }
 
     let controller = ctl();
     let save = saved();
+    let auth = authentication();
     extensions.add_prepare_single(
         "/clear-schedulers".to_string(),
-        prepare!(_req, _host, _path, _addr, save controller, {
-            {
-                controller.lock().unwrap().send(Command::ClearAllSchedulers);
+        prepare!(request, host, _path, _addr, save controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            if controller.lock().unwrap().send(Command::ClearAllSchedulers).is_err() {
+                return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host).await;
             }
             save.lock().unwrap().get_mut().mut_schedulers().clear();
             r200()
         }),
     );
 
+    let controller = ctl();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/skip-next".to_string(),
+        prepare!(request, host, _path, _addr, controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            if controller.lock().unwrap().send(Command::SkipNext).is_err() {
+                return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host).await;
+            }
+            r200()
+        }),
+    );
+
+    let controller = ctl();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/undo".to_string(),
+        prepare!(request, host, _path, _addr, controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            if controller.lock().unwrap().send(Command::Undo).is_err() {
+                return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host).await;
+            }
+            r200()
+        }),
+    );
+
     let controller = ctl();
     let save = saved();
+    let auth = authentication();
     extensions.add_prepare_single(
         "/set-strength".to_string(),
-        prepare!(request, host, _path, _addr, save controller, {
+        prepare!(request, host, _path, _addr, save controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
             match get_query_value(request,  "strength")
-                .and_then(|value| value.parse().ok()) {
-                    Some(f) => {
-                    controller
+                .and_then(|value| value.parse().ok())
+                .and_then(|value| Strength::try_new(value).ok()) {
+                    Some(strength) => {
+                    if controller
                         .lock()
                         .unwrap()
-                        .send(Command::Set(Strength::new_clamped(f)));
+                        .send(Command::Set(strength))
+                        .is_err()
+                    {
+                        return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host).await;
+                    }
                     save.lock()
                         .unwrap()
                         .get_mut()
-                        .set_strength(Strength::new_clamped(f));
+                        .set_strength(strength);
                 },
                 None => return default_error_response(StatusCode::BAD_REQUEST, host).await,
                 }
@@ -224,9 +413,13 @@ fn create_server<T: VariableOut + Send>(
     );
     let controller = ctl();
     let save = saved();
+    let auth = authentication();
     extensions.add_prepare_single(
         "/set-day-time".to_string(),
-        prepare!( request,host, _path, _addr, save controller,  {
+        prepare!(request, host, _path, _addr, save controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
              let body = match read_body(request).await {
                  Ok(b) => b,
                  Err(_) => return default_error_response(StatusCode::BAD_REQUEST, host).await,
@@ -241,15 +434,18 @@ fn create_server<T: VariableOut + Send>(
                 Some((day, time)) => {
                     info!("Changed time of {} to {:?}", day, time);
 
+                    if controller
+                        .lock()
+                        .unwrap()
+                        .send(Command::ChangeDayTimer(day, time))
+                        .is_err()
                     {
-                        let mut lock = save.lock().unwrap();
-                        let week_scheduler = lock.get_mut().mut_week_scheduler();
-                        *week_scheduler.get_mut(day) =
-                            time.map(|time| time.format("%H:%M:%S").to_string());
+                        return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host).await;
                     }
                     {
-                        let lock = controller.lock().unwrap();
-                        lock.send(Command::ChangeDayTimer(day, time));
+                        let mut lock = save.lock().unwrap();
+                        let week_scheduler = lock.get_mut().mut_week_scheduler();
+                        *week_scheduler.get_mut(day) = time;
                     }
                 }
                 None =>
@@ -261,9 +457,59 @@ fn create_server<T: VariableOut + Send>(
 
     let controller = ctl();
     let save = saved();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/set-day-times".to_string(),
+        prepare!(request, host, _path, _addr, save controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            let body = match read_body(request).await {
+                Ok(b) => b,
+                Err(_) => return default_error_response(StatusCode::BAD_REQUEST, host).await,
+            };
+
+            let diff = serde_json::from_slice(&body)
+                .ok()
+                .and_then(|data: datas::DayTimesData| data.parse());
+            let diff = match diff {
+                Some(diff) => diff,
+                None => return default_error_response(StatusCode::BAD_REQUEST, host).await,
+            };
+
+            let receiver = { controller.lock().unwrap().send_schedule_diff(diff.clone()) };
+            // The controller answers as soon as it's gotten the command.
+            let changed = match answer(receiver).await {
+                Ok(changed) => changed,
+                Err(err) => return default_error_response(unresponsive(err), host).await,
+            };
+            if !changed.is_empty() {
+                let mut lock = save.lock().unwrap();
+                lock.get_mut().mut_week_scheduler().apply_diff(&diff);
+            }
+            info!("Changed time of {:?}", changed);
+
+            let changed: Vec<&str> = changed.iter().map(weekday_to_lowercase_str).collect();
+            let mut buffer = utility::WriteableBytes::new(BytesMut::with_capacity(64));
+            serde_json::to_writer(&mut buffer, &changed).expect("failed to write to Vec?");
+            (
+                Response::new(buffer.into_inner().freeze()),
+                ClientCachePreference::None,
+                ServerCachePreference::None,
+                CompressPreference::None,
+            )
+        }),
+    );
+
+    let controller = ctl();
+    let save = saved();
+    let auth = authentication();
     extensions.add_prepare_single(
         "/transition".to_string(),
-        prepare!(request, host, _path, _addr, save controller, {
+        prepare!(request, host, _path, _addr, save controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
             let body = match read_body(request).await {
                 Ok(b) => b,
                 Err(_) => return default_error_response(StatusCode::BAD_REQUEST, host).await,
@@ -271,39 +517,107 @@ fn create_server<T: VariableOut + Send>(
 
             let queries = request.uri().query().map(|q| parse::format_query(q));
             let action = queries.as_ref().and_then(|q| q.get("action")).map(|a| *a);
-            let transition = serde_json::from_slice(&body)
-                .ok()
-                .and_then(|set_transition: datas::TransitionData| set_transition.to_transition());
-            let transition = match transition {
-                Some(transition) => transition,
-                None => {
+            let day = match queries.as_ref().and_then(|q| q.get("day")) {
+                Some(day) => match day.parse::<Weekday>() {
+                    Ok(day) => Some(day),
+                    Err(_) => return r400(format!("Invalid day: {}", day)),
+                },
+                None => None,
+            };
 
-                    return default_error_response(StatusCode::BAD_REQUEST, host).await
+            if action == Some("reset-day") {
+                let day = match day {
+                    Some(day) => day,
+                    None => return r400("Missing day".to_string()),
+                };
+                info!("Resetting transition of {}.", day);
+                if controller
+                    .lock()
+                    .unwrap()
+                    .send(Command::ChangeDayTransition(day, None))
+                    .is_err()
+                {
+                    return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host).await;
                 }
+                save.lock()
+                    .unwrap()
+                    .get_mut()
+                    .mut_week_scheduler()
+                    .set_day_transition(day, None);
+                return r200();
+            }
+
+            let transition: Transition = match serde_json::from_slice(&body) {
+                Ok(transition) => transition,
+                Err(err) => return r400(err.to_string()),
             };
 
-            match action {
-                Some("set") => {
+            match (action, day) {
+                (Some("set"), Some(day)) => {
+                    save.lock()
+                        .unwrap()
+                        .get_mut()
+                        .mut_week_scheduler()
+                        .set_day_transition(day, Some(Transition::clone(&transition)));
+                    info!("Setting transition of {}.", day);
+                    if controller
+                        .lock()
+                        .unwrap()
+                        .send(Command::ChangeDayTransition(day, Some(transition)))
+                        .is_err()
+                    {
+                        return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host).await;
+                    }
+                }
+                (Some("set"), None) => {
                     save.lock()
                         .unwrap()
                         .get_mut()
                         .mut_week_scheduler()
-                        .transition = datas::TransitionData::from_transition(&transition);
+                        .transition = Transition::clone(&transition);
                     info!("Setting default transition.");
+                    if controller
+                        .lock()
+                        .unwrap()
+                        .send(Command::ChangeDayTimerTransition(transition))
+                        .is_err()
                     {
-                        controller
-                            .lock()
-                            .unwrap()
-                            .send(Command::ChangeDayTimerTransition(transition));
+                        return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host).await;
                     }
                 }
-                Some("preview") => {
+                (Some("preview"), _) if get_query_value(request, "wait").is_some() => {
+                    info!("Applying transition, waiting for it to end.");
+                    let duration = transition.total_duration();
+                    let receiver = controller.lock().unwrap().send_transition(transition);
+                    // Gives up after a while, in case it's paused.
+                    let outcome = tokio::task::spawn_blocking(move || {
+                        receiver.recv_timeout(duration + LONG_POLL_TIMEOUT)
+                    })
+                    .await;
+                    let body = match outcome {
+                        Ok(Ok(outcome)) => outcome.as_str(),
+                        Ok(Err(std::sync::mpsc::RecvTimeoutError::Timeout)) => "pending",
+                        Ok(Err(std::sync::mpsc::RecvTimeoutError::Disconnected)) | Err(_) => {
+                            return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host)
+                                .await
+                        }
+                    };
+                    return (
+                        Response::new(Bytes::from(body)),
+                        ClientCachePreference::None,
+                        ServerCachePreference::None,
+                        CompressPreference::None,
+                    );
+                }
+                (Some("preview"), _) => {
                     info!("Applying transition.");
+                    if controller
+                        .lock()
+                        .unwrap()
+                        .send(Command::SetTransition(transition))
+                        .is_err()
                     {
-                        controller
-                            .lock()
-                            .unwrap()
-                            .send(Command::SetTransition(transition));
+                        return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host).await;
                     }
                 }
                 _ => {
@@ -315,10 +629,79 @@ fn create_server<T: VariableOut + Send>(
         }),
     );
 
+    let controller = ctl();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/transition-speed".to_string(),
+        prepare!(request, host, _path, _addr, controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            match get_query_value(request, "factor").and_then(|value| value.parse().ok()) {
+                Some(factor) => {
+                    if controller
+                        .lock()
+                        .unwrap()
+                        .send(Command::SetTransitionSpeed(factor))
+                        .is_err()
+                    {
+                        return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host).await;
+                    }
+                }
+                None => return default_error_response(StatusCode::BAD_REQUEST, host).await,
+            }
+            r200()
+        }),
+    );
+
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/preview-transition".to_string(),
+        prepare!(request, host, _path, _addr, auth, {
+            if !auth.allows_read(request) {
+                return r401();
+            }
+            let body = match read_body(request).await {
+                Ok(b) => b,
+                Err(_) => return default_error_response(StatusCode::BAD_REQUEST, host).await,
+            };
+            let transition: Transition = match serde_json::from_slice(&body) {
+                Ok(transition) => transition,
+                Err(err) => return r400(err.to_string()),
+            };
+            let samples = match get_query_value(request, "samples") {
+                Some(samples) => match samples.parse::<usize>() {
+                    Ok(samples) if (2..=PREVIEW_SAMPLES_MAX).contains(&samples) => samples,
+                    _ => {
+                        return r400(format!(
+                            "Invalid samples: {}, expected 2 to {}",
+                            samples, PREVIEW_SAMPLES_MAX
+                        ))
+                    }
+                },
+                None => PREVIEW_SAMPLES,
+            };
+
+            let strengths = preview_transition(&transition, samples);
+            let mut body = utility::WriteableBytes::new(BytesMut::with_capacity(samples * 8));
+            serde_json::to_writer(&mut body, &strengths).expect("failed to serialize strengths");
+            (
+                Response::new(body.into_inner().freeze()),
+                ClientCachePreference::None,
+                ServerCachePreference::None,
+                CompressPreference::Full,
+            )
+        }),
+    );
+
     let local_state = state();
+    let auth = authentication();
     extensions.add_prepare_single(
         "/get-state".to_string(),
-        prepare!(_request, _host, _path, _addr, local_state, {
+        prepare!(request, _host, _path, _addr, local_state auth, {
+            if !auth.allows_read(request) {
+                return r401();
+            }
             let state = datas::StateData::from_shared_state(&*local_state.lock().unwrap());
             let mut body = utility::WriteableBytes::new(BytesMut::with_capacity(1024));
             serde_json::to_writer(&mut body, &state).expect("failed to parse shared state");
@@ -332,47 +715,186 @@ fn create_server<T: VariableOut + Send>(
         }),
     );
 
-    let controller = ctl();
-    let save = saved();
+    let local_state = state();
+    let auth = authentication();
     extensions.add_prepare_single(
-        "/add-scheduler".to_string(),
-        prepare!(request, host, _path, _addr, save controller, {
-        let body = match read_body(request).await {
-            Ok(b) => b,
-            Err(_) => return default_error_response(StatusCode::BAD_REQUEST, host).await,
-        };
-
-                    let data = serde_json::from_slice(&body).ok();
-                    let command = data.and_then(|data: datas::AddSchedulerData| {
-                        let data_clone = data.clone();
-                        data.into_command(false).map(|cmd| (data_clone, cmd))
-                    });
+        "/get-schedule".to_string(),
+        prepare!(request, host, _path, _addr, local_state auth, {
+            if !auth.allows_read(request) {
+                return r401();
+            }
+            // The controller thread only holds the lock briefly; a poisoned lock means it panicked.
+            let schedule = match local_state.lock() {
+                Ok(state) => datas::ScheduleData::from_week_scheduler(state.ref_week_schedule()),
+                Err(_) => {
+                    return default_error_response(StatusCode::INTERNAL_SERVER_ERROR, host).await
+                }
+            };
+            let mut body = utility::WriteableBytes::new(BytesMut::with_capacity(512));
+            serde_json::to_writer(&mut body, &schedule).expect("failed to serialize schedule");
+            (
+                Response::new(body.into_inner().freeze()),
+                ClientCachePreference::None,
+                ServerCachePreference::None,
+                CompressPreference::Full,
+            )
+        }),
+    );
 
-                    match command {
-                        Some((data, cmd)) => {
-                            {
-                                controller.lock().unwrap().send(cmd);
-                            }
-                            save.lock().unwrap().get_mut().mut_schedulers().push(data);
-                        }
-                        None => {
-                            return default_error_response(StatusCode::BAD_REQUEST, host).await
+    let controller = ctl();
+    let local_state = state();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/ws/strength".to_string(),
+        prepare!(request, host, _path, _addr, controller local_state auth, {
+            if !auth.allows_read(request) {
+                return r401();
+            }
+            let controller = Arc::clone(&controller);
+            let local_state = Arc::clone(&local_state);
+            kvarn::websocket::response(
+                request,
+                host,
+                response_pipe_fut!(response_pipe, _host, move |controller, local_state| {
+                    use kvarn::websocket::{Message, SinkExt, StreamExt};
+
+                    let mut ws = kvarn::websocket::wrap(response_pipe).await;
+                    let mut updates = handlers::subscribe_strengths(&controller);
+
+                    // Starts with the current strength, so the client doesn't have to ask.
+                    let current = local_state.lock().unwrap().get_strength().into_inner();
+                    if ws.send(Message::Text(current.to_string())).await.is_err() {
+                        return;
+                    }
+                    loop {
+                        tokio::select! {
+                            update = updates.recv() => match update {
+                                Some(strength) => {
+                                    let text = strength.into_inner().to_string();
+                                    if ws.send(Message::Text(text)).await.is_err() {
+                                        break;
+                                    }
+                                }
+                                None => break,
+                            },
+                            // The client only sends pings, which are answered when reading,
+                            // and the close.
+                            message = ws.next() => match message {
+                                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                                Some(Ok(_)) => {}
+                            },
                         }
                     }
-
-                    r200()
                 }),
+            )
+            .await
+        }),
     );
 
     let local_state = state();
+    let save = saved();
+    let auth = authentication();
     extensions.add_prepare_single(
-        "/get-schedulers".to_string(),
-        prepare!(request, host, _path, _addr, local_state, {
-            let mut now = scheduler::LazyNow::new();
+        "/export-config".to_string(),
+        prepare!(request, _host, _path, _addr, local_state save auth, {
+            if !auth.allows_read(request) {
+                return r401();
+            }
+            let config = datas::ConfigData::new(
+                save.lock().unwrap().get_ref(),
+                local_state.lock().unwrap().ref_week_schedule(),
+            );
+            let mut body = utility::WriteableBytes::new(BytesMut::with_capacity(1024));
+            serde_json::to_writer(&mut body, &config).expect("failed to serialize config");
+            (
+                Response::new(body.into_inner().freeze()),
+                ClientCachePreference::None,
+                ServerCachePreference::None,
+                CompressPreference::Full,
+            )
+        }),
+    );
 
-            let mut schedulers: Vec<(datas::SchedulerData, Option<Duration>)> = local_state
+    let controller = ctl();
+    let save = saved();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/import-config".to_string(),
+        prepare!(request, host, _path, _addr, save controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            let body = match read_body(request).await {
+                Ok(b) => b,
+                Err(_) => return default_error_response(StatusCode::BAD_REQUEST, host).await,
+            };
+            let config: datas::ConfigData = match serde_json::from_slice(&body) {
+                Ok(config) => config,
+                Err(err) => return r400(err.to_string()),
+            };
+            let bedtime = controller
+                .lock()
+                .unwrap()
+                .get_state()
                 .lock()
                 .unwrap()
+                .ref_bedtime()
+                .clone();
+            // Validate everything before applying anything.
+            let (new_bedtime, command) = match config.to_commands(&bedtime) {
+                Ok(commands) => commands,
+                Err(err) => return r400(err),
+            };
+            let sent = {
+                let controller = controller.lock().unwrap();
+                new_bedtime
+                    .map_or(Ok(()), |time| controller.send(Command::SetBedtime(time)))
+                    .and_then(|()| controller.send(command))
+                    .is_ok()
+            };
+            if !sent {
+                return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host).await;
+            }
+            info!("Imported config.");
+            save.lock().unwrap().get_mut().replace_config(config);
+            r200()
+        }),
+    );
+
+    let controller = ctl();
+    let save = saved();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/add-scheduler".to_string(),
+        prepare!(request, host, _path, _addr, save controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            let body = match read_body(request).await {
+                Ok(b) => b,
+                Err(_) => return default_error_response(StatusCode::BAD_REQUEST, host).await,
+            };
+            match handlers::add_scheduler(&controller, &save, &body) {
+                Ok(()) => r200(),
+                Err(status) => default_error_response(status, host).await,
+            }
+        }),
+    );
+
+    let local_state = state();
+    let auth = authentication();
+    let clock = time();
+    extensions.add_prepare_single(
+        "/get-schedulers".to_string(),
+        prepare!(request, host, _path, _addr, local_state auth clock, {
+            if !auth.allows_read(request) {
+                return r401();
+            }
+            let mut now = scheduler::LazyNow::from_clock(Arc::clone(&clock));
+            let current = now.now();
+
+            let lock = local_state.lock().unwrap();
+            let mut schedulers: Vec<(datas::SchedulerData, Option<Duration>)> = lock
                 .ref_schedulers()
                 .iter()
                 .map(|(name, scheduler)| {
@@ -380,11 +902,12 @@ fn create_server<T: VariableOut + Send>(
                         datas::SchedulerData::from_scheduler(
                             scheduler.as_ref(),
                             name.to_string(),
+                            lock.is_scheduler_enabled(name),
                             &mut now,
                         ),
                         match scheduler.get_next(&mut now) {
                             Next::At(dur, _) => Some(
-                                (dur - get_naive_now())
+                                (dur - current)
                                     .to_std()
                                     .unwrap_or(Duration::new(0, 0)),
                             ),
@@ -393,6 +916,7 @@ fn create_server<T: VariableOut + Send>(
                     )
                 })
                 .collect();
+            drop(lock);
 
             schedulers.sort_by(|(_, d1), (_, d2)| d1.cmp(d2));
 
@@ -411,25 +935,360 @@ fn create_server<T: VariableOut + Send>(
         }),
     );
 
+    let local_state = state();
+    let auth = authentication();
+    let clock = time();
+    extensions.add_prepare_single(
+        "/next".to_string(),
+        prepare!(request, _host, _path, _addr, local_state auth clock, {
+            if !auth.allows_read(request) {
+                return r401();
+            }
+            let mut now = scheduler::LazyNow::from_clock(Arc::clone(&clock));
+            let lock = local_state.lock().unwrap();
+            let next = lock
+                .get_next_scheduler(&mut now)
+                .map(|next| datas::NextData::new(&*lock, &next, now.now()));
+            drop(lock);
+
+            match next {
+                Some(next) => {
+                    let mut buffer = utility::WriteableBytes::new(BytesMut::with_capacity(256));
+                    serde_json::to_writer(&mut buffer, &next).expect("failed to write to Vec?");
+                    (
+                        Response::new(buffer.into_inner().freeze()),
+                        ClientCachePreference::None,
+                        ServerCachePreference::None,
+                        CompressPreference::None,
+                    )
+                }
+                None => {
+                    let mut response = Response::new(Bytes::new());
+                    *response.status_mut() = StatusCode::NO_CONTENT;
+                    (
+                        response,
+                        ClientCachePreference::None,
+                        ServerCachePreference::None,
+                        CompressPreference::None,
+                    )
+                }
+            }
+        }),
+    );
+
     let controller = ctl();
+    let auth = authentication();
     extensions.add_prepare_single(
-        "/remove-scheduler".to_string(),
-        prepare!(request, host, _path, _addr, controller, {
-            match get_query_value(request, "name")
+        "/scene/save".to_string(),
+        prepare!(request, host, _path, _addr, controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            let name = match get_query_value(request, "name")
                 .map(|name| percent_encoding::percent_decode_str(name).decode_utf8())
                 .and_then(Result::ok)
             {
-                Some(s) => {
-                    {
-                        controller
-                            .lock()
-                            .unwrap()
-                            .send(Command::RemoveScheduler(s.to_string()));
-                    }
-                    // Can be removed since we check if internal schedulers disappeared.
-                    // save.lock()
-                    //     .unwrap()
-                    //     .get_mut()
+                Some(name) if valid_scene_name(&name) => name.to_string(),
+                _ => {
+                    return r400(format!(
+                        "Expected a `name` of 1 to {} letters, digits, spaces, `-`, or `_`.",
+                        SCENE_NAME_MAX
+                    ))
+                }
+            };
+            let body = match read_body(request).await {
+                Ok(b) => b,
+                Err(_) => return default_error_response(StatusCode::BAD_REQUEST, host).await,
+            };
+            let scene: Scene = match serde_json::from_slice(&body) {
+                Ok(scene) => scene,
+                Err(err) => return r400(err.to_string()),
+            };
+            // The saved state is updated when it notices the config changed.
+            if controller
+                .lock()
+                .unwrap()
+                .send(Command::SaveScene(name, scene))
+                .is_err()
+            {
+                return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host).await;
+            }
+            r200()
+        }),
+    );
+
+    let controller = ctl();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/scene/recall".to_string(),
+        prepare!(request, host, _path, _addr, controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            let name = match get_query_value(request, "name")
+                .map(|name| percent_encoding::percent_decode_str(name).decode_utf8())
+                .and_then(Result::ok)
+            {
+                Some(name) => name.to_string(),
+                None => return default_error_response(StatusCode::BAD_REQUEST, host).await,
+            };
+            let receiver = { controller.lock().unwrap().send_recall_scene(name) };
+            match answer(receiver).await {
+                Ok(true) => r200(),
+                Ok(false) => default_error_response(StatusCode::NOT_FOUND, host).await,
+                Err(err) => default_error_response(unresponsive(err), host).await,
+            }
+        }),
+    );
+
+    let local_state = state();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/scene/list".to_string(),
+        prepare!(request, _host, _path, _addr, local_state auth, {
+            if !auth.allows_read(request) {
+                return r401();
+            }
+            let scenes = datas::SceneData::list(local_state.lock().unwrap().ref_scenes());
+            let mut body = utility::WriteableBytes::new(BytesMut::with_capacity(256));
+            serde_json::to_writer(&mut body, &scenes).expect("failed to serialize scenes");
+            (
+                Response::new(body.into_inner().freeze()),
+                ClientCachePreference::None,
+                ServerCachePreference::None,
+                CompressPreference::Full,
+            )
+        }),
+    );
+
+    let controller = ctl();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/scene/delete".to_string(),
+        prepare!(request, host, _path, _addr, controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            let name = match get_query_value(request, "name")
+                .map(|name| percent_encoding::percent_decode_str(name).decode_utf8())
+                .and_then(Result::ok)
+            {
+                Some(name) => name.to_string(),
+                None => return default_error_response(StatusCode::BAD_REQUEST, host).await,
+            };
+            let receiver = { controller.lock().unwrap().send_remove_scene(name) };
+            // The saved state is updated when it notices the config changed.
+            match answer(receiver).await {
+                Ok(true) => r200(),
+                Ok(false) => default_error_response(StatusCode::NOT_FOUND, host).await,
+                Err(err) => default_error_response(unresponsive(err), host).await,
+            }
+        }),
+    );
+
+    let controller = ctl();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/pause".to_string(),
+        prepare!(request, host, _path, _addr, controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            if let Err(status) = handlers::set_paused(&controller, true).await {
+                return default_error_response(status, host).await;
+            }
+            info!("Paused");
+            (
+                Response::new(Bytes::from_static(b"{\"paused\":true}")),
+                ClientCachePreference::None,
+                ServerCachePreference::None,
+                CompressPreference::None,
+            )
+        }),
+    );
+
+    let controller = ctl();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/resume".to_string(),
+        prepare!(request, host, _path, _addr, controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            if let Err(status) = handlers::set_paused(&controller, false).await {
+                return default_error_response(status, host).await;
+            }
+            info!("Resumed");
+            (
+                Response::new(Bytes::from_static(b"{\"paused\":false}")),
+                ClientCachePreference::None,
+                ServerCachePreference::None,
+                CompressPreference::None,
+            )
+        }),
+    );
+
+    let controller = ctl();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/dry-run".to_string(),
+        prepare!(request, host, _path, _addr, controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            let enabled = match get_query_value(request, "enabled")
+                .and_then(|value| value.parse().ok())
+            {
+                Some(enabled) => enabled,
+                None => return r400("Expected `enabled` to be `true` or `false`.".to_string()),
+            };
+            if controller
+                .lock()
+                .unwrap()
+                .send(Command::SetDryRun(enabled))
+                .is_err()
+            {
+                return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host).await;
+            }
+            info!(enabled, "Changed dry run");
+            r200()
+        }),
+    );
+
+    let controller = ctl();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/status".to_string(),
+        prepare!(request, host, _path, _addr, controller auth, {
+            if !auth.allows_read(request) {
+                return r401();
+            }
+            let receiver = { controller.lock().unwrap().send_get_status() };
+            // The controller answers as soon as it's gotten the command.
+            let status = match answer(receiver).await {
+                Ok(status) => status,
+                Err(err) => return default_error_response(unresponsive(err), host).await,
+            };
+
+            let mut buffer = utility::WriteableBytes::new(BytesMut::with_capacity(1024));
+            serde_json::to_writer(&mut buffer, &status).expect("failed to write to Vec?");
+            (
+                Response::new(buffer.into_inner().freeze()),
+                ClientCachePreference::None,
+                ServerCachePreference::None,
+                CompressPreference::Full,
+            )
+        }),
+    );
+
+    let controller = ctl();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/get-day-transition".to_string(),
+        prepare!(request, host, _path, _addr, controller auth, {
+            if !auth.allows_read(request) {
+                return r401();
+            }
+            let receiver = { controller.lock().unwrap().send_get_day_transition() };
+            let transition = match answer(receiver).await {
+                Ok(transition) => transition,
+                Err(err) => return default_error_response(unresponsive(err), host).await,
+            };
+
+            let mut buffer = utility::WriteableBytes::new(BytesMut::with_capacity(128));
+            serde_json::to_writer(&mut buffer, &transition).expect("failed to write to Vec?");
+            (
+                Response::new(buffer.into_inner().freeze()),
+                ClientCachePreference::None,
+                ServerCachePreference::None,
+                CompressPreference::Full,
+            )
+        }),
+    );
+
+    let controller = ctl();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/history".to_string(),
+        prepare!(request, host, _path, _addr, controller auth, {
+            if !auth.allows_read(request) {
+                return r401();
+            }
+            let receiver = { controller.lock().unwrap().send_get_history() };
+            // The controller answers as soon as it's gotten the command.
+            let history = match answer(receiver).await {
+                Ok(history) => history,
+                Err(err) => return default_error_response(unresponsive(err), host).await,
+            };
+            let history: Vec<_> = history
+                .into_iter()
+                .map(|(ago, strength)| datas::HistoryData::new(ago, strength))
+                .collect();
+
+            let mut buffer = utility::WriteableBytes::new(BytesMut::with_capacity(1024));
+            serde_json::to_writer(&mut buffer, &history).expect("failed to write to Vec?");
+            (
+                Response::new(buffer.into_inner().freeze()),
+                ClientCachePreference::None,
+                ServerCachePreference::None,
+                CompressPreference::Full,
+            )
+        }),
+    );
+
+    let controller = ctl();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/list-schedulers".to_string(),
+        prepare!(request, host, _path, _addr, controller auth, {
+            if !auth.allows_read(request) {
+                return r401();
+            }
+            let receiver = { controller.lock().unwrap().send_list_schedulers() };
+            // The controller answers as soon as it's gotten the command.
+            let schedulers = match answer(receiver).await {
+                Ok(schedulers) => schedulers,
+                Err(err) => return default_error_response(unresponsive(err), host).await,
+            };
+
+            let mut buffer = utility::WriteableBytes::new(BytesMut::with_capacity(1024));
+            serde_json::to_writer(&mut buffer, &schedulers).expect("failed to write to Vec?");
+            (
+                Response::new(buffer.into_inner().freeze()),
+                ClientCachePreference::None,
+                ServerCachePreference::None,
+                CompressPreference::Full,
+            )
+        }),
+    );
+
+    let controller = ctl();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/remove-scheduler".to_string(),
+        prepare!(request, host, _path, _addr, controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            // The name is the scheduler's id; accept both.
+            match get_query_value(request, "name")
+                .or_else(|| get_query_value(request, "id"))
+                .map(|name| percent_encoding::percent_decode_str(name).decode_utf8())
+                .and_then(Result::ok)
+            {
+                Some(s) => {
+                    if controller
+                        .lock()
+                        .unwrap()
+                        .send(Command::RemoveScheduler(s.to_string()))
+                        .is_err()
+                    {
+                        return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host).await;
+                    }
+                    // Can be removed since we check if internal schedulers disappeared.
+                    // save.lock()
+                    //     .unwrap()
+                    //     .get_mut()
                     //     .mut_schedulers()
                     //     .retain(|scheduler| scheduler.name != s);
                 }
@@ -440,9 +1299,111 @@ fn create_server<T: VariableOut + Send>(
         }),
     );
 
-    let localhost = Host::no_certification("localhost", PathBuf::from("web"), extensions);
-    let hosts = HostData::builder(localhost).build();
-    let ports = vec![HostDescriptor::new(8080, hosts)];
+    let controller = ctl();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/delete-scheduler".to_string(),
+        prepare!(request, host, _path, _addr, controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            let id = match get_query_value(request, "id")
+                .map(|id| percent_encoding::percent_decode_str(id).decode_utf8())
+                .and_then(Result::ok)
+            {
+                Some(id) => id.to_string(),
+                None => return default_error_response(StatusCode::BAD_REQUEST, host).await,
+            };
+
+            match handlers::delete_scheduler(&controller, id).await {
+                Ok(()) => r200(),
+                Err(status) => default_error_response(status, host).await,
+            }
+        }),
+    );
+
+    let controller = ctl();
+    let save = saved();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/scheduler-enabled".to_string(),
+        prepare!(request, host, _path, _addr, save controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            let name = get_query_value(request, "id")
+                .or_else(|| get_query_value(request, "name"))
+                .map(|name| percent_encoding::percent_decode_str(name).decode_utf8())
+                .and_then(Result::ok);
+            let enabled = get_query_value(request, "enabled").and_then(|value| value.parse().ok());
+            match (name, enabled) {
+                (Some(name), Some(enabled)) => {
+                    let receiver = controller
+                        .lock()
+                        .unwrap()
+                        .send_set_scheduler_enabled(name.to_string(), enabled);
+                    match answer(receiver).await {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            return default_error_response(StatusCode::NOT_FOUND, host).await
+                        }
+                        Err(err) => return default_error_response(unresponsive(err), host).await,
+                    }
+                    let mut save = save.lock().unwrap();
+                    if let Some(data) = save
+                        .get_mut()
+                        .mut_schedulers()
+                        .iter_mut()
+                        .find(|data| data.name == name)
+                    {
+                        data.enabled = enabled;
+                    }
+                }
+                _ => return default_error_response(StatusCode::BAD_REQUEST, host).await,
+            }
+
+            r200()
+        }),
+    );
+
+    let controller = ctl();
+    let save = saved();
+    let auth = authentication();
+    extensions.add_prepare_single(
+        "/set-bedtime".to_string(),
+        prepare!(request, host, _path, _addr, save controller auth, {
+            if !auth.allows(request) {
+                return r401();
+            }
+            match get_query_value(request, "time")
+                .map(|time| percent_encoding::percent_decode_str(time).decode_utf8())
+                .and_then(Result::ok)
+                .and_then(|time| parse_time(&time))
+            {
+                Some(time) => {
+                    info!("Changed bedtime to {}", time);
+                    if controller.lock().unwrap().send(Command::SetBedtime(time)).is_err() {
+                        return default_error_response(StatusCode::SERVICE_UNAVAILABLE, host).await;
+                    }
+                    save.lock().unwrap().get_mut().set_bedtime(time);
+                }
+                None => return default_error_response(StatusCode::BAD_REQUEST, host).await,
+            }
+            r200()
+        }),
+    );
+
+    info!(
+        "Serving {} as {} on port {}",
+        server.web_dir.display(),
+        server.host_name,
+        server.port
+    );
+    // Lives for the rest of the program anyway.
+    let host_name: &'static str = Box::leak(server.host_name.into_boxed_str());
+    let host = Host::no_certification(host_name, server.web_dir, extensions);
+    let hosts = HostData::builder(host).build();
+    let ports = vec![HostDescriptor::new(server.port, hosts)];
 
     let config = Config::new(ports);
     config
@@ -454,76 +1415,409 @@ pub fn parse_time(string: &str) -> Option<chrono::NaiveTime> {
         .ok()
 }
 
-/// Quite nasty code
-pub mod save_state {
+/// `samples` strengths evenly spaced over the whole of `transition`, including both ends
+/// and the way back of the to-and-back interpolations.
+pub fn preview_transition(transition: &Transition, samples: usize) -> Vec<Strength> {
+    let total = transition.total_duration();
+    (0..samples)
+        .map(|sample| {
+            let progress = sample as f64 / (samples.max(2) - 1) as f64;
+            transition.strength_at(total.mul_f64(progress))
+        })
+        .collect()
+}
+
+/// Names of 1 to [`SCENE_NAME_MAX`] letters, digits, spaces, `-`, and `_`,
+/// so they're easy to put in URLs and to show.
+pub fn valid_scene_name(name: &str) -> bool {
+    let len = name.chars().count();
+    (1..=SCENE_NAME_MAX).contains(&len)
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_')
+}
+
+/// The initial configuration, from the file in the arguments or [`config::CONFIG_ENV`].
+pub mod config {
     use super::*;
-    use chrono::Weekday;
+    use std::env;
+    use std::path::PathBuf;
+
+    /// Environment variable with the path to the config file, used if no argument is given.
+    pub const CONFIG_ENV: &str = "HTTPWM_CONFIG";
+
+    /// A RON file. Everything is optional; what's left out uses the defaults in `main`.
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default)]
+    pub struct Config {
+        /// The day times and transitions.
+        /// The one in the state file takes precedence, since it has the changes made at runtime.
+        pub week_scheduler: Option<WeekScheduler>,
+        /// Played when starting, before the saved state is applied.
+        pub startup_transition: Option<Transition>,
+        /// The frequency of the hardware PWM in Hz. Defaults to 1kHz.
+        pub pwm_frequency: Option<f64>,
+        /// Any strength above `0` but below this is raised to it, for drivers which flicker
+        /// at low duty cycles. `0` still turns the output off. Defaults to `0`.
+        pub min_strength: Option<f64>,
+        /// No strength above this is ever set, whatever is requested, to limit heat and power draw.
+        /// Larger values are clamped to it; smaller ones are left as they are. Defaults to `1`.
+        pub max_strength: Option<f64>,
+        /// Strengths set within this many milliseconds of the last are held back,
+        /// applying only the latest when the time has passed, so a dragged slider doesn't
+        /// flood the controller. Off by default.
+        pub set_rate_limit_ms: Option<u64>,
+        /// Requests have to send it as `Authorization: Bearer <token>`.
+        /// Overridden by the [`auth::TOKEN_ENV`] environment variable.
+        pub auth_token: Option<String>,
+        /// Also require the token for the endpoints which only read.
+        pub auth_read_only: bool,
+        /// Fade back to the strength from before a restart, such as after a power cut.
+        /// Otherwise, the light stays off until something turns it on.
+        pub restore_strength: bool,
+        /// Overridden by `--port` and [`PORT_ENV`]. Defaults to `8080`.
+        pub port: Option<u16>,
+        /// The name of the virtual host to serve, which requests are matched to by their `Host` header.
+        /// The server listens on all addresses either way.
+        /// Overridden by [`HOST_NAME_ENV`]. Defaults to `localhost`.
+        pub host_name: Option<String>,
+        /// The directory with the web interface.
+        /// Overridden by [`WEB_DIR_ENV`]. Defaults to `web`.
+        pub web_dir: Option<PathBuf>,
+        /// The IANA name of the time zone the schedules are in, e.g. `Europe/Stockholm`.
+        /// Overridden by [`TIME_ZONE_ENV`]. Defaults to the system's.
+        pub time_zone: Option<String>,
+    }
 
-    #[derive(Debug, Serialize, Deserialize)]
-    pub struct WeekSchedulerData {
-        pub mon: Option<String>,
-        pub tue: Option<String>,
-        pub wed: Option<String>,
-        pub thu: Option<String>,
-        pub fri: Option<String>,
-        pub sat: Option<String>,
-        pub sun: Option<String>,
-        pub transition: datas::TransitionData,
-    }
-    impl WeekSchedulerData {
-        pub fn get_mut(&mut self, day: Weekday) -> &mut Option<String> {
-            match day {
-                Weekday::Mon => &mut self.mon,
-                Weekday::Tue => &mut self.tue,
-                Weekday::Wed => &mut self.wed,
-                Weekday::Thu => &mut self.thu,
-                Weekday::Fri => &mut self.fri,
-                Weekday::Sat => &mut self.sat,
-                Weekday::Sun => &mut self.sun,
-            }
-        }
-        pub fn from_scheduler(scheduler: &WeekScheduler) -> Self {
-            macro_rules! fmt_time {
-                ($e:expr) => {
-                    $e.map(|time| time.format("%H:%M:%S").to_string())
-                };
+    /// Environment variable with the port to listen on.
+    pub const PORT_ENV: &str = "HTTPWM_PORT";
+    /// Environment variable with the name of the virtual host to serve. See [`Config::host_name`].
+    pub const HOST_NAME_ENV: &str = "HTTPWM_HOST_NAME";
+    /// Environment variable with the directory of the web interface.
+    pub const WEB_DIR_ENV: &str = "HTTPWM_WEB_DIR";
+    /// Environment variable with the time zone of the schedules.
+    pub const TIME_ZONE_ENV: &str = "HTTPWM_TIME_ZONE";
+
+    /// The clock in the time zone from the environment or [`Config`].
+    /// Errors if the time zone isn't known.
+    pub fn clock(config: &Config) -> Result<Arc<dyn scheduler::Clock>, String> {
+        match env::var(TIME_ZONE_ENV)
+            .ok()
+            .or_else(|| config.time_zone.clone())
+        {
+            Some(name) => {
+                let time_zone: chrono_tz::Tz = name
+                    .parse()
+                    .map_err(|_| format!("unknown time zone {:?}", name))?;
+                Ok(Arc::new(scheduler::ZonedClock::new(time_zone)))
+            }
+            None => Ok(Arc::new(scheduler::SystemClock)),
+        }
+    }
+
+    /// Wraps `output` in the [`Config::min_strength`] floor and [`Config::max_strength`] cap.
+    /// Invalid values are warned about and ignored.
+    pub fn limit_output<T: VariableOut>(
+        inner: T,
+        config: &Config,
+    ) -> output::Floored<output::Clamped<T>> {
+        let max_strength = match config.max_strength.map(Strength::try_new) {
+            Some(Ok(strength)) => strength,
+            Some(Err(err)) => {
+                warn!("Invalid max strength: {}. Using 1.", err);
+                Strength::full()
+            }
+            None => Strength::full(),
+        };
+        // Innermost, so the floor can't raise anything above it.
+        let clamped = output::Clamped::new(inner, Strength::off(), max_strength);
+        let min_strength = match config.min_strength.map(Strength::try_new) {
+            Some(Ok(strength)) => strength,
+            Some(Err(err)) => {
+                warn!("Invalid min strength: {}. Using 0.", err);
+                Strength::off()
+            }
+            None => Strength::off(),
+        };
+        output::Floored::new(clamped, min_strength)
+    }
+
+    /// Where and what the server serves, from the environment or [`Config`].
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct ServerConfig {
+        pub port: u16,
+        pub host_name: String,
+        pub web_dir: PathBuf,
+    }
+    impl ServerConfig {
+        /// The port in `args` takes precedence over everything else.
+        ///
+        /// Errors if the port isn't a number in `1..=65535`.
+        pub fn new(config: &Config, args: &args::Args) -> Result<Self, String> {
+            let port = match (args.port, env::var(PORT_ENV)) {
+                (Some(port), _) => port,
+                (None, Ok(port)) => port.parse().map_err(|_| {
+                    format!(
+                        "{} is {:?}, which isn't a port from 1 to 65535",
+                        PORT_ENV, port
+                    )
+                })?,
+                (None, Err(_)) => config.port.unwrap_or(8080),
             };
+            if port == 0 {
+                return Err("the port can't be 0".to_string());
+            }
+            Ok(Self {
+                port,
+                host_name: env::var(HOST_NAME_ENV)
+                    .ok()
+                    .or_else(|| config.host_name.clone())
+                    .unwrap_or_else(|| "localhost".to_string()),
+                web_dir: env::var_os(WEB_DIR_ENV)
+                    .map(PathBuf::from)
+                    .or_else(|| config.web_dir.clone())
+                    .unwrap_or_else(|| PathBuf::from("web")),
+            })
+        }
+    }
+    impl Config {
+        /// The path in `args`, otherwise the one in [`CONFIG_ENV`].
+        pub fn path(args: &args::Args) -> Option<PathBuf> {
+            args.config
+                .clone()
+                .or_else(|| env::var_os(CONFIG_ENV).map(PathBuf::from))
+        }
+        /// Reads the config at [`Self::path()`].
+        /// If there is none or it can't be read, the defaults are used.
+        pub fn load(args: &args::Args) -> Self {
+            let path = match Self::path(args) {
+                Some(path) => path,
+                None => return Self::default(),
+            };
+            match Self::read_from_file(&path) {
+                Ok(config) => {
+                    info!("Using config {}", path.display());
+                    config
+                }
+                Err(err) => {
+                    warn!(
+                        "Failed to read config {}: {}. Using defaults.",
+                        path.display(),
+                        err
+                    );
+                    Self::default()
+                }
+            }
+        }
+        pub fn read_from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+            let file = std::fs::File::open(path)?;
+            ron::de::from_reader(file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+    }
+}
 
-            WeekSchedulerData {
-                mon: fmt_time!(scheduler.mon),
-                tue: fmt_time!(scheduler.tue),
-                wed: fmt_time!(scheduler.wed),
-                thu: fmt_time!(scheduler.thu),
-                fri: fmt_time!(scheduler.fri),
-                sat: fmt_time!(scheduler.sat),
-                sun: fmt_time!(scheduler.sun),
-                transition: datas::TransitionData::from_transition(&scheduler.transition),
+/// The command line arguments. See [`args::USAGE`].
+pub mod args {
+    use std::ffi::OsString;
+    use std::fmt;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    pub const USAGE: &str = "\
+Usage: httpwmd [OPTIONS] [CONFIG]
+
+Arguments:
+    [CONFIG]                     The config file. Same as `--config`.
+
+Options:
+    --output <pwm|print|softpwm> Where the strength is set. Defaults to `pwm`,
+                                 or `print` with the `test` feature.
+    --pin <PIN>                  The BCM number of the GPIO pin used by `softpwm`. Defaults to 18.
+    --port <PORT>                The port to listen on. Overrides `HTTPWM_PORT` and the config.
+    --config <PATH>              The config file. Overrides `HTTPWM_CONFIG`.
+    --dry-run                    Start in dry run, logging strengths instead of setting them.
+    --help                       Print this message.";
+
+    /// The pin of the hardware PWM channel 0, so `softpwm` works with the same circuit.
+    pub const DEFAULT_PIN: u8 = 18;
+
+    /// The [`httpwm::VariableOut`] to use.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum OutputKind {
+        /// The hardware PWM channel 0.
+        Pwm,
+        /// [`httpwm::PrintOut`], for running without the hardware.
+        Print,
+        /// [`httpwm::output::SoftPwm`] on [`Args::pin`].
+        SoftPwm,
+    }
+    impl Default for OutputKind {
+        fn default() -> Self {
+            match cfg!(feature = "test") {
+                true => Self::Print,
+                false => Self::Pwm,
             }
         }
-        pub fn to_scheduler(&self) -> Option<WeekScheduler> {
-            macro_rules! fmt_time {
-                ($e:expr) => {
-                    match $e.as_ref() {
-                        Some(time) => Some(
-                            chrono::NaiveTime::parse_from_str(time.as_str(), "%H:%M:%S").ok()?,
-                        ),
-                        None => None,
+    }
+    impl FromStr for OutputKind {
+        type Err = String;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "pwm" => Ok(Self::Pwm),
+                "print" => Ok(Self::Print),
+                "softpwm" => Ok(Self::SoftPwm),
+                _ => Err(format!(
+                    "unknown output {:?}; expected `pwm`, `print`, or `softpwm`",
+                    s
+                )),
+            }
+        }
+    }
+    impl fmt::Display for OutputKind {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str(match self {
+                Self::Pwm => "pwm",
+                Self::Print => "print",
+                Self::SoftPwm => "softpwm",
+            })
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Args {
+        pub output: OutputKind,
+        pub pin: u8,
+        /// Takes precedence over the environment and config.
+        pub port: Option<u16>,
+        /// The path of the config file.
+        pub config: Option<PathBuf>,
+        /// See [`httpwm::Command::SetDryRun`].
+        pub dry_run: bool,
+        /// Print [`USAGE`] and exit.
+        pub help: bool,
+    }
+    impl Default for Args {
+        fn default() -> Self {
+            Self {
+                output: OutputKind::default(),
+                pin: DEFAULT_PIN,
+                port: None,
+                config: None,
+                dry_run: false,
+                help: false,
+            }
+        }
+    }
+    impl Args {
+        /// Parses the arguments, without the name of the program.
+        ///
+        /// Errors if an option is unknown, lacks its value, or has an invalid one.
+        pub fn parse<I: IntoIterator<Item = OsString>>(args: I) -> Result<Self, String> {
+            let mut parsed = Self::default();
+            let mut args = args.into_iter();
+            while let Some(arg) = args.next() {
+                let mut value =
+                    |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+                match arg.to_str() {
+                    Some("--output") => {
+                        parsed.output = utf8(value("--output")?, "--output")?.parse()?
                     }
-                };
-            };
+                    Some("--pin") => {
+                        parsed.pin = utf8(value("--pin")?, "--pin")?
+                            .parse()
+                            .map_err(|_| "--pin needs a GPIO pin number".to_string())?
+                    }
+                    Some("--port") => {
+                        parsed.port = match utf8(value("--port")?, "--port")?.parse() {
+                            Ok(0) | Err(_) => {
+                                return Err("--port needs a port from 1 to 65535".to_string())
+                            }
+                            Ok(port) => Some(port),
+                        }
+                    }
+                    Some("--config") => parsed.config = Some(value("--config")?.into()),
+                    Some("--dry-run") => parsed.dry_run = true,
+                    Some("--help") | Some("-h") => parsed.help = true,
+                    Some(option) if option.starts_with('-') => {
+                        return Err(format!("unknown option {}", option))
+                    }
+                    // For compatibility, the config can be given as the only argument.
+                    _ if parsed.config.is_none() => parsed.config = Some(arg.into()),
+                    _ => return Err(format!("unexpected argument {:?}", arg)),
+                }
+            }
+            Ok(parsed)
+        }
+    }
+    fn utf8(value: OsString, name: &str) -> Result<String, String> {
+        value
+            .into_string()
+            .map_err(|value| format!("{} got {:?}, which isn't UTF-8", name, value))
+    }
+}
+
+#[cfg(feature = "web")]
+pub mod auth {
+    use super::*;
+    use std::env;
+
+    /// Environment variable with the token, taking precedence over the config.
+    pub const TOKEN_ENV: &str = "HTTPWM_TOKEN";
 
-            let mut scheduler = WeekScheduler::empty(self.transition.to_transition()?);
+    /// Which requests are let through. Without a token, all are.
+    #[derive(Debug, Clone, Default)]
+    pub struct Auth {
+        token: Option<String>,
+        protect_reads: bool,
+    }
+    impl Auth {
+        /// An empty `token` is the same as none.
+        pub fn new(token: Option<String>, protect_reads: bool) -> Self {
+            Self {
+                token: token.filter(|token| !token.is_empty()),
+                protect_reads,
+            }
+        }
+        pub fn from_config(config: &config::Config) -> Self {
+            let token = env::var(TOKEN_ENV)
+                .ok()
+                .or_else(|| config.auth_token.clone());
+            Self::new(token, config.auth_read_only)
+        }
+        /// If the request has the token, or no token is required.
+        pub fn allows<T>(&self, request: &http::Request<T>) -> bool {
+            let token = match self.token.as_ref() {
+                Some(token) => token,
+                None => return true,
+            };
+            request
+                .headers()
+                .get("authorization")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()))
+        }
+        /// Same as [`Self::allows()`], but always lets reads through unless
+        /// [`config::Config::auth_read_only`] is set.
+        pub fn allows_read<T>(&self, request: &http::Request<T>) -> bool {
+            !self.protect_reads || self.allows(request)
+        }
+    }
 
-            scheduler.mon = fmt_time!(self.mon);
-            scheduler.tue = fmt_time!(self.tue);
-            scheduler.wed = fmt_time!(self.wed);
-            scheduler.thu = fmt_time!(self.thu);
-            scheduler.fri = fmt_time!(self.fri);
-            scheduler.sat = fmt_time!(self.sat);
-            scheduler.sun = fmt_time!(self.sun);
-            Some(scheduler)
+    /// Compares in time only depending on the length, so the token can't be guessed byte by byte.
+    pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+        if a.len() != b.len() {
+            return false;
         }
+        a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
     }
+}
+
+/// Quite nasty code
+pub mod save_state {
+    use super::*;
+    use std::collections::{BTreeMap, HashMap};
+
     pub struct DataWrapper(Data, bool);
     impl DataWrapper {
         pub fn new(data: Data) -> Self {
@@ -553,9 +1847,13 @@ pub mod save_state {
     pub struct Data {
         strength: Option<f64>,
         schedulers: Vec<datas::AddSchedulerData>,
-        week_scheduler: Option<WeekSchedulerData>,
+        week_scheduler: Option<WeekScheduler>,
         #[serde(skip_serializing_if = "Option::is_none")]
-        current_transition: Option<datas::TransitionData>,
+        current_transition: Option<Transition>,
+        #[serde(default)]
+        bedtime: Option<String>,
+        #[serde(default)]
+        scenes: BTreeMap<String, Scene>,
     }
     impl Data {
         pub fn read_from_file<P: AsRef<Path>>(
@@ -569,7 +1867,7 @@ pub mod save_state {
             }
             read(path.as_ref()).map(|mut data| {
                 if data.week_scheduler.is_none() {
-                    data.week_scheduler = Some(WeekSchedulerData::from_scheduler(week_scheduler));
+                    data.week_scheduler = Some(week_scheduler.clone());
                 }
                 data
             })
@@ -578,29 +1876,52 @@ pub mod save_state {
             Self {
                 strength: None,
                 schedulers: Vec::new(),
-                week_scheduler: Some(WeekSchedulerData::from_scheduler(scheduler)),
+                week_scheduler: Some(scheduler.clone()),
                 current_transition: None,
+                bedtime: None,
+                scenes: BTreeMap::new(),
             }
         }
 
-        pub fn apply<T: VariableOut + Send>(&self, controller: &Controller<T>) {
-            if let Some(s) = self.strength {
-                controller.send(Command::Set(Strength::new_clamped(s)));
+        /// `restore_strength` fades to the saved strength, see [`config::Config::restore_strength`].
+        pub fn apply<T: VariableOut + Send>(
+            &self,
+            controller: &Controller<T>,
+            restore_strength: bool,
+        ) -> Result<(), httpwm::Error> {
+            match self.strength {
+                Some(s) if restore_strength && s > 0.0 => {
+                    info!("Restoring strength {}.", s);
+                    controller.send(Command::SetTransition(Transition {
+                        from: Strength::off(),
+                        to: Strength::new_clamped(s),
+                        time: RESTORE_FADE,
+                        interpolation: TransitionInterpolation::Sine,
+                    }))?;
+                }
+                _ => {}
+            }
+            if let Some(bedtime) = self.bedtime.as_deref().and_then(parse_time) {
+                controller.send(Command::SetBedtime(bedtime))?;
             }
+            let bedtime = controller.get_state().lock().unwrap().ref_bedtime().clone();
             for scheduler in self
                 .schedulers
                 .iter()
-                .filter_map(|s| s.clone().into_command(true))
+                .filter_map(|s| s.clone().into_command(true, &bedtime))
             {
-                controller.send(scheduler);
+                controller.send(scheduler)?;
             }
-            if let Some(transition) = self
-                .current_transition
-                .as_ref()
-                .and_then(datas::TransitionData::to_transition)
-            {
-                controller.send(Command::SetTransition(transition));
+            for (name, scene) in &self.scenes {
+                controller.send(Command::SaveScene(name.clone(), scene.clone()))?;
+            }
+            for scheduler in self.schedulers.iter().filter(|s| !s.enabled) {
+                controller.send(Command::SetSchedulerEnabled(scheduler.name.clone(), false))?;
+            }
+            if let Some(transition) = self.current_transition.as_ref() {
+                controller.send(Command::SetTransition(Transition::clone(transition)))?;
             }
+            Ok(())
         }
 
         pub fn ref_strength(&self) -> Option<f64> {
@@ -609,7 +1930,24 @@ pub mod save_state {
         pub fn set_strength(&mut self, strength: Strength) -> Option<Strength> {
             self.strength
                 .replace(strength.into_inner())
-                .map(|f| Strength::new_clamped(f))
+                .map(Strength::new_clamped)
+        }
+
+        pub fn set_bedtime(&mut self, bedtime: chrono::NaiveTime) -> Option<String> {
+            self.bedtime.replace(bedtime.format("%H:%M:%S").to_string())
+        }
+        pub fn ref_bedtime(&self) -> Option<&str> {
+            self.bedtime.as_deref()
+        }
+
+        /// Replaces the parts of the config [`datas::ConfigData`] holds.
+        /// The bedtime is kept if `config` doesn't have one.
+        pub fn replace_config(&mut self, config: datas::ConfigData) {
+            self.week_scheduler = Some(config.week_scheduler);
+            self.schedulers = config.schedulers;
+            if config.bedtime.is_some() {
+                self.bedtime = config.bedtime;
+            }
         }
 
         pub fn ref_schedulers(&self) -> &Vec<datas::AddSchedulerData> {
@@ -619,39 +1957,33 @@ pub mod save_state {
             &mut self.schedulers
         }
 
-        pub fn ref_week_scheduler(&self) -> &WeekSchedulerData {
+        pub fn set_scenes(&mut self, scenes: &HashMap<String, Scene>) {
+            self.scenes = scenes
+                .iter()
+                .map(|(name, scene)| (name.clone(), scene.clone()))
+                .collect();
+        }
+
+        pub fn ref_week_scheduler(&self) -> &WeekScheduler {
             // ok, since it must be `Some`, it's just an option for parsing from file.
             self.week_scheduler.as_ref().unwrap()
         }
-        pub fn mut_week_scheduler(&mut self) -> &mut WeekSchedulerData {
+        pub fn mut_week_scheduler(&mut self) -> &mut WeekScheduler {
             // ok, since it must be `Some`, it's just an option for parsing from file.
             self.week_scheduler.as_mut().unwrap()
         }
-        pub fn set_week_scheduler(&mut self, new: &WeekScheduler) -> Option<WeekSchedulerData> {
-            self.week_scheduler
-                .replace(WeekSchedulerData::from_scheduler(new))
+        pub fn set_week_scheduler(&mut self, new: &WeekScheduler) -> Option<WeekScheduler> {
+            self.week_scheduler.replace(new.clone())
         }
         pub fn eq_transition(&self, other: Option<&Transition>) -> bool {
-            match self.current_transition.as_ref() {
-                Some(transition) => match transition.to_transition() {
-                    Some(transition) => match other {
-                        Some(other) => &transition == other,
-                        None => false,
-                    },
-                    None => false,
-                },
-                None => other.is_none(),
-            }
+            self.current_transition.as_ref() == other
         }
-        pub fn set_transition(
-            &mut self,
-            new: Option<&Transition>,
-        ) -> Option<datas::TransitionData> {
+        pub fn set_transition(&mut self, new: Option<&Transition>) -> Option<Transition> {
             match new {
                 None => self.current_transition.take(),
                 Some(transition) => self
                     .current_transition
-                    .replace(datas::TransitionData::from_transition(transition)),
+                    .replace(Transition::clone(transition)),
             }
         }
     }
@@ -668,48 +2000,60 @@ pub mod datas {
         pub fn parse(&self) -> Option<(Weekday, Option<NaiveTime>)> {
             let day: chrono::Weekday = self.day.parse().ok()?;
             let time = match self.time.as_ref() {
-                Some(time) => Some(parse_time(&time)?),
+                Some(time) => Some(parse_time(time)?),
                 None => None,
             };
             Some((day, time))
         }
     }
 
-    #[derive(Deserialize, Serialize, Debug, Clone)]
-    pub struct TransitionData {
-        from: f64,
-        to: f64,
-        time: f64,
-        interpolation: String,
-        extras: Vec<String>,
-    }
-    impl TransitionData {
-        pub fn to_transition(&self) -> Option<Transition> {
-            let from = Strength::new_clamped(self.from);
-            let to = Strength::new_clamped(self.to);
-            let time = Duration::from_secs_f64(self.time);
-
-            let interpolation =
-                TransitionInterpolation::from_str(&self.interpolation, &self.extras)?;
-            Some(Transition {
-                from,
-                to,
-                time,
-                interpolation,
-            })
+    /// Lowercase weekday to time, or `null` to remove the time. Days not present are left as is.
+    #[derive(Deserialize, Debug)]
+    #[serde(transparent)]
+    pub struct DayTimesData(HashMap<String, Option<String>>);
+    impl DayTimesData {
+        pub fn parse(&self) -> Option<HashMap<Weekday, Option<NaiveTime>>> {
+            let mut diff = HashMap::with_capacity(self.0.len());
+            for (day, time) in &self.0 {
+                let day: chrono::Weekday = day.parse().ok()?;
+                let time = match time.as_ref() {
+                    Some(time) => Some(parse_time(time)?),
+                    None => None,
+                };
+                diff.insert(day, time);
+            }
+            Some(diff)
         }
+    }
 
-        pub fn from_transition(transition: &Transition) -> Self {
-            let mut extras = Vec::with_capacity(4);
-
-            transition.interpolation.apply_extras(&mut extras);
+    /// The times and transitions of a [`WeekScheduler`], keyed by the lowercase weekday.
+    #[derive(Debug, Serialize)]
+    pub struct ScheduleData {
+        days: HashMap<String, Option<String>>,
+        transition: Transition,
+        day_transitions: HashMap<String, Transition>,
+    }
+    impl ScheduleData {
+        pub fn from_week_scheduler(scheduler: &WeekScheduler) -> Self {
+            let mut days = HashMap::with_capacity(7);
+            let mut day_transitions = HashMap::new();
+            for (day, time) in scheduler.all_days().iter() {
+                days.insert(
+                    weekday_to_lowercase_str(day).to_string(),
+                    time.map(|time| time.to_string()),
+                );
+                if let Some(transition) = scheduler.get_day_transition(*day) {
+                    day_transitions.insert(
+                        weekday_to_lowercase_str(day).to_string(),
+                        Transition::clone(transition),
+                    );
+                }
+            }
 
             Self {
-                from: Strength::clone(&transition.from).into_inner(),
-                to: Strength::clone(&transition.to).into_inner(),
-                time: transition.time.as_secs_f64(),
-                interpolation: transition.interpolation.as_str().to_string(),
-                extras,
+                days,
+                transition: Transition::clone(&scheduler.transition),
+                day_transitions,
             }
         }
     }
@@ -717,28 +2061,53 @@ pub mod datas {
     #[derive(Debug, Serialize)]
     pub struct StateData {
         strength: f64,
-        days: HashMap<String, Option<String>>,
-        transition: TransitionData,
+        #[serde(flatten)]
+        schedule: ScheduleData,
+        bedtime: String,
     }
     impl StateData {
         pub fn from_shared_state(state: &SharedState) -> Self {
-            let mut days = HashMap::with_capacity(7);
-            let mut day = chrono::Weekday::Mon;
-            for _ in 0..7 {
-                days.insert(
-                    weekday_to_lowercase_str(&day).to_string(),
-                    state
-                        .ref_week_schedule()
-                        .get(day)
-                        .map(|time| time.to_string()),
-                );
-                day = day.succ();
-            }
-
             Self {
                 strength: Strength::clone(state.get_strength()).into_inner(),
-                days,
-                transition: TransitionData::from_transition(&state.ref_week_schedule().transition),
+                schedule: ScheduleData::from_week_scheduler(state.ref_week_schedule()),
+                bedtime: state.ref_bedtime().get().to_string(),
+            }
+        }
+    }
+    /// A scene in `/scene/list`.
+    #[derive(Debug, Serialize)]
+    pub struct SceneData {
+        pub name: String,
+        strength: f64,
+    }
+    impl SceneData {
+        pub fn new(name: &str, scene: &Scene) -> Self {
+            Self {
+                name: name.to_string(),
+                strength: scene.strength.into_inner(),
+            }
+        }
+        /// All of `scenes`, sorted by name.
+        pub fn list(scenes: &HashMap<String, Scene>) -> Vec<Self> {
+            let mut list: Vec<Self> = scenes
+                .iter()
+                .map(|(name, scene)| Self::new(name, scene))
+                .collect();
+            list.sort_by(|a, b| a.name.cmp(&b.name));
+            list
+        }
+    }
+    /// A strength set on the output, `ago` seconds ago.
+    #[derive(Debug, Serialize)]
+    pub struct HistoryData {
+        ago: f64,
+        strength: f64,
+    }
+    impl HistoryData {
+        pub fn new(ago: Duration, strength: Strength) -> Self {
+            Self {
+                ago: ago.as_secs_f64(),
+                strength: strength.into_inner(),
             }
         }
     }
@@ -749,58 +2118,205 @@ pub mod datas {
         pub name: String,
         pub description: String,
         pub extras: Vec<String>,
-        pub transition: TransitionData,
+        pub transition: Transition,
+        /// See [`Scheduler::priority()`].
+        #[serde(default)]
+        pub priority: i32,
+        #[serde(default = "default_true")]
+        pub enabled: bool,
+        /// Moves every occurrence randomly by up to this many minutes, earlier or later.
+        #[serde(default)]
+        pub jitter: Option<f64>,
+    }
+    fn default_true() -> bool {
+        true
     }
     impl AddSchedulerData {
-        pub fn into_command(self, allow_past: bool) -> Option<Command> {
-            let transition = self.transition.to_transition()?;
-            let time = parse_time(&self.time)?;
+        /// `bedtime` is used by the `bedtime` kind, which takes the offset in minutes as it's extra.
+        pub fn into_command(
+            self,
+            allow_past: bool,
+            bedtime: &scheduler::Bedtime,
+        ) -> Option<Command> {
+            let Self {
+                kind,
+                time,
+                name,
+                description,
+                extras,
+                transition,
+                priority,
+                enabled: _,
+                jitter,
+            } = self;
             // Unwrap is ok, since we know `SetTransition` is clonable
             let run_command = ClonableCommand::new(Command::SetTransition(transition)).unwrap();
-            let common = extra_schedulers::Common::new(self.description, run_command);
-
-            let scheduler: Box<dyn Scheduler> = match self.kind.as_str() {
-                "at" if self.extras.len() == 1 => {
-                    let date_time =
-                        chrono::NaiveDate::parse_from_str(self.extras[0].as_str(), "%Y-%m-%d")
-                            .ok()?
-                            .and_time(time);
-                    if has_occurred(date_time) && !allow_past {
-                        return None;
+
+            let scheduler: Box<dyn Scheduler> = match kind.as_str() {
+                // `cron` and `bedtime` don't use the time.
+                "cron" if extras.len() == 1 => Box::new(
+                    CronScheduler::new(
+                        cron::CronSchedule::parse(&extras[0])?,
+                        description,
+                        run_command,
+                    )
+                    .with_priority(priority),
+                ),
+                "bedtime" if extras.len() == 1 => Box::new(
+                    scheduler::BedtimeScheduler::new(
+                        scheduler::Bedtime::clone(bedtime),
+                        chrono::Duration::minutes(extras[0].parse().ok()?),
+                        description,
+                        run_command,
+                    )
+                    .with_priority(priority),
+                ),
+                _ => {
+                    let time = parse_time(&time)?;
+                    let common = extra_schedulers::Common::new(description, run_command)
+                        .with_priority(priority);
+
+                    match kind.as_str() {
+                        "at" if extras.len() == 1 => {
+                            let date_time =
+                                chrono::NaiveDate::parse_from_str(extras[0].as_str(), "%Y-%m-%d")
+                                    .ok()?
+                                    .and_time(time);
+                            if has_occurred(date_time) && !allow_past {
+                                return None;
+                            }
+                            Box::new(extra_schedulers::At::new(common, date_time))
+                        }
+                        "every-week" if extras.len() == 1 => Box::new(
+                            extra_schedulers::EveryWeek::new(common, time, extras[0].parse().ok()?),
+                        ),
+                        "every-day" => Box::new(extra_schedulers::EveryDay::new(common, time)),
+                        _ => return None,
                     }
-                    Box::new(extra_schedulers::At::new(common, date_time))
                 }
-                "every-week" if self.extras.len() == 1 => Box::new(
-                    extra_schedulers::EveryWeek::new(common, time, self.extras[0].parse().ok()?),
-                ),
-                "every-day" => Box::new(extra_schedulers::EveryDay::new(common, time)),
-                _ => return None,
             };
-            Some(Command::AddReplaceScheduler(self.name, scheduler))
+            let scheduler: Box<dyn Scheduler> = match jitter {
+                Some(minutes) => Box::new(scheduler::JitterScheduler::new(
+                    scheduler,
+                    chrono::Duration::milliseconds((minutes * 60.0 * 1000.0) as i64),
+                )),
+                None => scheduler,
+            };
+            Some(Command::AddReplaceScheduler(name, scheduler))
+        }
+    }
+    /// The schedule, schedulers and bedtime, for moving them between installations.
+    /// Returned by `/export-config` and accepted by `/import-config`.
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct ConfigData {
+        pub week_scheduler: WeekScheduler,
+        pub schedulers: Vec<AddSchedulerData>,
+        /// The current bedtime is kept if this is `None`.
+        #[serde(default)]
+        pub bedtime: Option<String>,
+    }
+    impl ConfigData {
+        /// The schedulers and bedtime are only kept in `saved`; the schedule is taken from
+        /// the controller, as `saved` catches up on it later.
+        pub fn new(saved: &save_state::Data, week_scheduler: &WeekScheduler) -> Self {
+            Self {
+                week_scheduler: week_scheduler.clone(),
+                schedulers: saved.ref_schedulers().clone(),
+                bedtime: saved.ref_bedtime().map(str::to_string),
+            }
+        }
+        /// The new bedtime, if any, and the [`Command::ReplaceConfig`] applying the rest.
+        ///
+        /// Errors with the reason if any part is invalid, so nothing gets applied.
+        pub fn to_commands(
+            &self,
+            bedtime: &scheduler::Bedtime,
+        ) -> Result<(Option<NaiveTime>, Command), String> {
+            let new_bedtime = match self.bedtime.as_deref() {
+                Some(time) => {
+                    Some(parse_time(time).ok_or_else(|| format!("Invalid bedtime: {}", time))?)
+                }
+                None => None,
+            };
+            let mut schedulers = HashMap::with_capacity(self.schedulers.len());
+            let mut disabled = std::collections::HashSet::new();
+            for data in &self.schedulers {
+                if schedulers.contains_key(&data.name) {
+                    return Err(format!("Duplicate scheduler: {}", data.name));
+                }
+                match data.clone().into_command(true, bedtime) {
+                    Some(Command::AddReplaceScheduler(name, scheduler)) => {
+                        schedulers.insert(name, scheduler);
+                    }
+                    _ => return Err(format!("Invalid scheduler: {}", data.name)),
+                }
+                if !data.enabled {
+                    disabled.insert(data.name.clone());
+                }
+            }
+            Ok((
+                new_bedtime,
+                Command::ReplaceConfig(Box::new(self.week_scheduler.clone()), schedulers, disabled),
+            ))
+        }
+    }
+
+    /// The scheduler which runs next.
+    #[derive(Debug, Serialize)]
+    pub struct NextData {
+        /// `None` for the week scheduler
+        name: Option<String>,
+        description: String,
+        kind: String,
+        /// Seconds until it runs.
+        eta: f64,
+    }
+    impl NextData {
+        /// `now` is the current time of the controller's clock.
+        pub fn new(
+            state: &SharedState,
+            next: &scheduler::NextScheduler,
+            now: chrono::NaiveDateTime,
+        ) -> Self {
+            let scheduler: &dyn Scheduler = match next.name {
+                // Unwrap is ok; the name was just taken from the list.
+                Some(name) => state.ref_schedulers().get(name).unwrap().as_ref(),
+                None => state.ref_week_schedule(),
+            };
+            Self {
+                name: next.name.map(str::to_string),
+                description: scheduler.description().to_string(),
+                kind: scheduler.kind().to_string(),
+                eta: (next.at - now)
+                    .to_std()
+                    .unwrap_or(Duration::new(0, 0))
+                    .as_secs_f64(),
+            }
         }
     }
+
     #[derive(Debug, Serialize)]
     pub struct SchedulerData {
         name: String,
         description: String,
         kind: String,
         next_occurrence: String,
+        enabled: bool,
     }
     impl SchedulerData {
         pub fn from_scheduler(
             scheduler: &dyn Scheduler,
             name: String,
+            enabled: bool,
             now: &mut scheduler::LazyNow,
         ) -> Self {
             let dur = scheduler.get_next(now);
 
             let next_occurrence = match dur {
                 Next::At(date_time, _) => {
-                    let dur = date_time - get_naive_now();
+                    let dur = date_time - now.now();
                     if dur.num_days() > 0 {
-                        (get_naive_now() + dur)
-                            .format("%Y-%m-%d %H:%M:%S")
-                            .to_string()
+                        date_time.format("%Y-%m-%d %H:%M:%S").to_string()
                     } else if dur.num_hours() > 0 {
                         format!("In {} hours", dur.num_hours())
                     } else if dur.num_minutes() > 0 {
@@ -822,6 +2338,7 @@ pub mod datas {
                 description: scheduler.description().to_string(),
                 kind: scheduler.kind().to_string(),
                 next_occurrence,
+                enabled,
             }
         }
     }
@@ -852,6 +2369,7 @@ pub mod extra_schedulers {
     pub struct Common {
         description: String,
         command: ClonableCommand,
+        priority: i32,
     }
     impl Common {
         /// Returns `Err` when command is not clonable
@@ -859,14 +2377,25 @@ pub mod extra_schedulers {
             Self {
                 description,
                 command,
+                priority: 0,
             }
         }
+        pub fn with_priority(mut self, priority: i32) -> Self {
+            self.priority = priority;
+            self
+        }
         pub fn get_command(&self) -> ClonableCommand {
             // Ok, since it's guaranteed the command in `Common` is clonable.
             ClonableCommand::clone(&self.command)
         }
     }
 
+    /// `local` in the clock's time zone, moved past a DST gap if it falls in one.
+    /// See [`scheduler::resolve_local()`].
+    fn resolve(now: &scheduler::LazyNow, local: chrono::NaiveDateTime) -> chrono::NaiveDateTime {
+        now.before(local, chrono::Duration::zero())
+    }
+
     #[derive(Debug)]
     pub struct At {
         common: Common,
@@ -890,6 +2419,9 @@ pub mod extra_schedulers {
         fn kind(&self) -> &str {
             "At"
         }
+        fn priority(&self) -> i32 {
+            self.common.priority
+        }
     }
     #[derive(Debug)]
     pub struct EveryWeek {
@@ -903,14 +2435,12 @@ pub mod extra_schedulers {
         }
     }
     impl Scheduler for EveryWeek {
-        fn get_next(&self, now: &mut scheduler::LazyNow) -> Next {
-            let now = now.now();
-            if self.day == now.weekday() && now.time() < self.time {
-                // Unwrap is OK, now will never be over self.time.
-                Next::At(
-                    chrono::Local::today().naive_utc().and_time(self.time),
-                    self.common.get_command().into_inner(),
-                )
+        fn get_next(&self, lazy_now: &mut scheduler::LazyNow) -> Next {
+            let now = lazy_now.now();
+            let today = resolve(lazy_now, now.date().and_time(self.time));
+            // If it's due right now, it runs today.
+            if self.day == now.weekday() && now <= today {
+                Next::At(today, self.common.get_command().into_inner())
             } else {
                 // Unwrap is ok, we must have one day containing a date.
                 let (time, offset) = get_next_day(now.weekday(), |day| {
@@ -921,8 +2451,9 @@ pub mod extra_schedulers {
                     }
                 })
                 .unwrap();
+                let date = now.date() + chrono::Duration::days(offset as i64);
                 Next::At(
-                    now.date().and_time(time) + chrono::Duration::days(offset as i64),
+                    resolve(lazy_now, date.and_time(time)),
                     self.common.get_command().into_inner(),
                 )
             }
@@ -936,43 +2467,880 @@ pub mod extra_schedulers {
         fn kind(&self) -> &str {
             "Every week at"
         }
+        fn priority(&self) -> i32 {
+            self.common.priority
+        }
     }
+    /// How long after its time [`EveryDay`] still runs that day, unless changed with
+    /// [`EveryDay::with_grace()`].
+    pub const DEFAULT_GRACE_SECONDS: i64 = 5;
+
     #[derive(Debug)]
     pub struct EveryDay {
         common: Common,
         time: chrono::NaiveTime,
+        grace: chrono::Duration,
+        /// The occurrence which last ran, so it isn't run again within the grace.
+        fired: Option<chrono::NaiveDateTime>,
     }
     impl EveryDay {
         pub fn new(common: Common, time: chrono::NaiveTime) -> Self {
-            Self { common, time }
+            Self {
+                common,
+                time,
+                grace: chrono::Duration::seconds(DEFAULT_GRACE_SECONDS),
+                fired: None,
+            }
+        }
+        /// If the time passed at most `grace` ago, e.g. when added just too late,
+        /// it runs at once instead of tomorrow.
+        pub fn with_grace(mut self, grace: chrono::Duration) -> Self {
+            self.grace = grace;
+            self
         }
     }
     impl Scheduler for EveryDay {
-        fn get_next(&self, now: &mut scheduler::LazyNow) -> Next {
-            let now = now.now();
-            if now.time() < self.time {
-                // Unwrap is OK, now will never be over self.time.
-                Next::At(
-                    chrono::Local::today().naive_utc().and_time(self.time),
-                    self.common.get_command().into_inner(),
-                )
+        fn get_next(&self, lazy_now: &mut scheduler::LazyNow) -> Next {
+            let now = lazy_now.now();
+            // Use the date of `now`, not `Local::today()`, which can have passed midnight since.
+            // A time skipped by DST runs when the clocks have been set forward.
+            let today = resolve(lazy_now, now.date().and_time(self.time));
+            // If it's due right now, or was just missed, it runs today.
+            let next = if now <= today || (now - today <= self.grace && self.fired != Some(today)) {
+                today
             } else {
-                // Unwrap is OK, it's one day ahead!
-                Next::At(
-                    chrono::Local::today().naive_utc().and_time(self.time)
-                        + chrono::Duration::days(1),
-                    self.common.get_command().into_inner(),
-                )
-            }
+                let tomorrow = now.date() + chrono::Duration::days(1);
+                resolve(lazy_now, tomorrow.and_time(self.time))
+            };
+            Next::At(next, self.common.get_command().into_inner())
         }
         fn advance(&mut self) -> Keep {
             Keep::Keep
         }
+        fn advance_past(&mut self, occurrence: chrono::NaiveDateTime) -> Keep {
+            self.fired = Some(occurrence);
+            Keep::Keep
+        }
         fn description(&self) -> &str {
             self.common.description.as_str()
         }
         fn kind(&self) -> &str {
             "Every day at"
         }
+        fn priority(&self) -> i32 {
+            self.common.priority
+        }
+    }
+}
+
+/// What the endpoints do with the controller, apart from reading the request.
+/// The errors are the status to respond with.
+pub mod handlers {
+    use super::*;
+    use http::StatusCode;
+    use std::sync::mpsc::{Receiver, RecvTimeoutError};
+
+    /// The status when the controller doesn't answer: a stopped controller thread is unavailable,
+    /// a slow one is an internal error.
+    pub fn unresponsive(err: RecvTimeoutError) -> StatusCode {
+        match err {
+            RecvTimeoutError::Timeout => StatusCode::INTERNAL_SERVER_ERROR,
+            RecvTimeoutError::Disconnected => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+    /// Waits a second for the controller's answer on `receiver`.
+    /// It's done on a blocking thread, so the server keeps handling other requests.
+    pub async fn answer<T: Send + 'static>(receiver: Receiver<T>) -> Result<T, RecvTimeoutError> {
+        tokio::task::spawn_blocking(move || receiver.recv_timeout(Duration::from_secs(1)))
+            .await
+            .unwrap_or(Err(RecvTimeoutError::Disconnected))
+    }
+
+    /// `/add-scheduler`, with the [`datas::AddSchedulerData`] as JSON in `body`.
+    pub fn add_scheduler<T: VariableOut + Send>(
+        controller: &Mutex<Controller<T>>,
+        save: &Mutex<save_state::DataWrapper>,
+        body: &[u8],
+    ) -> Result<(), StatusCode> {
+        let data: datas::AddSchedulerData =
+            serde_json::from_slice(body).map_err(|_| StatusCode::BAD_REQUEST)?;
+        let controller = controller.lock().unwrap();
+        let bedtime = controller.get_state().lock().unwrap().ref_bedtime().clone();
+        let command = data
+            .clone()
+            .into_command(false, &bedtime)
+            .ok_or(StatusCode::BAD_REQUEST)?;
+        controller
+            .send(command)
+            .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+        // Right after it's added, so it never runs.
+        if !data.enabled {
+            controller
+                .send(Command::SetSchedulerEnabled(data.name.clone(), false))
+                .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+        }
+        save.lock().unwrap().get_mut().mut_schedulers().push(data);
+        Ok(())
+    }
+    /// `/delete-scheduler`. The saved state is updated when it notices the scheduler is gone.
+    pub async fn delete_scheduler<T: VariableOut + Send>(
+        controller: &Mutex<Controller<T>>,
+        id: String,
+    ) -> Result<(), StatusCode> {
+        let receiver = { controller.lock().unwrap().send_remove_scheduler(id) };
+        match answer(receiver).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(StatusCode::NOT_FOUND),
+            Err(err) => Err(unresponsive(err)),
+        }
+    }
+    /// `/ws/strength`. Gets every [`Strength`] set on the output.
+    ///
+    /// The controller's channel blocks, so it's forwarded from a blocking thread.
+    /// It stops within a second of the returned receiver being dropped.
+    pub fn subscribe_strengths<T: VariableOut + Send>(
+        controller: &Mutex<Controller<T>>,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<Strength> {
+        let receiver = controller.lock().unwrap().subscribe();
+        let (sender, updates) = tokio::sync::mpsc::unbounded_channel();
+        tokio::task::spawn_blocking(move || loop {
+            match receiver.recv_timeout(Duration::from_secs(1)) {
+                Ok(strength) => {
+                    if sender.send(strength).is_err() {
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if sender.is_closed() {
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        });
+        updates
+    }
+    /// `/pause` and `/resume`. Errors with [`StatusCode::CONFLICT`] if it's already done.
+    pub async fn set_paused<T: VariableOut + Send>(
+        controller: &Mutex<Controller<T>>,
+        paused: bool,
+    ) -> Result<(), StatusCode> {
+        let receiver = {
+            let controller = controller.lock().unwrap();
+            if paused {
+                controller.send_pause()
+            } else {
+                controller.send_resume()
+            }
+        };
+        match answer(receiver).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(StatusCode::CONFLICT),
+            Err(err) => Err(unresponsive(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDateTime, TimeZone};
+
+    fn at(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+    fn cron(expression: &str, description: &str) -> Box<dyn Scheduler> {
+        Box::new(CronScheduler::new(
+            cron::CronSchedule::parse(expression).unwrap(),
+            description.to_string(),
+            ClonableCommand::new(Command::Set(Strength::full())).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn next_is_the_nearest() {
+        let mut state = SharedState::new(WeekScheduler::empty(Transition::default()));
+        let now = at("2021-06-02 06:00:00");
+        assert!(state
+            .get_next_scheduler(&mut scheduler::LazyNow::at(now))
+            .is_none());
+
+        state
+            .mut_schedulers()
+            .insert("late".to_string(), cron("0 9 * * *", "Sleep in"));
+        state
+            .mut_schedulers()
+            .insert("early".to_string(), cron("30 6 * * *", "Wake up"));
+        let next = state
+            .get_next_scheduler(&mut scheduler::LazyNow::at(now))
+            .unwrap();
+        let data = serde_json::to_value(datas::NextData::new(&state, &next, now)).unwrap();
+        assert_eq!(data["name"], "early");
+        assert_eq!(data["description"], "Wake up");
+        assert_eq!(data["kind"], "Cron");
+        assert_eq!(data["eta"], 30.0 * 60.0);
+    }
+
+    #[test]
+    fn preview_of_a_sine_transition() {
+        let json = r#"{"from":0.2,"to":0.8,"time":10,"interpolation":"sine"}"#;
+        let transition: Transition = serde_json::from_str(json).unwrap();
+        let strengths = preview_transition(&transition, 5);
+        assert_eq!(strengths.len(), 5);
+        assert_eq!(strengths[0], Strength::new(0.2));
+        assert_eq!(strengths[4], Strength::new(0.8));
+        // Symmetric around the middle.
+        assert!((strengths[2].into_inner() - 0.5).abs() < 1e-9);
+        assert!(strengths[1].into_inner() < 0.35);
+        assert!(strengths[3].into_inner() > 0.65);
+    }
+
+    /// A clock in Stockholm, stopped at `now`.
+    #[derive(Debug)]
+    struct Stockholm(NaiveDateTime);
+    impl scheduler::Clock for Stockholm {
+        fn now(&self) -> NaiveDateTime {
+            self.0
+        }
+        fn before(&self, local: NaiveDateTime, duration: chrono::Duration) -> NaiveDateTime {
+            (scheduler::resolve_local(&chrono_tz::Europe::Stockholm, local) - duration)
+                .naive_local()
+        }
+    }
+    fn every_day(time: &str) -> extra_schedulers::EveryDay {
+        extra_schedulers::EveryDay::new(
+            extra_schedulers::Common::new(
+                "every day".to_string(),
+                ClonableCommand::new(Command::Set(Strength::full())).unwrap(),
+            ),
+            NaiveTime::parse_from_str(time, "%H:%M:%S").unwrap(),
+        )
+    }
+    fn next_in_stockholm(scheduler: &dyn Scheduler, now: &str) -> NaiveDateTime {
+        let clock = Arc::new(Stockholm(at(now)));
+        match scheduler.get_next(&mut scheduler::LazyNow::from_clock(clock)) {
+            Next::At(date_time, _) => date_time,
+            Next::Unknown => panic!("it runs every day"),
+        }
+    }
+
+    /// A clock in `.0`, stopped at the UTC time `.1`.
+    #[derive(Debug)]
+    struct Zoned(chrono_tz::Tz, NaiveDateTime);
+    impl scheduler::Clock for Zoned {
+        fn now(&self) -> NaiveDateTime {
+            self.0.from_utc_datetime(&self.1).naive_local()
+        }
+        fn before(&self, local: NaiveDateTime, duration: chrono::Duration) -> NaiveDateTime {
+            (scheduler::resolve_local(&self.0, local) - duration).naive_local()
+        }
+    }
+
+    #[test]
+    fn week_scheduler_in_time_zones() {
+        let instant = Transition::new(
+            Strength::off(),
+            Strength::full(),
+            Duration::new(0, 0),
+            TransitionInterpolation::Constant,
+        )
+        .unwrap();
+        let week = WeekScheduler::builder(instant)
+            .day(Weekday::Tue, NaiveTime::from_hms_opt(7, 0, 0).unwrap())
+            .day(Weekday::Wed, NaiveTime::from_hms_opt(7, 0, 0).unwrap())
+            .build();
+        let utc = at("2021-06-02 03:00:00");
+        let next = |time_zone| {
+            let clock = Arc::new(Zoned(time_zone, utc));
+            let now = scheduler::Clock::now(&*clock);
+            match week.get_next(&mut scheduler::LazyNow::from_clock(clock)) {
+                Next::At(date_time, _) => (date_time, date_time - now),
+                Next::Unknown => panic!("it runs every Tuesday and Wednesday"),
+            }
+        };
+        // 05:00 on Wednesday in Stockholm, at UTC+2.
+        let (stockholm, in_stockholm) = next(chrono_tz::Europe::Stockholm);
+        assert_eq!(stockholm, at("2021-06-02 07:00:00"));
+        assert_eq!(in_stockholm, chrono::Duration::hours(2));
+        // Still 23:00 on Tuesday in New York, at UTC-4.
+        let (new_york, in_new_york) = next(chrono_tz::America::New_York);
+        assert_eq!(new_york, at("2021-06-02 07:00:00"));
+        assert_eq!(in_new_york, chrono::Duration::hours(8));
+    }
+
+    #[test]
+    fn every_day_boundaries() {
+        let scheduler = every_day("07:00:00");
+        let next = |now| next_in_stockholm(&scheduler, now);
+        assert_eq!(next("2021-06-02 06:59:59"), at("2021-06-02 07:00:00"));
+        assert_eq!(next("2021-06-02 07:00:00"), at("2021-06-02 07:00:00"));
+        assert_eq!(next("2021-06-02 07:01:00"), at("2021-06-03 07:00:00"));
+        assert_eq!(next("2021-06-02 23:59:59"), at("2021-06-03 07:00:00"));
+    }
+
+    #[test]
+    fn every_day_just_missed() {
+        let mut scheduler = every_day("07:00:00");
+        // 1s ago is within the grace, so it runs now.
+        assert_eq!(
+            next_in_stockholm(&scheduler, "2021-06-02 07:00:01"),
+            at("2021-06-02 07:00:00")
+        );
+        // Showing the schedulers doesn't change that.
+        assert_eq!(
+            next_in_stockholm(&scheduler, "2021-06-02 07:00:01"),
+            at("2021-06-02 07:00:00")
+        );
+        // 1min ago it waits until tomorrow.
+        assert_eq!(
+            next_in_stockholm(&scheduler, "2021-06-02 07:01:00"),
+            at("2021-06-03 07:00:00")
+        );
+        // Once it's run, it isn't run again within the grace.
+        scheduler.advance_past(at("2021-06-02 07:00:00"));
+        assert_eq!(
+            next_in_stockholm(&scheduler, "2021-06-02 07:00:02"),
+            at("2021-06-03 07:00:00")
+        );
+        // A longer grace covers 1min ago too.
+        let scheduler = every_day("07:00:00").with_grace(chrono::Duration::minutes(2));
+        assert_eq!(
+            next_in_stockholm(&scheduler, "2021-06-02 07:01:00"),
+            at("2021-06-02 07:00:00")
+        );
+    }
+
+    #[test]
+    fn week_scheduler_over_dst() {
+        let transition = |minutes: u64, interpolation| {
+            Transition::new(
+                Strength::off(),
+                Strength::full(),
+                Duration::from_secs(minutes * 60),
+                interpolation,
+            )
+            .unwrap()
+        };
+        let week = |time, minutes: u64, interpolation| {
+            WeekScheduler::builder(transition(minutes, interpolation))
+                .day(
+                    Weekday::Sun,
+                    NaiveTime::parse_from_str(time, "%H:%M:%S").unwrap(),
+                )
+                .build()
+        };
+        // The clocks went from 02:00 to 03:00 on 2021-03-28, so 02:30 doesn't exist.
+        let skipped = week("02:30:00", 0, TransitionInterpolation::Constant);
+        assert_eq!(
+            next_in_stockholm(&skipped, "2021-03-28 01:00:00"),
+            at("2021-03-28 03:00:00")
+        );
+        // An hour of real time before 03:30 is 01:30, not the skipped 02:30.
+        let after_skip = week("03:30:00", 60, TransitionInterpolation::Linear);
+        assert_eq!(
+            next_in_stockholm(&after_skip, "2021-03-27 12:00:00"),
+            at("2021-03-28 01:30:00")
+        );
+        // They went back from 03:00 to 02:00 on 2021-10-31, so two hours before 03:30 is
+        // the first 02:30.
+        let after_repeat = week("03:30:00", 120, TransitionInterpolation::Linear);
+        assert_eq!(
+            next_in_stockholm(&after_repeat, "2021-10-30 12:00:00"),
+            at("2021-10-31 02:30:00")
+        );
+    }
+
+    #[test]
+    fn every_day_over_dst() {
+        // The clocks went from 02:00 to 03:00 on 2021-03-28.
+        let scheduler = every_day("02:30:00");
+        let next = |now| next_in_stockholm(&scheduler, now);
+        assert_eq!(next("2021-03-28 01:00:00"), at("2021-03-28 03:00:00"));
+        assert_eq!(next("2021-03-27 12:00:00"), at("2021-03-28 03:00:00"));
+        assert_eq!(next("2021-03-28 03:10:00"), at("2021-03-29 02:30:00"));
+        // When they're set back, it runs the first time.
+        assert_eq!(next("2021-10-31 01:00:00"), at("2021-10-31 02:30:00"));
+
+        let weekly = extra_schedulers::EveryWeek::new(
+            extra_schedulers::Common::new(
+                "sundays".to_string(),
+                ClonableCommand::new(Command::Set(Strength::full())).unwrap(),
+            ),
+            NaiveTime::from_hms_opt(2, 30, 0).unwrap(),
+            Weekday::Sun,
+        );
+        assert_eq!(
+            next_in_stockholm(&weekly, "2021-03-25 12:00:00"),
+            at("2021-03-28 03:00:00")
+        );
+        assert_eq!(
+            next_in_stockholm(&weekly, "2021-03-28 02:00:00"),
+            at("2021-03-28 03:00:00")
+        );
+    }
+
+    #[test]
+    fn boots_with_config() {
+        let path = std::env::temp_dir().join(format!("httpwm-config-{}.ron", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"(
+                week_scheduler: Some((
+                    mon: Some("06:30:00"), tue: Some("07:00"), wed: None, thu: None,
+                    fri: None, sat: None, sun: Some("10:00:00"),
+                    transition: (from: 0.0, to: 0.8, time: 600.0, interpolation: (name: "linear")),
+                )),
+            )"#,
+        )
+        .unwrap();
+        let args = args::Args::parse(vec![path.clone().into_os_string()]).unwrap();
+        let config = config::Config::load(&args);
+        std::fs::remove_file(&path).unwrap();
+        assert!(config.startup_transition.is_none());
+
+        let controller = Controller::new(PrintOut, config.week_scheduler.unwrap());
+        {
+            let state = controller.get_state();
+            let state = state.lock().unwrap();
+            let week = state.ref_week_schedule();
+            assert_eq!(week.mon, NaiveTime::from_hms_opt(6, 30, 0));
+            assert_eq!(week.tue, NaiveTime::from_hms_opt(7, 0, 0));
+            assert_eq!(week.wed, None);
+            assert_eq!(week.sun, NaiveTime::from_hms_opt(10, 0, 0));
+            assert_eq!(week.transition.to, Strength::new(0.8));
+            assert_eq!(week.transition.time, Duration::from_secs(600));
+        }
+        controller.finish();
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let week = WeekScheduler::builder(Transition::default())
+            .day(Weekday::Mon, NaiveTime::from_hms_opt(7, 0, 0).unwrap())
+            .build();
+        let mut saved = save_state::Data::from_week_scheduler(&week);
+        for json in [
+            r#"{"kind":"every-day","time":"08:00","name":"morning","description":"Wake up",
+                "extras":[],"transition":{"from":0,"to":1,"time":60,"interpolation":"linear"}}"#,
+            r#"{"kind":"cron","time":"","name":"night","description":"Dim","extras":["0 22 * * *"],
+                "transition":{"from":1,"to":0,"time":60,"interpolation":"sine"},"enabled":false}"#,
+        ] {
+            saved
+                .mut_schedulers()
+                .push(serde_json::from_str(json).unwrap());
+        }
+        saved.set_bedtime(NaiveTime::from_hms_opt(23, 0, 0).unwrap());
+        let controller = Controller::new(PrintOut, week);
+        saved.apply(&controller, false).unwrap();
+        let list = |controller: &Controller<PrintOut>| {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            controller.send(Command::ListSchedulers(sender)).unwrap();
+            let mut list: Vec<_> = receiver
+                .recv()
+                .unwrap()
+                .into_iter()
+                .map(|info| (info.name, info.kind, info.description, info.enabled))
+                .collect();
+            list.sort();
+            list
+        };
+        let before = list(&controller);
+        assert_eq!(before.len(), 2);
+
+        let exported = {
+            let state = controller.get_state();
+            let state = state.lock().unwrap();
+            serde_json::to_string(&datas::ConfigData::new(&saved, state.ref_week_schedule()))
+                .unwrap()
+        };
+        controller.send(Command::ClearAllSchedulers).unwrap();
+        assert!(list(&controller).is_empty());
+
+        let imported: datas::ConfigData = serde_json::from_str(&exported).unwrap();
+        let bedtime = controller.get_state().lock().unwrap().ref_bedtime().clone();
+        let (new_bedtime, command) = imported.to_commands(&bedtime).unwrap();
+        assert_eq!(new_bedtime, NaiveTime::from_hms_opt(23, 0, 0));
+        controller.send(command).unwrap();
+        assert_eq!(list(&controller), before);
+        {
+            let state = controller.get_state();
+            let state = state.lock().unwrap();
+            assert_eq!(
+                serde_json::to_string(state.ref_week_schedule()).unwrap(),
+                serde_json::to_string(&imported.week_scheduler).unwrap()
+            );
+        }
+        controller.finish();
+
+        // Nothing is applied if a part is invalid.
+        let mut invalid: datas::ConfigData = serde_json::from_str(&exported).unwrap();
+        invalid.schedulers[0].extras = vec!["not cron".to_string()];
+        invalid.schedulers[0].kind = "cron".to_string();
+        assert!(invalid.to_commands(&bedtime).is_err());
+    }
+
+    #[test]
+    fn add_disabled_scheduler() {
+        let week = WeekScheduler::empty(Transition::default());
+        let save = Mutex::new(save_state::DataWrapper::new(
+            save_state::Data::from_week_scheduler(&week),
+        ));
+        let controller = Mutex::new(Controller::new(PrintOut, week));
+        let json =
+            br#"{"kind":"cron","time":"","name":"night","description":"Dim","extras":["* * * * *"],
+            "transition":{"from":1,"to":0,"time":60,"interpolation":"sine"},"enabled":false}"#;
+        handlers::add_scheduler(&controller, &save, json).unwrap();
+        assert_eq!(
+            handlers::add_scheduler(&controller, &save, b"{}"),
+            Err(http::StatusCode::BAD_REQUEST)
+        );
+
+        let controller = controller.into_inner().unwrap();
+        let schedulers = controller.send_list_schedulers().recv().unwrap();
+        assert_eq!(schedulers.len(), 1);
+        assert!(!schedulers[0].enabled);
+        let mut save = save.into_inner().unwrap();
+        assert_eq!(save.get_mut().mut_schedulers().len(), 1);
+        assert!(!save.get_mut().mut_schedulers()[0].enabled);
+        controller.finish();
+    }
+
+    #[tokio::test]
+    async fn delete_scheduler() {
+        let controller = Mutex::new(Controller::new(
+            PrintOut,
+            WeekScheduler::empty(Transition::default()),
+        ));
+        controller
+            .lock()
+            .unwrap()
+            .send(Command::AddReplaceScheduler(
+                "night".to_string(),
+                cron("0 22 * * *", "Dim"),
+            ))
+            .unwrap();
+        handlers::delete_scheduler(&controller, "night".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            handlers::delete_scheduler(&controller, "night".to_string()).await,
+            Err(http::StatusCode::NOT_FOUND)
+        );
+
+        let controller = controller.into_inner().unwrap();
+        assert!(controller.send_list_schedulers().recv().unwrap().is_empty());
+        controller.finish();
+    }
+
+    #[tokio::test]
+    async fn pause_and_resume() {
+        let controller = Mutex::new(Controller::new(
+            PrintOut,
+            WeekScheduler::empty(Transition::default()),
+        ));
+        let paused = || {
+            let receiver = controller.lock().unwrap().send_get_status();
+            receiver.recv().unwrap().paused
+        };
+        assert_eq!(
+            handlers::set_paused(&controller, false).await,
+            Err(http::StatusCode::CONFLICT)
+        );
+        handlers::set_paused(&controller, true).await.unwrap();
+        assert!(paused());
+        assert_eq!(
+            handlers::set_paused(&controller, true).await,
+            Err(http::StatusCode::CONFLICT)
+        );
+        handlers::set_paused(&controller, false).await.unwrap();
+        assert!(!paused());
+        controller.into_inner().unwrap().finish();
+    }
+
+    #[tokio::test]
+    async fn strength_socket_gets_sets() {
+        let controller = Mutex::new(Controller::new(
+            PrintOut,
+            WeekScheduler::empty(Transition::default()),
+        ));
+        let mut updates = handlers::subscribe_strengths(&controller);
+        controller
+            .lock()
+            .unwrap()
+            .send(Command::Set(Strength::new(0.4)))
+            .unwrap();
+        assert_eq!(updates.recv().await, Some(Strength::new(0.4)));
+
+        controller.into_inner().unwrap().finish();
+        // The forwarding stops with the controller.
+        assert_eq!(updates.recv().await, None);
+    }
+
+    /// Records every strength it's set to.
+    #[derive(Debug, Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<Strength>>>);
+    impl VariableOut for Recorder {
+        fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+            self.0.lock().unwrap().push(value);
+            Ok(())
+        }
+        fn enable(&mut self) {}
+        fn disable(&mut self) {}
+        fn prepare(&mut self) {}
+    }
+
+    #[test]
+    fn max_strength_caps() {
+        let config = config::Config {
+            max_strength: Some(0.8),
+            ..Default::default()
+        };
+        let recorder = Recorder::default();
+        let mut output = config::limit_output(recorder.clone(), &config);
+        for value in [0.0, 0.5, 0.8, 0.9, 1.0] {
+            output.set(Strength::new(value)).unwrap();
+        }
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [0.0, 0.5, 0.8, 0.8, 0.8].map(Strength::new)
+        );
+    }
+
+    #[test]
+    fn restores_saved_strength() {
+        let path = std::env::temp_dir().join(format!("httpwm-state-{}.ron", std::process::id()));
+        std::fs::write(
+            &path,
+            "(strength: Some(0.6), schedulers: [], week_scheduler: None)",
+        )
+        .unwrap();
+        let week = WeekScheduler::empty(Transition::default());
+        let saved = save_state::Data::read_from_file(&path, &week);
+        std::fs::write(&path, "not a state file").unwrap();
+        assert!(save_state::Data::read_from_file(&path, &week).is_err());
+        std::fs::remove_file(&path).unwrap();
+        let saved = saved.unwrap();
+        assert_eq!(saved.ref_strength(), Some(0.6));
+
+        let status = |restore_strength| {
+            let controller = Controller::new(Recorder::default(), week.clone());
+            saved.apply(&controller, restore_strength).unwrap();
+            let (sender, receiver) = std::sync::mpsc::channel();
+            controller.send(Command::GetStatus(sender)).unwrap();
+            let status = receiver.recv().unwrap();
+            controller.send(Command::Set(Strength::off())).unwrap();
+            controller.finish();
+            status
+        };
+        let restoring = status(true).transition.expect("it fades in");
+        assert_eq!(restoring.from, Strength::off());
+        assert_eq!(restoring.to, Strength::new(0.6));
+        assert_eq!(restoring.time, RESTORE_FADE);
+        // Opt-in; it stays off otherwise.
+        assert_eq!(status(false).transition, None);
+    }
+
+    #[test]
+    fn parses_args() {
+        let parse = |args: &[&str]| args::Args::parse(args.iter().map(Into::into));
+        assert_eq!(
+            parse(&[
+                "--output",
+                "softpwm",
+                "--pin",
+                "12",
+                "--port",
+                "9090",
+                "--dry-run",
+                "httpwm.ron",
+            ]),
+            Ok(args::Args {
+                output: args::OutputKind::SoftPwm,
+                pin: 12,
+                port: Some(9090),
+                config: Some(PathBuf::from("httpwm.ron")),
+                dry_run: true,
+                help: false,
+            })
+        );
+        assert_eq!(parse(&[]), Ok(args::Args::default()));
+        assert_eq!(
+            parse(&["--config", "a.ron"]).unwrap().config,
+            Some(PathBuf::from("a.ron"))
+        );
+        assert!(parse(&["--output", "dmx"]).is_err());
+        assert!(parse(&["--port", "0"]).is_err());
+        assert!(parse(&["--port"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+        assert!(parse(&["a.ron", "b.ron"]).is_err());
+    }
+
+    #[test]
+    fn scene_save_list_recall_delete() {
+        assert!(valid_scene_name("movie night"));
+        assert!(valid_scene_name("dinner_2"));
+        assert!(!valid_scene_name(""));
+        assert!(!valid_scene_name("a/b"));
+        assert!(!valid_scene_name(&"a".repeat(SCENE_NAME_MAX + 1)));
+
+        let controller = Controller::new(
+            Recorder::default(),
+            WeekScheduler::empty(Transition::default()),
+        );
+        for (name, strength, time, interpolation) in
+            [("movie", 0.1, 2, "sine"), ("dinner", 0.6, 0, "linear")]
+        {
+            let scene: Scene = serde_json::from_value(serde_json::json!({
+                "strength": strength,
+                "time": time,
+                "interpolation": {"name": interpolation},
+            }))
+            .unwrap();
+            controller
+                .send(Command::SaveScene(name.to_string(), scene))
+                .unwrap();
+        }
+        let timeout = Duration::from_secs(1);
+        let list = |controller: &Controller<Recorder>| {
+            serde_json::to_value(datas::SceneData::list(
+                controller.get_state().lock().unwrap().ref_scenes(),
+            ))
+            .unwrap()
+        };
+        // Recalling waits on the controller, so the scenes are saved after it.
+        assert_eq!(
+            controller
+                .send_recall_scene("dinner".to_string())
+                .recv_timeout(timeout),
+            Ok(true)
+        );
+        assert_eq!(
+            list(&controller),
+            serde_json::json!([
+                {"name": "dinner", "strength": 0.6},
+                {"name": "movie", "strength": 0.1},
+            ])
+        );
+        assert_eq!(
+            controller
+                .send_recall_scene("party".to_string())
+                .recv_timeout(timeout),
+            Ok(false)
+        );
+
+        assert_eq!(
+            controller
+                .send_remove_scene("movie".to_string())
+                .recv_timeout(timeout),
+            Ok(true)
+        );
+        assert_eq!(
+            controller
+                .send_remove_scene("movie".to_string())
+                .recv_timeout(timeout),
+            Ok(false)
+        );
+        assert_eq!(
+            list(&controller),
+            serde_json::json!([{"name": "dinner", "strength": 0.6}])
+        );
+        let recorder = controller.finish();
+        assert_eq!(recorder.0.lock().unwrap().last(), Some(&Strength::new(0.6)));
+    }
+
+    #[test]
+    fn custom_port() {
+        let config = config::Config {
+            port: Some(9090),
+            ..Default::default()
+        };
+        let args = args::Args::parse(Vec::new()).unwrap();
+        let server = config::ServerConfig::new(&config, &args).unwrap();
+        assert_eq!(
+            server,
+            config::ServerConfig {
+                port: 9090,
+                host_name: "localhost".to_string(),
+                web_dir: PathBuf::from("web"),
+            }
+        );
+
+        let args = args::Args::parse(vec!["--port".into(), "9191".into()]).unwrap();
+        let server = config::ServerConfig::new(&config, &args).unwrap();
+        assert_eq!(server.port, 9191);
+
+        let config = config::Config {
+            port: Some(0),
+            ..Default::default()
+        };
+        let args = args::Args::parse(Vec::new()).unwrap();
+        assert!(config::ServerConfig::new(&config, &args).is_err());
+    }
+
+    #[test]
+    fn schedule_json() {
+        let scheduler = WeekScheduler::builder(Transition::default())
+            .day(Weekday::Mon, NaiveTime::from_hms_opt(7, 0, 0).unwrap())
+            .day_with_transition(
+                Weekday::Sat,
+                NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+                Transition::new(
+                    Strength::off(),
+                    Strength::new(0.5),
+                    Duration::from_secs(60),
+                    TransitionInterpolation::Linear,
+                )
+                .unwrap(),
+            )
+            .build();
+        let json =
+            serde_json::to_string(&datas::ScheduleData::from_week_scheduler(&scheduler)).unwrap();
+        let data: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(data["days"]["mon"], "07:00:00");
+        assert_eq!(data["days"]["sat"], "09:30:00");
+        assert_eq!(data["days"]["sun"], serde_json::Value::Null);
+        assert_eq!(data["days"].as_object().unwrap().len(), 7);
+        assert_eq!(data["transition"]["time"], 15.0 * 60.0);
+        assert_eq!(data["day_transitions"]["sat"]["to"], 0.5);
+        assert_eq!(
+            data["day_transitions"]["sat"]["interpolation"]["name"],
+            "linear"
+        );
+        assert!(data["day_transitions"].get("mon").is_none());
+    }
+
+    #[test]
+    fn bearer_token() {
+        let request = |authorization: Option<&str>| {
+            let mut request = http::Request::builder().uri("/set-strength?strength=1");
+            if let Some(authorization) = authorization {
+                request = request.header("authorization", authorization);
+            }
+            request.body(()).unwrap()
+        };
+        let auth = auth::Auth::new(Some("secret".to_string()), false);
+        assert!(auth.allows(&request(Some("Bearer secret"))));
+        assert!(!auth.allows(&request(None)));
+        assert!(!auth.allows(&request(Some("Bearer secrets"))));
+        assert!(!auth.allows(&request(Some("secret"))));
+        // Reads are let through unless they're protected too.
+        assert!(auth.allows_read(&request(None)));
+        let protected = auth::Auth::new(Some("secret".to_string()), true);
+        assert!(!protected.allows_read(&request(None)));
+        assert!(protected.allows_read(&request(Some("Bearer secret"))));
+
+        // Without a token, or with an empty one, everything is allowed.
+        for open in [
+            auth::Auth::default(),
+            auth::Auth::new(Some(String::new()), true),
+        ] {
+            assert!(open.allows(&request(None)));
+            assert!(open.allows_read(&request(None)));
+        }
+
+        assert!(auth::constant_time_eq(b"token", b"token"));
+        assert!(!auth::constant_time_eq(b"token", b"tokem"));
+        assert!(!auth::constant_time_eq(b"token", b"tokens"));
     }
 }
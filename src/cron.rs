@@ -0,0 +1,348 @@
+//! A [`Scheduler`] driven by a standard 5-field cron expression.
+//!
+//! The fields are `minute hour day-of-month month day-of-week`.
+//! Each field accepts `*`, single values, ranges (`1-5`), lists (`1,3,5`) and steps (`*/15`, `0-30/10`).
+//! Day of week is `0-7`, where both `0` and `7` are Sunday.
+//!
+//! As in most cron implementations, if both day-of-month and day-of-week are restricted,
+//! a day matches if *either* of them match.
+
+use crate::scheduler::{Keep, LazyNow, Next, Scheduler};
+use crate::ClonableCommand;
+use chrono::prelude::*;
+
+/// How many days forward we look for a match before giving up.
+/// Eight years covers leap days (`29 2 *`) combined with a weekday restriction.
+const MAX_DAYS_AHEAD: i64 = 366 * 8;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Field(u64);
+impl Field {
+    fn parse(string: &str, min: u8, max: u8) -> Option<Self> {
+        let mut bits = 0_u64;
+        for part in string.split(',') {
+            let (range, step) = match part.find('/') {
+                Some(pos) => (&part[..pos], part[pos + 1..].parse::<u8>().ok()?),
+                None => (part, 1),
+            };
+            if step == 0 {
+                return None;
+            }
+            let (start, end) = if range == "*" {
+                (min, max)
+            } else {
+                match range.find('-') {
+                    Some(pos) => (
+                        range[..pos].parse::<u8>().ok()?,
+                        range[pos + 1..].parse::<u8>().ok()?,
+                    ),
+                    // `5/10` means "from 5 to the end, every 10"
+                    None if step != 1 => (range.parse().ok()?, max),
+                    None => {
+                        let value = range.parse().ok()?;
+                        (value, value)
+                    }
+                }
+            };
+            if start < min || end > max || start > end {
+                return None;
+            }
+            let mut value = start;
+            while value <= end {
+                bits |= 1 << value;
+                // A step past the end of `u8` is also past `max`.
+                value = match value.checked_add(step) {
+                    Some(next) => next,
+                    None => break,
+                };
+            }
+        }
+        Some(Self(bits))
+    }
+    fn contains(&self, value: u32) -> bool {
+        self.0 & (1 << value) != 0
+    }
+    /// The first value in this field greater than or equal to `from`.
+    fn first_from(&self, from: u32) -> Option<u32> {
+        (from..64).find(|value| self.contains(*value))
+    }
+}
+
+/// A parsed cron expression.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CronSchedule {
+    minutes: Field,
+    hours: Field,
+    days_of_month: Field,
+    months: Field,
+    days_of_week: Field,
+    day_of_month_restricted: bool,
+    day_of_week_restricted: bool,
+}
+impl CronSchedule {
+    /// Parses a 5-field cron expression, returning `None` if it's malformed.
+    pub fn parse(expression: &str) -> Option<Self> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return None;
+        }
+        let mut days_of_week = Field::parse(fields[4], 0, 7)?;
+        // 7 is also Sunday
+        if days_of_week.contains(7) {
+            days_of_week.0 |= 1;
+        }
+        Some(Self {
+            minutes: Field::parse(fields[0], 0, 59)?,
+            hours: Field::parse(fields[1], 0, 23)?,
+            days_of_month: Field::parse(fields[2], 1, 31)?,
+            months: Field::parse(fields[3], 1, 12)?,
+            days_of_week,
+            // `*/2` still covers the whole range, it just skips some of it.
+            day_of_month_restricted: !fields[2].starts_with('*'),
+            day_of_week_restricted: !fields[4].starts_with('*'),
+        })
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        if !self.months.contains(date.month()) {
+            return false;
+        }
+        let day_of_month = self.days_of_month.contains(date.day());
+        let day_of_week = self
+            .days_of_week
+            .contains(date.weekday().num_days_from_sunday());
+        match (self.day_of_month_restricted, self.day_of_week_restricted) {
+            (true, true) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        }
+    }
+    /// The first time on `date` at or after `from` which matches the hour and minute fields.
+    fn first_time_from(&self, from: NaiveTime) -> Option<NaiveTime> {
+        let mut hour = self.hours.first_from(from.hour())?;
+        let mut minute = if hour == from.hour() {
+            self.minutes.first_from(from.minute())
+        } else {
+            self.minutes.first_from(0)
+        };
+        if minute.is_none() {
+            hour = self.hours.first_from(hour + 1)?;
+            minute = self.minutes.first_from(0);
+        }
+        NaiveTime::from_hms_opt(hour, minute?, 0)
+    }
+
+    /// Gets the first matching minute at or after `date_time`.
+    ///
+    /// Returns `None` if nothing matches within the next eight years,
+    /// which is the case for impossible dates such as `0 0 31 2 *`.
+    pub fn next_from(&self, date_time: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut start = date_time
+            .date()
+            .and_hms_opt(date_time.hour(), date_time.minute(), 0)?;
+        if start < date_time {
+            start += chrono::Duration::minutes(1);
+        }
+
+        let mut date = start.date();
+        let mut from = start.time();
+        for _ in 0..MAX_DAYS_AHEAD {
+            if self.matches_day(date) {
+                if let Some(time) = self.first_time_from(from) {
+                    return Some(date.and_time(time));
+                }
+            }
+            date = date.succ_opt()?;
+            from = NaiveTime::from_hms_opt(0, 0, 0)?;
+        }
+        None
+    }
+}
+
+/// A scheduler firing every time the cron expression matches.
+#[derive(Debug)]
+pub struct CronScheduler {
+    schedule: CronSchedule,
+    description: String,
+    command: ClonableCommand,
+    priority: i32,
+}
+impl CronScheduler {
+    pub fn new(schedule: CronSchedule, description: String, command: ClonableCommand) -> Self {
+        Self {
+            schedule,
+            description,
+            command,
+            priority: 0,
+        }
+    }
+    /// See [`Scheduler::priority()`].
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+    pub fn schedule(&self) -> &CronSchedule {
+        &self.schedule
+    }
+}
+impl Scheduler for CronScheduler {
+    fn advance(&mut self) -> Keep {
+        Keep::Keep
+    }
+    fn get_next(&self, now: &mut LazyNow) -> Next {
+        match self.schedule.next_from(now.now()) {
+            Some(date_time) => Next::At(
+                date_time,
+                ClonableCommand::clone(&self.command).into_inner(),
+            ),
+            None => Next::Unknown,
+        }
+    }
+    fn description(&self) -> &str {
+        self.description.as_str()
+    }
+    fn kind(&self) -> &str {
+        "Cron"
+    }
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+    fn next(expression: &str, now: &str) -> Option<NaiveDateTime> {
+        CronSchedule::parse(expression).unwrap().next_from(at(now))
+    }
+
+    #[test]
+    fn weekdays_at_seven() {
+        let weekdays = "0 7 * * 1-5";
+        // Wednesday before, at and after 07:00
+        assert_eq!(
+            next(weekdays, "2021-06-02 06:59:59"),
+            Some(at("2021-06-02 07:00:00"))
+        );
+        assert_eq!(
+            next(weekdays, "2021-06-02 07:00:00"),
+            Some(at("2021-06-02 07:00:00"))
+        );
+        assert_eq!(
+            next(weekdays, "2021-06-02 07:00:01"),
+            Some(at("2021-06-03 07:00:00"))
+        );
+        // Friday evening and Saturday skip to Monday
+        assert_eq!(
+            next(weekdays, "2021-06-04 20:00:00"),
+            Some(at("2021-06-07 07:00:00"))
+        );
+        assert_eq!(
+            next(weekdays, "2021-06-05 06:00:00"),
+            Some(at("2021-06-07 07:00:00"))
+        );
+        // Over the end of the year
+        assert_eq!(
+            next(weekdays, "2021-12-31 08:00:00"),
+            Some(at("2022-01-03 07:00:00"))
+        );
+    }
+
+    #[test]
+    fn scheduler_gets_next() {
+        let scheduler = CronScheduler::new(
+            CronSchedule::parse("0 7 * * 1-5").unwrap(),
+            "weekdays".to_string(),
+            ClonableCommand::new(crate::Command::Set(crate::Strength::full())).unwrap(),
+        );
+        match scheduler.get_next(&mut LazyNow::at(at("2021-06-05 12:00:00"))) {
+            Next::At(date_time, _) => assert_eq!(date_time, at("2021-06-07 07:00:00")),
+            Next::Unknown => panic!("expected a next occurrence"),
+        }
+    }
+
+    #[test]
+    fn steps_and_lists() {
+        assert_eq!(
+            next("*/15 * * * *", "2021-06-02 10:16:00"),
+            Some(at("2021-06-02 10:30:00"))
+        );
+        assert_eq!(
+            next("0-30/10 8 * * *", "2021-06-02 08:31:00"),
+            Some(at("2021-06-03 08:00:00"))
+        );
+        assert_eq!(
+            next("5/20 * * * *", "2021-06-02 10:46:00"),
+            Some(at("2021-06-02 11:05:00"))
+        );
+        assert_eq!(
+            next("0 9,12,18 * * *", "2021-06-02 12:01:00"),
+            Some(at("2021-06-02 18:00:00"))
+        );
+        assert_eq!(
+            next("30 9 * * 0,6", "2021-06-02 12:00:00"),
+            Some(at("2021-06-05 09:30:00"))
+        );
+        // 7 is Sunday too
+        assert_eq!(
+            next("0 9 * * 7", "2021-06-02 12:00:00"),
+            Some(at("2021-06-06 09:00:00"))
+        );
+    }
+
+    #[test]
+    fn stepped_day_of_week_is_unrestricted() {
+        // The 1st, if it's a Sunday, Tuesday, Thursday or Saturday. Both fields have to match;
+        // September to December 2021 start on odd days of the week.
+        assert_eq!(
+            next("0 0 1 * */2", "2021-08-02 00:00:00"),
+            Some(at("2022-01-01 00:00:00"))
+        );
+        // Restricted in both, either matches.
+        assert_eq!(
+            next("0 0 1 * 1", "2021-06-02 00:00:00"),
+            Some(at("2021-06-07 00:00:00"))
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        assert!(CronSchedule::parse("1/255 * * * *").is_some());
+        assert_eq!(
+            next("1/255 * * * *", "2021-06-02 10:02:00"),
+            Some(at("2021-06-02 11:01:00"))
+        );
+        // Months away
+        assert_eq!(
+            next("0 0 29 2 *", "2021-03-01 00:00:00"),
+            Some(at("2024-02-29 00:00:00"))
+        );
+        assert_eq!(next("0 0 31 2 *", "2021-03-01 00:00:00"), None);
+        assert_eq!(
+            next("59 23 31 12 *", "2021-01-01 00:00:00"),
+            Some(at("2021-12-31 23:59:00"))
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        for expression in [
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert_eq!(CronSchedule::parse(expression), None, "{}", expression);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Curves for [`TransitionInterpolation::Custom`], registered by name at runtime.
+//!
+//! A registered name is parsed like the built-in interpolations, e.g. in the config
+//! or the `interpolation` of a transition sent to the web API.
+
+use crate::TransitionInterpolation;
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// The names of the built-in interpolations, which can't be registered.
+/// See [`TransitionInterpolation::as_str()`].
+pub const BUILT_IN_NAMES: [&str; 5] = ["linear", "sine", "linear-extra", "sine-extra", "constant"];
+
+/// Maps how far a transition has come in time, from `0.0` to `1.0`,
+/// to how far it is between [`Transition::from`](crate::Transition::from) and
+/// [`Transition::to`](crate::Transition::to).
+///
+/// It should return `0.0` at the start and `1.0` at the end.
+/// Strengths outside `[0, 1]` are clamped, so curves can overshoot.
+pub trait Easing: fmt::Debug + Send + Sync {
+    fn apply(&self, t: f64) -> f64;
+    /// What it's registered and serialized as. See [`register()`].
+    fn name(&self) -> &str;
+}
+
+/// The curve of [`TransitionInterpolation::Linear`].
+#[derive(Debug, Clone, Copy)]
+pub struct Linear;
+impl Easing for Linear {
+    fn apply(&self, t: f64) -> f64 {
+        t
+    }
+    fn name(&self) -> &str {
+        "linear"
+    }
+}
+/// The curve of [`TransitionInterpolation::Sine`]; slow at the ends and fast in the middle.
+#[derive(Debug, Clone, Copy)]
+pub struct Sine;
+impl Easing for Sine {
+    fn apply(&self, t: f64) -> f64 {
+        const HALF_PI: f64 = core::f64::consts::PI / 2.0;
+        const PI: f64 = core::f64::consts::PI;
+        ((t * PI - HALF_PI).sin() + 1.0) / 2.0
+    }
+    fn name(&self) -> &str {
+        "sine"
+    }
+}
+
+static REGISTRY: Mutex<Vec<Arc<dyn Easing>>> = Mutex::new(Vec::new());
+
+/// Registers `easing` by its [name](Easing::name), so [`TransitionInterpolation::from_str()`]
+/// and deserializing accept it. Replaces any registered with the same name.
+///
+/// Returns `false`, registering nothing, if the name is one of the [`BUILT_IN_NAMES`].
+pub fn register(easing: impl Easing + 'static) -> bool {
+    if BUILT_IN_NAMES.contains(&easing.name()) {
+        return false;
+    }
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.retain(|registered| registered.name() != easing.name());
+    registry.push(Arc::new(easing));
+    true
+}
+/// Removes the easing registered as `name`. Transitions already using it keep it.
+pub fn unregister(name: &str) -> bool {
+    let mut registry = REGISTRY.lock().unwrap();
+    let len = registry.len();
+    registry.retain(|registered| registered.name() != name);
+    registry.len() != len
+}
+/// The easing registered as `name`, if any.
+pub fn get(name: &str) -> Option<CustomEasing> {
+    REGISTRY
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|registered| registered.name() == name)
+        .map(|easing| CustomEasing(Arc::clone(easing)))
+}
+
+/// An [`Easing`] used by [`TransitionInterpolation::Custom`].
+/// Compared by name, as only one can be registered per name.
+#[derive(Clone)]
+pub struct CustomEasing(Arc<dyn Easing>);
+impl CustomEasing {
+    /// Uses `easing` without [registering](register()) it.
+    /// Transitions using it can then be serialized, but not deserialized.
+    pub fn new(easing: impl Easing + 'static) -> Self {
+        Self(Arc::new(easing))
+    }
+    pub fn apply(&self, t: f64) -> f64 {
+        self.0.apply(t)
+    }
+    pub fn name(&self) -> &str {
+        self.0.name()
+    }
+}
+impl fmt::Debug for CustomEasing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+impl PartialEq for CustomEasing {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
+impl PartialOrd for CustomEasing {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.name().partial_cmp(other.name())
+    }
+}
+impl From<CustomEasing> for TransitionInterpolation {
+    fn from(easing: CustomEasing) -> Self {
+        Self::Custom(easing)
+    }
+}
@@ -1,25 +1,67 @@
+pub mod cron;
+pub mod easing;
+pub mod mqtt;
+pub mod output;
+pub mod pca9685;
+pub mod random;
 pub mod scheduler;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod sun;
 
 use chrono::prelude::*;
+pub use cron::CronScheduler;
 use rppal::{gpio::OutputPin, pwm::Pwm};
 pub use scheduler::{Next, Scheduler, WeekScheduler};
-use std::collections::HashMap;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryInto;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::{Duration, Instant};
 use std::{
     sync::{mpsc, Arc, Mutex},
     thread,
 };
+use tracing::{debug, error, info, warn};
 
+/// With the `serde` feature, this is (de)serialized as the bare number.
+/// Values outside `[0, 1]` are an error when deserializing.
 #[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
 pub struct Strength(f64);
 impl Strength {
+    /// # Panics
+    ///
+    /// Panics if `value` isn't in `[0, 1]`.
+    /// Use [`Self::try_new()`] or [`Self::new_clamped()`] for values from the outside.
     pub fn new(value: f64) -> Self {
         assert!(value <= 1.0);
         assert!(value >= 0.0);
         Self(value)
     }
+    /// Errors if `value` isn't in `[0, 1]`, which includes NaN and the infinities.
+    pub fn try_new(value: f64) -> Result<Self, StrengthError> {
+        if value.is_nan() {
+            Err(StrengthError::NaN)
+        } else if (0.0..=1.0).contains(&value) {
+            Ok(Self(value))
+        } else {
+            Err(StrengthError::OutOfRange(value))
+        }
+    }
+    /// `0`, the output turned off.
+    pub const fn off() -> Self {
+        Self(0.0)
+    }
+    /// `1`, the output at full duty cycle.
+    pub const fn full() -> Self {
+        Self(1.0)
+    }
+    /// Moves `value` into `[0, 1]`. NaN is taken as `0`.
     pub fn new_clamped(value: f64) -> Self {
-        if value < 0.0 {
+        if value.is_nan() || value < 0.0 {
             Self(0.0)
         } else if value > 1.0 {
             Self(1.0)
@@ -35,7 +77,111 @@ impl Strength {
     }
 }
 
+/// Error from [`Strength::try_new()`].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StrengthError {
+    NaN,
+    /// Not in `[0, 1]`.
+    OutOfRange(f64),
+}
+impl fmt::Display for StrengthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NaN => f.write_str("strength is NaN"),
+            Self::OutOfRange(value) => write!(f, "strength {} out of range", value),
+        }
+    }
+}
+impl std::error::Error for StrengthError {}
+
+/// The largest multiplier accepted for [`TransitionInterpolation::LinearToAndBack`] and
+/// [`TransitionInterpolation::SineToAndBack`].
+pub const TO_AND_BACK_MULTIPLIER_MAX: f64 = 100.0;
+/// The smallest multiplier accepted for the to-and-back interpolations.
+///
+/// It's the inverse of [`TO_AND_BACK_MULTIPLIER_MAX`], since [`Transition::reversed()`]
+/// inverts the multiplier and has to give a valid transition too.
+pub const TO_AND_BACK_MULTIPLIER_MIN: f64 = 1.0 / TO_AND_BACK_MULTIPLIER_MAX;
+
+/// If `multiplier` is in `[TO_AND_BACK_MULTIPLIER_MIN, TO_AND_BACK_MULTIPLIER_MAX]`. NaN isn't.
+fn valid_multiplier(multiplier: f64) -> bool {
+    (TO_AND_BACK_MULTIPLIER_MIN..=TO_AND_BACK_MULTIPLIER_MAX).contains(&multiplier)
+}
+
+/// Error from [`TransitionInterpolation::from_str()`].
+#[derive(Debug, PartialEq, Clone)]
+pub enum InterpolationParseError {
+    /// The name isn't one of the interpolations.
+    UnknownName(String),
+    /// The interpolation takes another number of extras.
+    WrongExtrasCount { expected: usize, got: usize },
+    /// The multiplier isn't a number.
+    UnparseableMultiplier(String),
+    /// The multiplier isn't in `[TO_AND_BACK_MULTIPLIER_MIN, TO_AND_BACK_MULTIPLIER_MAX]`.
+    MultiplierOutOfRange(f64),
+}
+impl fmt::Display for InterpolationParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownName(name) => write!(f, "unknown interpolation {:?}", name),
+            Self::WrongExtrasCount { expected, got } => {
+                write!(f, "expected {} extras, got {}", expected, got)
+            }
+            Self::UnparseableMultiplier(extra) => {
+                write!(f, "multiplier {:?} isn't a number", extra)
+            }
+            Self::MultiplierOutOfRange(multiplier) => write!(
+                f,
+                "multiplier {} out of range [{}, {}]",
+                multiplier, TO_AND_BACK_MULTIPLIER_MIN, TO_AND_BACK_MULTIPLIER_MAX
+            ),
+        }
+    }
+}
+impl std::error::Error for InterpolationParseError {}
+
+/// Error from [`Transition::new()`].
+#[derive(Debug, PartialEq, Clone)]
+pub enum TransitionError {
+    /// [`Transition::time`] is zero, and the interpolation isn't [`TransitionInterpolation::Constant`].
+    ZeroTime,
+    /// The interpolation is invalid, such as a multiplier out of range or NaN.
+    Interpolation(InterpolationParseError),
+}
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ZeroTime => f.write_str("transition time is zero"),
+            Self::Interpolation(err) => err.fmt(f),
+        }
+    }
+}
+impl std::error::Error for TransitionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Interpolation(err) => Some(err),
+            Self::ZeroTime => None,
+        }
+    }
+}
+impl From<InterpolationParseError> for TransitionError {
+    fn from(err: InterpolationParseError) -> Self {
+        Self::Interpolation(err)
+    }
+}
+
+/// With the `serde` feature, this is (de)serialized as `{ "name": "linear-extra", "multiplier": 0.5 }`,
+/// with the name from [`TransitionInterpolation::as_str()`].
+/// The multiplier is left out for interpolations which don't take one.
 #[derive(Debug, PartialEq, PartialOrd, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(
+        into = "serialize::InterpolationRepr",
+        try_from = "serialize::InterpolationRepr"
+    )
+)]
 pub enum TransitionInterpolation {
     Linear,
     Sine,
@@ -44,32 +190,91 @@ pub enum TransitionInterpolation {
     LinearToAndBack(f64),
     /// Same as above, but with sine interpolation
     SineToAndBack(f64),
+    /// Jumps straight to [`Transition::to`] and holds it; [`Transition::time`] is ignored.
+    /// Useful for schedulers which should just set a fixed level.
+    Constant,
+    /// A curve registered at runtime, parsed by its name. See [`easing::register()`].
+    Custom(easing::CustomEasing),
 }
 impl TransitionInterpolation {
-    pub fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &str {
         match self {
             Self::Linear => "linear",
             Self::Sine => "sine",
             Self::LinearToAndBack(_) => "linear-extra",
             Self::SineToAndBack(_) => "sine-extra",
+            Self::Constant => "constant",
+            Self::Custom(easing) => easing.name(),
         }
     }
-    pub fn from_str<S: AsRef<str>>(string: &str, extras: &[S]) -> Option<Self> {
-        Some(match string.as_ref() {
-            "linear" => Self::Linear,
-            "sine" => Self::Sine,
-            "linear-extra" if extras.len() == 1 => {
-                Self::LinearToAndBack(extras[0].as_ref().parse().ok()?)
+    pub fn from_str<S: AsRef<str>>(
+        string: &str,
+        extras: &[S],
+    ) -> Result<Self, InterpolationParseError> {
+        fn multiplier<S: AsRef<str>>(extras: &[S]) -> Result<f64, InterpolationParseError> {
+            if extras.len() != 1 {
+                return Err(InterpolationParseError::WrongExtrasCount {
+                    expected: 1,
+                    got: extras.len(),
+                });
             }
-            "sine-extra" if extras.len() == 1 => {
-                Self::SineToAndBack(extras[0].as_ref().parse().ok()?)
+            let extra = extras[0].as_ref();
+            let multiplier: f64 = extra
+                .parse()
+                .map_err(|_| InterpolationParseError::UnparseableMultiplier(extra.to_string()))?;
+            if valid_multiplier(multiplier) {
+                Ok(multiplier)
+            } else {
+                Err(InterpolationParseError::MultiplierOutOfRange(multiplier))
             }
-            _ => return None,
+        }
+        Ok(match string {
+            "linear" => Self::Linear,
+            "sine" => Self::Sine,
+            "constant" => Self::Constant,
+            "linear-extra" => Self::LinearToAndBack(multiplier(extras)?),
+            "sine-extra" => Self::SineToAndBack(multiplier(extras)?),
+            _ => match easing::get(string) {
+                Some(easing) => Self::Custom(easing),
+                None => return Err(InterpolationParseError::UnknownName(string.to_string())),
+            },
+        })
+    }
+    /// Same as [`Self::from_str()`], but with the multiplier as a number.
+    /// It's ignored by interpolations which don't take one.
+    pub fn from_name(name: &str, multiplier: Option<f64>) -> Result<Self, InterpolationParseError> {
+        let multiplier = || match multiplier {
+            Some(multiplier) if valid_multiplier(multiplier) => Ok(multiplier),
+            Some(multiplier) => Err(InterpolationParseError::MultiplierOutOfRange(multiplier)),
+            None => Err(InterpolationParseError::WrongExtrasCount {
+                expected: 1,
+                got: 0,
+            }),
+        };
+        Ok(match name {
+            "linear" => Self::Linear,
+            "sine" => Self::Sine,
+            "constant" => Self::Constant,
+            "linear-extra" => Self::LinearToAndBack(multiplier()?),
+            "sine-extra" => Self::SineToAndBack(multiplier()?),
+            _ => match easing::get(name) {
+                Some(easing) => Self::Custom(easing),
+                None => return Err(InterpolationParseError::UnknownName(name.to_string())),
+            },
         })
     }
+    /// The multiplier of [`Self::LinearToAndBack`] and [`Self::SineToAndBack`].
+    pub fn multiplier(&self) -> Option<f64> {
+        match self {
+            Self::Linear | Self::Sine | Self::Constant | Self::Custom(_) => None,
+            Self::LinearToAndBack(multiplier) | Self::SineToAndBack(multiplier) => {
+                Some(*multiplier)
+            }
+        }
+    }
     pub fn apply_extras(&self, extras: &mut Vec<String>) {
         match self {
-            Self::Linear | Self::Sine => {}
+            Self::Linear | Self::Sine | Self::Constant | Self::Custom(_) => {}
             Self::LinearToAndBack(extra) | Self::SineToAndBack(extra) => {
                 extras.push(extra.to_string())
             }
@@ -77,46 +282,775 @@ impl TransitionInterpolation {
     }
 }
 
+/// With the `serde` feature, [`Transition::time`] is (de)serialized as seconds.
+/// The format of [`TransitionInterpolation::from_str()`], with the name and a list of `extras`,
+/// is also accepted when deserializing. Deserializing goes through [`Transition::new()`].
 #[derive(Debug, PartialEq, PartialOrd, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(try_from = "serialize::TransitionRepr")
+)]
 pub struct Transition {
     pub from: Strength,
     pub to: Strength,
+    #[cfg_attr(feature = "serde", serde(with = "serialize::duration_secs"))]
     pub time: Duration,
     pub interpolation: TransitionInterpolation,
 }
 impl Default for Transition {
     fn default() -> Self {
         Self {
-            from: Strength::new(0.0),
-            to: Strength::new(1.0),
+            from: Strength::off(),
+            to: Strength::full(),
             time: Duration::from_secs(15 * 60),
             interpolation: TransitionInterpolation::SineToAndBack(0.5),
         }
     }
 }
+impl Transition {
+    /// Errors if `time` is zero, which only [`TransitionInterpolation::Constant`] allows,
+    /// or if the multiplier of a to-and-back interpolation isn't in
+    /// `[TO_AND_BACK_MULTIPLIER_MIN, TO_AND_BACK_MULTIPLIER_MAX]`.
+    ///
+    /// A transition ending where it starts is allowed, but logs a warning, as it does nothing.
+    pub fn new(
+        from: Strength,
+        to: Strength,
+        time: Duration,
+        interpolation: TransitionInterpolation,
+    ) -> Result<Self, TransitionError> {
+        if time == Duration::new(0, 0) && interpolation != TransitionInterpolation::Constant {
+            return Err(TransitionError::ZeroTime);
+        }
+        if let Some(multiplier) = interpolation.multiplier() {
+            if !valid_multiplier(multiplier) {
+                return Err(InterpolationParseError::MultiplierOutOfRange(multiplier).into());
+            }
+        }
+        if from == to {
+            warn!(
+                "Transition from {:?} to the same strength does nothing",
+                from
+            );
+        }
+        Ok(Self {
+            from,
+            to,
+            time,
+            interpolation,
+        })
+    }
+    /// The transition which plays this one backwards in time.
+    ///
+    /// Linear and sine curves are symmetric, so only [`Self::from`] and [`Self::to`] are swapped.
+    /// Custom curves are assumed to be symmetric too.
+    /// A to-and-back transition already ends where it started; the reverse keeps the endpoints,
+    /// but spends the multiplied time on the way there. [`Self::time`] is scaled so the total stays the same.
+    ///
+    /// Play it after this one with [`Command::QueueTransition`] to go there and back.
+    pub fn reversed(&self) -> Self {
+        match self.interpolation {
+            TransitionInterpolation::Linear
+            | TransitionInterpolation::Sine
+            | TransitionInterpolation::Constant
+            | TransitionInterpolation::Custom(_) => Self {
+                from: self.to,
+                to: self.from,
+                time: self.time,
+                interpolation: self.interpolation.clone(),
+            },
+            TransitionInterpolation::LinearToAndBack(multiplier)
+            | TransitionInterpolation::SineToAndBack(multiplier) => {
+                let interpolation = match self.interpolation {
+                    TransitionInterpolation::LinearToAndBack(_) => {
+                        TransitionInterpolation::LinearToAndBack(1.0 / multiplier)
+                    }
+                    _ => TransitionInterpolation::SineToAndBack(1.0 / multiplier),
+                };
+                Self {
+                    from: self.from,
+                    to: self.to,
+                    time: self.time.mul_f64(multiplier),
+                    interpolation,
+                }
+            }
+        }
+    }
+    /// How long the transition runs, including the way back of the to-and-back interpolations,
+    /// which takes [`Self::time`] times the multiplier.
+    /// [`TransitionInterpolation::Constant`] is applied at once, so it takes no time.
+    pub fn total_duration(&self) -> Duration {
+        match self.interpolation {
+            TransitionInterpolation::Constant => Duration::new(0, 0),
+            TransitionInterpolation::Linear
+            | TransitionInterpolation::Sine
+            | TransitionInterpolation::Custom(_) => self.time,
+            TransitionInterpolation::LinearToAndBack(multiplier)
+            | TransitionInterpolation::SineToAndBack(multiplier) => {
+                self.time.mul_f64(multiplier + 1.0)
+            }
+        }
+    }
+    /// How much of the [total duration](Self::total_duration()) has passed `elapsed` into the
+    /// transition, from `0.0` to `1.0`.
+    ///
+    /// The to-and-back interpolations peak at `1 / (1 + multiplier)`,
+    /// halfway with a multiplier of `1`.
+    /// [`TransitionInterpolation::Constant`] is done at once, so it's always `1.0`.
+    pub fn progress_at(&self, elapsed: Duration) -> f64 {
+        let total = self.total_duration();
+        if total == Duration::new(0, 0) {
+            return 1.0;
+        }
+        (elapsed.as_secs_f64() / total.as_secs_f64()).clamp(0.0, 1.0)
+    }
+    /// The strength `elapsed` into the transition.
+    ///
+    /// Before the start, it's [`Self::from`]. After the end, it's where the transition ends.
+    pub fn strength_at(&self, elapsed: Duration) -> Strength {
+        let progress = if self.time > Duration::new(0, 0) {
+            elapsed.as_secs_f64() / self.time.as_secs_f64()
+        } else {
+            f64::INFINITY
+        };
+        self.strength_at_progress(progress)
+    }
+    /// Same as [`Self::strength_at()`], but with the elapsed time in multiples of [`Self::time`].
+    pub(crate) fn strength_at_progress(&self, progress: f64) -> Strength {
+        use easing::Easing;
+
+        /// `0` at the start, `1` at the peak and `0` again at `multiplier + 1`, never outside.
+        fn to_and_back(progress: f64, multiplier: f64) -> f64 {
+            // Only possible by building the interpolation directly, not through `Transition::new`,
+            // and would make `clamp` panic. It then never fades back.
+            if !(multiplier > 0.0 && multiplier.is_finite()) {
+                return progress.clamp(0.0, 1.0);
+            }
+            let progress = progress.clamp(0.0, multiplier + 1.0);
+            if progress > 1.0 {
+                // Counted from the end, so it's exactly `0` there, without rounding errors.
+                ((multiplier + 1.0 - progress) / multiplier).clamp(0.0, 1.0)
+            } else {
+                progress
+            }
+        }
+
+        let zero_to_one = match &self.interpolation {
+            TransitionInterpolation::Linear => easing::Linear.apply(progress.clamp(0.0, 1.0)),
+            TransitionInterpolation::Sine => easing::Sine.apply(progress.clamp(0.0, 1.0)),
+            TransitionInterpolation::LinearToAndBack(multiplier) => {
+                easing::Linear.apply(to_and_back(progress, *multiplier))
+            }
+            TransitionInterpolation::SineToAndBack(multiplier) => {
+                easing::Sine.apply(to_and_back(progress, *multiplier))
+            }
+            TransitionInterpolation::Constant => 1.0,
+            TransitionInterpolation::Custom(easing) => easing.apply(progress.clamp(0.0, 1.0)),
+        };
+        // The ends are exact, so e.g. a to-and-back transition returns to exactly where it started.
+        if zero_to_one == 0.0 {
+            self.from
+        } else if zero_to_one == 1.0 {
+            self.to
+        } else {
+            Strength::new_clamped(zero_to_one * (self.to.0 - self.from.0) + self.from.0)
+        }
+    }
+}
+
+/// Lower bound of the factor in [`Command::SetTransitionSpeed`].
+pub const TRANSITION_SPEED_MIN: f64 = 0.01;
+/// Upper bound of the factor in [`Command::SetTransitionSpeed`].
+pub const TRANSITION_SPEED_MAX: f64 = 100.0;
 
 #[derive(Debug)]
 pub enum Command {
     Set(Strength),
     SetTransition(Transition),
+    /// Same as [`Command::SetTransition`], but the sender is notified when this specific transition ends.
+    /// See [`TransitionOutcome`] and [`ControllerChannel::send_transition()`].
+    SetTransitionWithCallback(Transition, mpsc::Sender<TransitionOutcome>),
+    /// Same as [`Command::SetTransition`], but the transition is timed from `start` instead of when
+    /// the command is handled. Controllers given the same `start` play it in step.
+    /// A `start` in the future holds [`Transition::from`] until then.
+    /// See [`ControllerGroup::broadcast_transition()`].
+    SetTransitionAt(Transition, Instant),
+    /// Plays the transition after the current one and any queued before it,
+    /// starting from where the previous one ended. Starts it directly if none is running.
+    /// [`Command::Set`] and the other transition commands clear the queue.
+    QueueTransition(Transition),
+    /// Like [`Command::SetTransition`], but plays the transition several times.
+    /// Each time starts over from [`Transition::from`]; it then holds at [`Transition::to`].
+    SetRepeatingTransition(Transition, Repeat),
+    /// Changes the speed of the current transition by the factor, keeping the current progress.
+    /// `2.0` finishes the rest of it twice as fast.
+    /// The factor is clamped to [`TRANSITION_SPEED_MIN`]..=[`TRANSITION_SPEED_MAX`].
+    SetTransitionSpeed(f64),
+    /// Sets how often the output is updated during transitions.
+    /// Defaults to [`scheduler::DEFAULT_UPDATE_INTERVAL`].
+    SetUpdateInterval(Duration),
+    /// While enabled, strengths are logged instead of set on the output.
+    /// Useful to check the timing of a new schedule. Disabling it sets the output to the current strength.
+    SetDryRun(bool),
+    /// Sets a named layer, which is blended with the strength from the other commands
+    /// according to [`BlendMode`]. The layer is added if it doesn't exist.
+    SetLayer(String, Strength),
+    /// Removes a layer added by [`Command::SetLayer`].
+    RemoveLayer(String),
+    /// Changes how the layers are combined. Defaults to [`BlendMode::Max`].
+    SetBlendMode(BlendMode),
+    /// Saves `scene` under the name, replacing any scene with the same name.
+    SaveScene(String, Scene),
+    /// Fades from the current strength to the named [`Scene`], as [`Command::SetTransition`] does.
+    /// Unknown names are logged and ignored.
+    RecallScene(String),
+    /// Same as [`Command::RecallScene`], but sends back whether the scene existed.
+    /// See [`ControllerChannel::send_recall_scene()`].
+    RecallSceneWithCallback(String, mpsc::Sender<bool>),
+    RemoveScene(String),
+    /// Same as [`Command::RemoveScene`], but sends back whether the scene existed.
+    /// See [`ControllerChannel::send_remove_scene()`].
+    RemoveSceneWithCallback(String, mpsc::Sender<bool>),
     ChangeDayTimer(Weekday, Option<NaiveTime>),
+    /// Changes the time of only the days in the map, leaving the rest untouched.
+    /// The days which actually changed are sent back.
+    /// See [`ControllerChannel::send_schedule_diff()`].
+    ScheduleDiff(
+        HashMap<Weekday, Option<NaiveTime>>,
+        mpsc::Sender<Vec<Weekday>>,
+    ),
     ChangeDayTimerTransition(Transition),
+    /// Sets the transition of a single day in the [`WeekScheduler`].
+    /// `None` makes the day use the default from [`Command::ChangeDayTimerTransition`].
+    ChangeDayTransition(Weekday, Option<Transition>),
     AddReplaceScheduler(String, Box<dyn Scheduler>),
     RemoveScheduler(String),
+    /// Same as [`Command::RemoveScheduler`], but sends back whether the scheduler existed.
+    /// See [`ControllerChannel::send_remove_scheduler()`].
+    RemoveSchedulerWithCallback(String, mpsc::Sender<bool>),
+    /// Disabled schedulers stay in the list, but are skipped when looking for the next one to run.
+    /// Unknown names are ignored.
+    SetSchedulerEnabled(String, bool),
+    /// Same as [`Command::SetSchedulerEnabled`], but sends back whether the scheduler exists.
+    /// See [`ControllerChannel::send_set_scheduler_enabled()`].
+    SetSchedulerEnabledWithCallback(String, bool, mpsc::Sender<bool>),
+    /// Sends back the named schedulers, soonest first.
+    /// See [`ControllerChannel::send_list_schedulers()`].
+    ListSchedulers(mpsc::Sender<Vec<scheduler::SchedulerInfo>>),
+    /// Sends back a [`Status`]. See [`ControllerChannel::send_get_status()`].
+    GetStatus(mpsc::Sender<Status>),
+    /// Sends back the transition the [`WeekScheduler`] runs today, by the controller's clock.
+    /// See [`ControllerChannel::send_get_day_transition()`].
+    GetDayTransition(mpsc::Sender<Transition>),
+    /// Sends back the most recently set strengths, oldest first,
+    /// with how long ago they were set. See [`ControllerChannel::send_get_history()`].
+    GetHistory(mpsc::Sender<Vec<(Duration, Strength)>>),
+    /// Freezes the ongoing transition and holds off all schedulers until [`Command::Resume`].
+    /// Schedulers which became due in the meantime run when resuming.
+    /// Commands such as [`Command::Set`] still apply.
+    Pause,
+    Resume,
+    /// Pauses or resumes, and sends back whether it changed anything.
+    /// See [`ControllerChannel::send_pause()`].
+    SetPausedWithCallback(bool, mpsc::Sender<bool>),
+    /// Sets the color of outputs with several channels. See [`output::RgbOutput`].
+    /// Ongoing transitions continue; they control the brightness.
+    SetRgb(Strength, Strength, Strength),
+    /// Moves the [`scheduler::Bedtime`], and with it every [`scheduler::BedtimeScheduler`].
+    SetBedtime(NaiveTime),
     ClearAllSchedulers,
+    /// Replaces the [`WeekScheduler`] and all schedulers at once, disabling the named ones.
+    /// Nothing of the old schedule is kept. Ongoing transitions continue.
+    ReplaceConfig(
+        Box<WeekScheduler>,
+        HashMap<String, Box<dyn Scheduler>>,
+        HashSet<String>,
+    ),
+    /// Skips the next occurrence of the [`WeekScheduler`], once. The ones after run as usual.
+    /// The skip is lost on restart.
+    SkipNext,
+    /// Jitters the strength randomly within `amplitude` of `base`, a new value every `rate`,
+    /// like a candle. Runs until [`Command::StopEffect`], another strength or transition is set,
+    /// or a scheduler runs. `rate` is at least [`scheduler::EFFECT_INTERVAL_MIN`].
+    StartFlicker {
+        base: Strength,
+        amplitude: f64,
+        rate: Duration,
+    },
+    /// Alternates between `high` and `low` every half `period`, starting with `high`.
+    /// Stops like [`Command::StartFlicker`]. `period` is at least [`scheduler::STROBE_PERIOD_MIN`].
+    StartStrobe {
+        low: Strength,
+        high: Strength,
+        period: Duration,
+    },
+    /// Fades smoothly from `low` to `high` and back every `period`, over and over, starting at `low`.
+    /// Stops like [`Command::StartFlicker`]. `period` is at least [`scheduler::STROBE_PERIOD_MIN`].
+    StartBreathing {
+        low: Strength,
+        high: Strength,
+        period: Duration,
+    },
+    /// Sets the brightness, as [`Command::Set`] does, and the warmth of the output, from `0` (cool)
+    /// to `1` (warm). The warmth is clamped to that. See [`output::ColorTempOutput`].
+    SetColorTemp {
+        brightness: Strength,
+        warmth: f64,
+    },
+    /// Plays the transition while fading the warmth from the current one to `warmth`,
+    /// both ending at the same time.
+    SetColorTempTransition {
+        transition: Transition,
+        warmth: f64,
+    },
+    /// Stops the running [`scheduler::Effect`], leaving the output where it is.
+    StopEffect,
+    /// Reverts the last [`Command::Set`], [`Command::ChangeDayTimer`] or
+    /// [`Command::ChangeDayTimerTransition`] not yet undone. Other commands can't be undone.
+    /// Does nothing if there's nothing to undo.
+    Undo,
+    /// Handles the commands in order as one, so only the output after the last one is set.
+    /// E.g. a schedule change and a transition are applied together.
+    ///
+    /// A batch containing [`Command::Batch`] or [`Command::Finish`] is ignored;
+    /// see [`Command::is_valid_batch()`].
+    Batch(Vec<Command>),
     Finish,
 }
 impl Command {
+    /// If the command changes the schedule or the schedulers, which should be persisted.
+    /// See [`SharedState::config_version()`].
+    pub fn changes_config(&self) -> bool {
+        match self {
+            Self::ChangeDayTimer(_, _)
+            | Self::ScheduleDiff(_, _)
+            | Self::ChangeDayTimerTransition(_)
+            | Self::ChangeDayTransition(_, _)
+            | Self::AddReplaceScheduler(_, _)
+            | Self::RemoveScheduler(_)
+            | Self::RemoveSchedulerWithCallback(_, _)
+            | Self::SetSchedulerEnabled(_, _)
+            | Self::SetSchedulerEnabledWithCallback(_, _, _)
+            | Self::SetBedtime(_)
+            | Self::SaveScene(_, _)
+            | Self::RemoveScene(_)
+            | Self::RemoveSceneWithCallback(_, _)
+            | Self::ClearAllSchedulers
+            | Self::ReplaceConfig(_, _, _) => true,
+            Self::Batch(commands) => commands.iter().any(Self::changes_config),
+            Self::Set(_)
+            | Self::SetTransition(_)
+            | Self::SetTransitionWithCallback(_, _)
+            | Self::SetTransitionAt(_, _)
+            | Self::QueueTransition(_)
+            | Self::SetRepeatingTransition(_, _)
+            | Self::SetTransitionSpeed(_)
+            | Self::SetUpdateInterval(_)
+            | Self::SetDryRun(_)
+            | Self::SetLayer(_, _)
+            | Self::RemoveLayer(_)
+            | Self::SetBlendMode(_)
+            | Self::RecallScene(_)
+            | Self::RecallSceneWithCallback(_, _)
+            | Self::ListSchedulers(_)
+            | Self::GetStatus(_)
+            | Self::GetDayTransition(_)
+            | Self::GetHistory(_)
+            | Self::Pause
+            | Self::Resume
+            | Self::SetPausedWithCallback(_, _)
+            | Self::SetRgb(_, _, _)
+            | Self::SkipNext
+            | Self::StartFlicker { .. }
+            | Self::StartStrobe { .. }
+            | Self::StartBreathing { .. }
+            | Self::SetColorTemp { .. }
+            | Self::SetColorTempTransition { .. }
+            | Self::StopEffect
+            | Self::Undo
+            | Self::Finish => false,
+        }
+    }
     pub fn can_clone(&self) -> bool {
         match self {
             Self::Set(_)
             | Self::SetTransition(_)
+            | Self::SetTransitionAt(_, _)
+            | Self::QueueTransition(_)
+            | Self::SetRepeatingTransition(_, _)
+            | Self::SetTransitionSpeed(_)
+            | Self::SetUpdateInterval(_)
+            | Self::SetDryRun(_)
+            | Self::SetLayer(_, _)
+            | Self::RemoveLayer(_)
+            | Self::SetBlendMode(_)
+            | Self::SaveScene(_, _)
+            | Self::RecallScene(_)
+            | Self::RemoveScene(_)
             | Self::ChangeDayTimer(_, _)
             | Self::ChangeDayTimerTransition(_)
+            | Self::ChangeDayTransition(_, _)
             | Self::RemoveScheduler(_)
+            | Self::SetSchedulerEnabled(_, _)
+            | Self::SetBedtime(_)
+            | Self::SetRgb(_, _, _)
             | Self::ClearAllSchedulers
+            | Self::SkipNext
+            | Self::StartFlicker { .. }
+            | Self::StartStrobe { .. }
+            | Self::StartBreathing { .. }
+            | Self::SetColorTemp { .. }
+            | Self::SetColorTempTransition { .. }
+            | Self::StopEffect
+            | Self::Undo
+            | Self::Pause
+            | Self::Resume
             | Self::Finish => true,
-            Self::AddReplaceScheduler(_, _) => false,
+            Self::Batch(commands) => commands.iter().all(Self::can_clone),
+            Self::AddReplaceScheduler(_, _)
+            | Self::SetTransitionWithCallback(_, _)
+            | Self::ScheduleDiff(_, _)
+            | Self::RemoveSchedulerWithCallback(_, _)
+            | Self::SetSchedulerEnabledWithCallback(_, _, _)
+            | Self::RecallSceneWithCallback(_, _)
+            | Self::RemoveSceneWithCallback(_, _)
+            | Self::ListSchedulers(_)
+            | Self::GetStatus(_)
+            | Self::GetDayTransition(_)
+            | Self::GetHistory(_)
+            | Self::ReplaceConfig(_, _, _)
+            | Self::SetPausedWithCallback(_, _) => false,
+        }
+    }
+    /// If this is a [`Command::Batch`] without any nested batches or [`Command::Finish`].
+    pub fn is_valid_batch(&self) -> bool {
+        match self {
+            Self::Batch(commands) => !commands
+                .iter()
+                .any(|command| matches!(command, Self::Batch(_) | Self::Finish)),
+            _ => false,
+        }
+    }
+}
+
+/// Concise descriptions for logs, such as `Set 50%` or `ChangeDayTimer(Mon -> 07:30:00)`.
+/// Channels and schedulers aren't shown, only their presence and kind.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        struct Percent(Strength);
+        impl fmt::Display for Percent {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}%", (self.0.into_inner() * 1000.0).round() / 10.0)
+            }
+        }
+        struct Short<'a>(&'a Transition);
+        impl fmt::Display for Short<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(
+                    f,
+                    "{} -> {} over {:?}, {}",
+                    Percent(self.0.from),
+                    Percent(self.0.to),
+                    self.0.time,
+                    self.0.interpolation.as_str()
+                )
+            }
+        }
+        struct Time(Option<NaiveTime>);
+        impl fmt::Display for Time {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self.0 {
+                    Some(time) => time.fmt(f),
+                    None => f.write_str("none"),
+                }
+            }
+        }
+
+        match self {
+            Self::Set(s) => write!(f, "Set {}", Percent(*s)),
+            Self::SetTransition(t) => write!(f, "SetTransition({})", Short(t)),
+            Self::SetTransitionAt(t, _) => write!(f, "SetTransitionAt({})", Short(t)),
+            Self::SetTransitionWithCallback(t, _) => {
+                write!(f, "SetTransition({}) with callback", Short(t))
+            }
+            Self::QueueTransition(t) => write!(f, "QueueTransition({})", Short(t)),
+            Self::SetRepeatingTransition(t, repeat) => match repeat {
+                Repeat::Times(times) => {
+                    write!(f, "SetRepeatingTransition({}, {} times)", Short(t), times)
+                }
+                Repeat::Forever => write!(f, "SetRepeatingTransition({}, forever)", Short(t)),
+            },
+            Self::SetTransitionSpeed(factor) => write!(f, "SetTransitionSpeed({}x)", factor),
+            Self::SetUpdateInterval(interval) => write!(f, "SetUpdateInterval({:?})", interval),
+            Self::SetDryRun(enabled) => write!(f, "SetDryRun({})", enabled),
+            Self::SetLayer(name, strength) => {
+                write!(f, "SetLayer({}, {})", name, Percent(*strength))
+            }
+            Self::RemoveLayer(name) => write!(f, "RemoveLayer({})", name),
+            Self::SetBlendMode(mode) => write!(f, "SetBlendMode({})", mode),
+            Self::SaveScene(name, scene) => write!(
+                f,
+                "SaveScene({:?}, {} over {:?})",
+                name,
+                Percent(scene.strength),
+                scene.time
+            ),
+            Self::RecallScene(name) => write!(f, "RecallScene({:?})", name),
+            Self::RecallSceneWithCallback(name, _) => {
+                write!(f, "RecallScene({:?}) with callback", name)
+            }
+            Self::RemoveScene(name) => write!(f, "RemoveScene({:?})", name),
+            Self::RemoveSceneWithCallback(name, _) => {
+                write!(f, "RemoveScene({:?}) with callback", name)
+            }
+            Self::ChangeDayTimer(day, time) => {
+                write!(f, "ChangeDayTimer({} -> {})", day, Time(*time))
+            }
+            Self::ScheduleDiff(diff, _) => {
+                let mut days: Vec<_> = diff.iter().collect();
+                days.sort_by_key(|(day, _)| day.num_days_from_monday());
+                f.write_str("ScheduleDiff(")?;
+                for (i, (day, time)) in days.into_iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{} -> {}", day, Time(*time))?;
+                }
+                f.write_str(")")
+            }
+            Self::ChangeDayTimerTransition(t) => {
+                write!(f, "ChangeDayTimerTransition({})", Short(t))
+            }
+            Self::ChangeDayTransition(day, Some(t)) => {
+                write!(f, "ChangeDayTransition({} -> {})", day, Short(t))
+            }
+            Self::ChangeDayTransition(day, None) => {
+                write!(f, "ChangeDayTransition({} -> default)", day)
+            }
+            Self::AddReplaceScheduler(name, scheduler) => {
+                write!(f, "AddReplaceScheduler({:?}, {})", name, scheduler.kind())
+            }
+            Self::RemoveScheduler(name) => write!(f, "RemoveScheduler({:?})", name),
+            Self::RemoveSchedulerWithCallback(name, _) => {
+                write!(f, "RemoveScheduler({:?}) with callback", name)
+            }
+            Self::SetSchedulerEnabled(name, enabled) => {
+                write!(f, "SetSchedulerEnabled({:?}, {})", name, enabled)
+            }
+            Self::SetSchedulerEnabledWithCallback(name, enabled, _) => {
+                write!(
+                    f,
+                    "SetSchedulerEnabled({:?}, {}) with callback",
+                    name, enabled
+                )
+            }
+            Self::ListSchedulers(_) => f.write_str("ListSchedulers"),
+            Self::GetStatus(_) => f.write_str("GetStatus"),
+            Self::GetDayTransition(_) => f.write_str("GetDayTransition"),
+            Self::GetHistory(_) => f.write_str("GetHistory"),
+            Self::StartFlicker {
+                base,
+                amplitude,
+                rate,
+            } => write!(
+                f,
+                "StartFlicker({} ± {} every {:?})",
+                Percent(*base),
+                amplitude,
+                rate
+            ),
+            Self::StartStrobe { low, high, period } => write!(
+                f,
+                "StartStrobe({} <-> {} every {:?})",
+                Percent(*low),
+                Percent(*high),
+                period
+            ),
+            Self::StartBreathing { low, high, period } => write!(
+                f,
+                "StartBreathing({} <-> {} every {:?})",
+                Percent(*low),
+                Percent(*high),
+                period
+            ),
+            Self::SetColorTemp { brightness, warmth } => {
+                write!(
+                    f,
+                    "SetColorTemp({}, warmth {})",
+                    Percent(*brightness),
+                    warmth
+                )
+            }
+            Self::SetColorTempTransition { transition, warmth } => write!(
+                f,
+                "SetColorTempTransition({}, warmth {})",
+                Short(transition),
+                warmth
+            ),
+            Self::StopEffect => f.write_str("StopEffect"),
+            Self::Pause => f.write_str("Pause"),
+            Self::Resume => f.write_str("Resume"),
+            Self::SetPausedWithCallback(true, _) => f.write_str("Pause with callback"),
+            Self::SetPausedWithCallback(false, _) => f.write_str("Resume with callback"),
+            Self::SetRgb(red, green, blue) => write!(
+                f,
+                "SetRgb({}, {}, {})",
+                Percent(*red),
+                Percent(*green),
+                Percent(*blue)
+            ),
+            Self::SetBedtime(time) => write!(f, "SetBedtime({})", time),
+            Self::ClearAllSchedulers => f.write_str("ClearAllSchedulers"),
+            Self::ReplaceConfig(_, schedulers, disabled) => write!(
+                f,
+                "ReplaceConfig({} schedulers, {} disabled)",
+                schedulers.len(),
+                disabled.len()
+            ),
+            Self::SkipNext => f.write_str("SkipNext"),
+            Self::Undo => f.write_str("Undo"),
+            Self::Batch(commands) => {
+                f.write_str("Batch(")?;
+                for (i, command) in commands.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    command.fmt(f)?;
+                }
+                f.write_str(")")
+            }
+            Self::Finish => f.write_str("Finish"),
+        }
+    }
+}
+
+/// How many times [`Command::SetRepeatingTransition`] plays the transition.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Repeat {
+    /// Plays it this many times. `0` plays it once, same as `1`.
+    Times(u32),
+    /// Plays it until another transition or strength is set.
+    Forever,
+}
+
+/// How a transition sent with [`Command::SetTransitionWithCallback`] ended.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TransitionOutcome {
+    /// The transition ran to the end.
+    Completed,
+    /// Another command replaced the transition before it finished.
+    Cancelled,
+}
+impl TransitionOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Completed => "completed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Reacts to what a [`Controller`] does, for integrations such as logging, MQTT or metrics.
+/// All methods do nothing by default. See [`ControllerChannel::add_listener()`].
+///
+/// They're called on the controller thread, so they should return quickly.
+pub trait EventListener: fmt::Debug + Send {
+    /// A strength, blended with the layers, is about to be set on the output.
+    /// Also called during dry runs.
+    fn on_set(&self, _strength: Strength) {}
+    /// A transition started, from a command or after the one before it in the queue.
+    /// Repeats of a [`Command::SetRepeatingTransition`] don't start it again.
+    fn on_transition_start(&self, _transition: &Transition) {}
+    /// A transition ran to the end, after its last strength was set.
+    /// Cancelled transitions aren't reported.
+    fn on_transition_complete(&self, _transition: &Transition) {}
+    /// A scheduler became due. Its command is handled right after.
+    fn on_scheduler_fired(&self, _event: &scheduler::SchedulerEvent) {}
+}
+
+/// A saved look, such as "movie" or "dinner", recalled by name. See [`Command::SaveScene`].
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Scene {
+    pub strength: Strength,
+    /// How long the fade to [`Self::strength`] takes.
+    #[cfg_attr(feature = "serde", serde(with = "serialize::duration_secs"))]
+    pub time: Duration,
+    pub interpolation: TransitionInterpolation,
+}
+impl Scene {
+    /// The transition from `from` to this scene.
+    /// A zero [`Self::time`] jumps there, whatever the interpolation.
+    pub fn transition_from(&self, from: Strength) -> Transition {
+        let interpolation = match self.time == Duration::new(0, 0) {
+            true => TransitionInterpolation::Constant,
+            false => self.interpolation.clone(),
+        };
+        Transition {
+            from,
+            to: self.strength,
+            time: self.time,
+            interpolation,
+        }
+    }
+}
+
+/// How the layers of [`Command::SetLayer`] are combined into the strength set on the output.
+///
+/// The strength from [`Command::Set`], transitions, and schedulers is the base layer,
+/// named [`BlendMode::BASE_LAYER`].
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum BlendMode {
+    /// The strongest layer.
+    #[default]
+    Max,
+    /// All layers added together, clamped to [`Strength::full()`].
+    Sum,
+    /// The average of the layers, each weighted by the factor for its name.
+    /// Layers not in the map have a weight of `1`.
+    WeightedAverage(HashMap<String, f64>),
+}
+impl BlendMode {
+    pub const BASE_LAYER: &'static str = "base";
+
+    pub fn blend(&self, base: Strength, layers: &HashMap<String, Strength>) -> Strength {
+        let all = std::iter::once((Self::BASE_LAYER, base)).chain(
+            layers
+                .iter()
+                .map(|(name, strength)| (name.as_str(), *strength)),
+        );
+        match self {
+            Self::Max => all
+                .map(|(_, strength)| strength)
+                .fold(Strength::off(), |a, b| {
+                    match a.into_inner() >= b.into_inner() {
+                        true => a,
+                        false => b,
+                    }
+                }),
+            Self::Sum => {
+                Strength::new_clamped(all.map(|(_, strength)| strength.into_inner()).sum())
+            }
+            Self::WeightedAverage(weights) => {
+                let (sum, total) = all.fold((0.0, 0.0), |(sum, total), (name, strength)| {
+                    let weight = weights.get(name).copied().unwrap_or(1.0).max(0.0);
+                    (sum + strength.into_inner() * weight, total + weight)
+                });
+                match total > 0.0 {
+                    true => Strength::new_clamped(sum / total),
+                    false => Strength::off(),
+                }
+            }
+        }
+    }
+}
+impl fmt::Display for BlendMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Max => f.write_str("max"),
+            Self::Sum => f.write_str("sum"),
+            Self::WeightedAverage(_) => f.write_str("weighted average"),
         }
     }
 }
@@ -137,21 +1071,88 @@ impl ClonableCommand {
 }
 impl Clone for ClonableCommand {
     fn clone(&self) -> Self {
-        Self(match &self.0 {
-            Command::Set(s) => Command::Set(Strength::clone(s)),
-            Command::SetTransition(t) => Command::SetTransition(Transition::clone(t)),
-            Command::ChangeDayTimer(d, t) => Command::ChangeDayTimer(*d, *t),
-            Command::ChangeDayTimerTransition(t) => {
-                Command::ChangeDayTimerTransition(Transition::clone(t))
-            }
-            Command::RemoveScheduler(s) => Command::RemoveScheduler(String::clone(s)),
-            Command::ClearAllSchedulers => Command::ClearAllSchedulers,
-            Command::Finish => Command::Finish,
+        Self(clone_command(&self.0))
+    }
+}
+/// Clones `command`, which has to be clonable. See [`Command::can_clone()`].
+fn clone_command(command: &Command) -> Command {
+    match command {
+        Command::Set(s) => Command::Set(Strength::clone(s)),
+        Command::SetTransition(t) => Command::SetTransition(Transition::clone(t)),
+        Command::SetTransitionAt(t, s) => Command::SetTransitionAt(Transition::clone(t), *s),
+        Command::QueueTransition(t) => Command::QueueTransition(Transition::clone(t)),
+        Command::SetRepeatingTransition(t, r) => {
+            Command::SetRepeatingTransition(Transition::clone(t), *r)
+        }
+        Command::SetTransitionSpeed(f) => Command::SetTransitionSpeed(*f),
+        Command::SetUpdateInterval(i) => Command::SetUpdateInterval(*i),
+        Command::SetDryRun(e) => Command::SetDryRun(*e),
+        Command::SetLayer(n, s) => Command::SetLayer(n.clone(), *s),
+        Command::RemoveLayer(n) => Command::RemoveLayer(n.clone()),
+        Command::SetBlendMode(m) => Command::SetBlendMode(m.clone()),
+        Command::SaveScene(n, s) => Command::SaveScene(n.clone(), s.clone()),
+        Command::RecallScene(n) => Command::RecallScene(n.clone()),
+        Command::RemoveScene(n) => Command::RemoveScene(n.clone()),
+        Command::ChangeDayTimer(d, t) => Command::ChangeDayTimer(*d, *t),
+        Command::ChangeDayTimerTransition(t) => {
+            Command::ChangeDayTimerTransition(Transition::clone(t))
+        }
+        Command::ChangeDayTransition(d, t) => Command::ChangeDayTransition(*d, t.clone()),
+        Command::RemoveScheduler(s) => Command::RemoveScheduler(String::clone(s)),
+        Command::SetSchedulerEnabled(s, e) => Command::SetSchedulerEnabled(String::clone(s), *e),
+        Command::SetBedtime(t) => Command::SetBedtime(*t),
+        Command::SetRgb(r, g, b) => Command::SetRgb(*r, *g, *b),
+        Command::ClearAllSchedulers => Command::ClearAllSchedulers,
+        Command::SkipNext => Command::SkipNext,
+        Command::Undo => Command::Undo,
+        Command::StartFlicker {
+            base,
+            amplitude,
+            rate,
+        } => Command::StartFlicker {
+            base: *base,
+            amplitude: *amplitude,
+            rate: *rate,
+        },
+        Command::StartStrobe { low, high, period } => Command::StartStrobe {
+            low: *low,
+            high: *high,
+            period: *period,
+        },
+        Command::StartBreathing { low, high, period } => Command::StartBreathing {
+            low: *low,
+            high: *high,
+            period: *period,
+        },
+        Command::SetColorTemp { brightness, warmth } => Command::SetColorTemp {
+            brightness: *brightness,
+            warmth: *warmth,
+        },
+        Command::SetColorTempTransition { transition, warmth } => Command::SetColorTempTransition {
+            transition: Transition::clone(transition),
+            warmth: *warmth,
+        },
+        Command::StopEffect => Command::StopEffect,
+        Command::Pause => Command::Pause,
+        Command::Resume => Command::Resume,
+        Command::Finish => Command::Finish,
+        Command::Batch(commands) => Command::Batch(commands.iter().map(clone_command).collect()),
 
-            Command::AddReplaceScheduler(_, _) => {
-                unreachable!("should have been checked when creating `ClonableCommand`")
-            }
-        })
+        Command::AddReplaceScheduler(_, _)
+        | Command::SetTransitionWithCallback(_, _)
+        | Command::ScheduleDiff(_, _)
+        | Command::RemoveSchedulerWithCallback(_, _)
+        | Command::SetSchedulerEnabledWithCallback(_, _, _)
+        | Command::RecallSceneWithCallback(_, _)
+        | Command::RemoveSceneWithCallback(_, _)
+        | Command::ListSchedulers(_)
+        | Command::GetStatus(_)
+        | Command::GetDayTransition(_)
+        | Command::GetHistory(_)
+        | Command::ReplaceConfig(_, _, _)
+        | Command::SetPausedWithCallback(_, _) => {
+            unreachable!("should have been checked when creating `ClonableCommand`")
+        }
     }
 }
 
@@ -161,13 +1162,189 @@ pub enum Action {
     Wait(scheduler::SleepTime),
     /// Set the output to this strength.
     Set(Strength),
+    /// Set the color of the output. See [`VariableOut::set_rgb()`].
+    SetRgb(Strength, Strength, Strength),
+    /// Set the warmth, then the strength, of the output. See [`VariableOut::set_warmth()`].
+    SetColorTemp(Strength, f64),
     /// Stop execution of loop
     Break,
 }
 
+/// Error from [`VariableOut::set()`].
+#[derive(Debug)]
+pub enum OutputError {
+    Pwm(rppal::pwm::Error),
+    Gpio(rppal::gpio::Error),
+    I2c(rppal::i2c::Error),
+    /// From outputs outside of this crate.
+    Other(Box<dyn std::error::Error + Send + Sync>),
+    /// Errors from the outputs of a [`output::CompositeOutput`], with their indices.
+    Composite(Vec<(usize, OutputError)>),
+}
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pwm(err) => write!(f, "hardware PWM error: {}", err),
+            Self::Gpio(err) => write!(f, "GPIO error: {}", err),
+            Self::I2c(err) => write!(f, "I2C error: {}", err),
+            Self::Other(err) => write!(f, "output error: {}", err),
+            Self::Composite(errors) => {
+                write!(f, "outputs failed:")?;
+                for (position, (index, err)) in errors.iter().enumerate() {
+                    let separator = if position == 0 { "" } else { "," };
+                    write!(f, "{} #{}: {}", separator, index, err)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+impl std::error::Error for OutputError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Pwm(err) => Some(err),
+            Self::Gpio(err) => Some(err),
+            Self::I2c(err) => Some(err),
+            Self::Other(err) => Some(&**err),
+            Self::Composite(errors) => errors.first().map(|(_, err)| err as _),
+        }
+    }
+}
+impl From<rppal::pwm::Error> for OutputError {
+    fn from(err: rppal::pwm::Error) -> Self {
+        Self::Pwm(err)
+    }
+}
+impl From<rppal::gpio::Error> for OutputError {
+    fn from(err: rppal::gpio::Error) -> Self {
+        Self::Gpio(err)
+    }
+}
+impl From<rppal::i2c::Error> for OutputError {
+    fn from(err: rppal::i2c::Error) -> Self {
+        Self::I2c(err)
+    }
+}
+
+/// Any error from this crate, convertible from the specific ones with `?`.
+#[derive(Debug)]
+pub enum Error {
+    Output(OutputError),
+    /// The controller thread has stopped, most likely from a panic.
+    /// Contains the command which couldn't be sent, if any.
+    ControllerStopped(Option<Command>),
+    /// The controller didn't answer in time.
+    Timeout,
+    /// No controller in the [`ControllerGroup`] has the name.
+    UnknownController(String),
+    /// The [`MultiController`] has no channel with the index.
+    UnknownChannel(usize),
+    Strength(StrengthError),
+    Interpolation(InterpolationParseError),
+    Transition(TransitionError),
+    /// From reading or writing files, such as in [`WeekScheduler::save()`].
+    Io(std::io::Error),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Output(err) => err.fmt(f),
+            Self::ControllerStopped(_) => f.write_str("the controller has stopped"),
+            Self::Timeout => f.write_str("the controller didn't answer in time"),
+            Self::UnknownController(name) => write!(f, "no controller named {:?}", name),
+            Self::UnknownChannel(index) => write!(f, "no channel {}", index),
+            Self::Strength(err) => err.fmt(f),
+            Self::Interpolation(err) => err.fmt(f),
+            Self::Transition(err) => err.fmt(f),
+            Self::Io(err) => write!(f, "IO error: {}", err),
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Output(err) => Some(err),
+            Self::Strength(err) => Some(err),
+            Self::Interpolation(err) => Some(err),
+            Self::Transition(err) => Some(err),
+            Self::Io(err) => Some(err),
+            Self::ControllerStopped(_)
+            | Self::Timeout
+            | Self::UnknownController(_)
+            | Self::UnknownChannel(_) => None,
+        }
+    }
+}
+impl From<OutputError> for Error {
+    fn from(err: OutputError) -> Self {
+        Self::Output(err)
+    }
+}
+impl From<StrengthError> for Error {
+    fn from(err: StrengthError) -> Self {
+        Self::Strength(err)
+    }
+}
+impl From<InterpolationParseError> for Error {
+    fn from(err: InterpolationParseError) -> Self {
+        Self::Interpolation(err)
+    }
+}
+impl From<TransitionError> for Error {
+    fn from(err: TransitionError) -> Self {
+        Self::Transition(err)
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+impl From<mpsc::SendError<Command>> for Error {
+    fn from(err: mpsc::SendError<Command>) -> Self {
+        Self::ControllerStopped(Some(err.0))
+    }
+}
+impl From<mpsc::RecvError> for Error {
+    fn from(_: mpsc::RecvError) -> Self {
+        Self::ControllerStopped(None)
+    }
+}
+impl From<mpsc::RecvTimeoutError> for Error {
+    fn from(err: mpsc::RecvTimeoutError) -> Self {
+        match err {
+            mpsc::RecvTimeoutError::Timeout => Self::Timeout,
+            mpsc::RecvTimeoutError::Disconnected => Self::ControllerStopped(None),
+        }
+    }
+}
+
+/// How many times the controller tries to set a value before skipping it.
+/// See [`VariableOut::set()`].
+pub const OUTPUT_ATTEMPTS: u32 = 3;
+
 pub trait VariableOut {
     /// Main function. Used to set output.
-    fn set(&mut self, value: Strength);
+    ///
+    /// The controller logs errors and retries up to [`OUTPUT_ATTEMPTS`] times,
+    /// after which the value is skipped. A transition continues with the next value.
+    fn set(&mut self, value: Strength) -> Result<(), OutputError>;
+
+    /// Sets the color of outputs with several channels, such as [`output::RgbOutput`].
+    /// Others ignore it.
+    fn set_rgb(
+        &mut self,
+        _red: Strength,
+        _green: Strength,
+        _blue: Strength,
+    ) -> Result<(), OutputError> {
+        Ok(())
+    }
+    /// Sets the warmth of outputs with a warm and a cool channel, such as
+    /// [`output::ColorTempOutput`], from `0` (cool) to `1` (warm). Others ignore it.
+    fn set_warmth(&mut self, _warmth: f64) -> Result<(), OutputError> {
+        Ok(())
+    }
 
     /// Enable the output when activating. Here for optimization of power usage when using PWM.
     fn enable(&mut self);
@@ -177,34 +1354,58 @@ pub trait VariableOut {
     /// Used to prepare the out device. Used for optimizing; internal guarantees.
     fn prepare(&mut self);
 }
+impl<T: VariableOut + ?Sized> VariableOut for Box<T> {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        (**self).set(value)
+    }
+    fn set_rgb(
+        &mut self,
+        red: Strength,
+        green: Strength,
+        blue: Strength,
+    ) -> Result<(), OutputError> {
+        (**self).set_rgb(red, green, blue)
+    }
+    fn set_warmth(&mut self, warmth: f64) -> Result<(), OutputError> {
+        (**self).set_warmth(warmth)
+    }
+    fn enable(&mut self) {
+        (**self).enable()
+    }
+    fn disable(&mut self) {
+        (**self).disable()
+    }
+    fn prepare(&mut self) {
+        (**self).prepare()
+    }
+}
+/// The period used by the [`VariableOut`] impls of [`Pwm`] and [`OutputPin`]; 1kHz.
+/// Use [`output::PwmOutput`] or [`output::PinPwmOutput`] for another period.
+pub const DEFAULT_PWM_PERIOD: Duration = Duration::from_millis(1);
+
 impl VariableOut for Pwm {
-    fn set(&mut self, value: Strength) {
-        self.set_pulse_width(Duration::from_nanos((value.0 * 1000000.0).round() as u64))
-            .unwrap();
-        thread::sleep(Duration::from_millis(10));
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        output::set_pulse_width(self, DEFAULT_PWM_PERIOD, value)
     }
     fn enable(&mut self) {
-        println!("Enabling hardware PWM.");
+        info!("Enabling hardware PWM.");
         Pwm::enable(self).expect("failed to enable hardware PWM");
     }
     fn disable(&mut self) {
-        println!("Disabling hardware PWM.");
+        info!("Disabling hardware PWM.");
         Pwm::disable(self).expect("failed to disable hardware PWM");
     }
     fn prepare(&mut self) {
-        self.set_pulse_width(Duration::new(0, 0))
-            .expect("failed to set pulse width to guarantee period");
-        self.set_period(Duration::new(0, 1000000))
-            .expect("failed to set period in `enable()`");
+        output::prepare_period(self, DEFAULT_PWM_PERIOD);
     }
 }
 impl VariableOut for OutputPin {
-    fn set(&mut self, value: Strength) {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
         self.set_pwm(
-            Duration::from_micros(1000),
-            Duration::from_micros((value.0 * 1000.0).round() as u64),
-        )
-        .unwrap();
+            DEFAULT_PWM_PERIOD,
+            output::pulse_width(DEFAULT_PWM_PERIOD, value),
+        )?;
+        Ok(())
     }
     fn enable(&mut self) {}
     fn disable(&mut self) {
@@ -213,26 +1414,45 @@ impl VariableOut for OutputPin {
     fn prepare(&mut self) {}
 }
 
+/// Logs everything it's told to do, at the info level, instead of driving an output.
 pub struct PrintOut;
 impl VariableOut for PrintOut {
-    fn set(&mut self, value: Strength) {
-        println!("Got strength {:?}", value);
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        debug!("Got strength {:?}", value);
         thread::sleep(Duration::from_millis(100));
+        Ok(())
     }
     fn enable(&mut self) {
-        println!("Enabling output");
+        info!("Enabling output");
     }
     fn disable(&mut self) {
-        println!("Disabling output");
+        info!("Disabling output");
     }
     fn prepare(&mut self) {
-        println!("Preparing device");
+        info!("Preparing device");
     }
 }
 
 pub fn get_naive_now() -> chrono::NaiveDateTime {
-    let now = chrono::Local::now();
-    now.date().naive_utc().and_time(now.time())
+    chrono::Local::now().naive_local()
+}
+
+/// Everything about the controller, at once. See [`Command::GetStatus`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Status {
+    pub strength: Strength,
+    /// The ongoing transition.
+    pub transition: Option<Transition>,
+    /// See [`scheduler::TransitionState::progress()`].
+    pub transition_progress: Option<f64>,
+    pub week_scheduler: WeekScheduler,
+    /// See [`SharedState::scheduler_infos()`].
+    pub schedulers: Vec<scheduler::SchedulerInfo>,
+    /// See [`Command::Pause`].
+    pub paused: bool,
+    /// See [`Command::SetDryRun`].
+    pub dry_run: bool,
 }
 
 #[derive(Debug)]
@@ -241,17 +1461,49 @@ pub struct SharedState {
     transition: Option<Transition>,
     week_scheduler: WeekScheduler,
     schedulers: HashMap<String, Box<dyn Scheduler>>,
+    disabled_schedulers: HashSet<String>,
+    scenes: HashMap<String, Scene>,
+    bedtime: scheduler::Bedtime,
+    rgb: (Strength, Strength, Strength),
+    warmth: f64,
+    config_version: u64,
+    paused: bool,
 }
 impl SharedState {
     pub fn new(scheduler: WeekScheduler) -> Self {
         Self {
-            strength: Strength::new(0.0),
+            strength: Strength::off(),
             transition: None,
             week_scheduler: scheduler,
             schedulers: HashMap::new(),
+            disabled_schedulers: HashSet::new(),
+            scenes: HashMap::new(),
+            bedtime: scheduler::Bedtime::default(),
+            rgb: (Strength::full(), Strength::full(), Strength::full()),
+            warmth: 0.5,
+            config_version: 0,
+            paused: false,
         }
     }
 
+    /// Increases every time a command which [`Command::changes_config()`] is processed.
+    /// Compare it to a previous value to know if the config has to be saved again.
+    pub fn config_version(&self) -> u64 {
+        self.config_version
+    }
+    pub(crate) fn config_changed(&mut self) {
+        self.config_version = self.config_version.wrapping_add(1);
+    }
+
+    /// See [`Command::Pause`].
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+    pub(crate) fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// The strength last set on the output, including during transitions.
     pub fn get_strength(&self) -> &Strength {
         &self.strength
     }
@@ -259,6 +1511,25 @@ impl SharedState {
         self.strength = strength;
         self.transition = None;
     }
+    /// Like [`Self::set_strength()`], but keeps the transition, for each step of it.
+    pub(crate) fn set_output_strength(&mut self, strength: Strength) {
+        self.strength = strength;
+    }
+
+    /// The color set by [`Command::SetRgb`]. Defaults to white.
+    pub fn get_rgb(&self) -> (Strength, Strength, Strength) {
+        self.rgb
+    }
+    pub fn set_rgb(&mut self, rgb: (Strength, Strength, Strength)) {
+        self.rgb = rgb;
+    }
+    /// The warmth last set, see [`Command::SetColorTemp`]. Defaults to `0.5`.
+    pub fn get_warmth(&self) -> f64 {
+        self.warmth
+    }
+    pub(crate) fn set_warmth(&mut self, warmth: f64) {
+        self.warmth = warmth;
+    }
 
     pub fn get_transition(&self) -> Option<&Transition> {
         self.transition.as_ref()
@@ -280,15 +1551,109 @@ impl SharedState {
     pub fn mut_schedulers(&mut self) -> &mut HashMap<String, Box<dyn Scheduler>> {
         &mut self.schedulers
     }
-}
 
-pub fn weekday_to_lowercase_str(weekday: &Weekday) -> &'static str {
-    match *weekday {
-        Weekday::Mon => "mon",
-        Weekday::Tue => "tue",
-        Weekday::Wed => "wed",
-        Weekday::Thu => "thu",
-        Weekday::Fri => "fri",
+    /// See [`Command::SaveScene`].
+    pub fn ref_scenes(&self) -> &HashMap<String, Scene> {
+        &self.scenes
+    }
+    pub fn mut_scenes(&mut self) -> &mut HashMap<String, Scene> {
+        &mut self.scenes
+    }
+
+    /// Names of the schedulers disabled by [`Command::SetSchedulerEnabled`].
+    pub fn ref_disabled_schedulers(&self) -> &HashSet<String> {
+        &self.disabled_schedulers
+    }
+    pub fn mut_disabled_schedulers(&mut self) -> &mut HashSet<String> {
+        &mut self.disabled_schedulers
+    }
+    pub fn is_scheduler_enabled(&self, name: &str) -> bool {
+        !self.disabled_schedulers.contains(name)
+    }
+    /// [`scheduler::SchedulerInfo`] of all named schedulers, sorted by when they next run.
+    /// The ones which don't know are last.
+    pub fn scheduler_infos(&self, now: &mut scheduler::LazyNow) -> Vec<scheduler::SchedulerInfo> {
+        let mut infos: Vec<_> = self
+            .schedulers
+            .iter()
+            .map(|(name, scheduler)| {
+                scheduler::SchedulerInfo::new(
+                    name.clone(),
+                    scheduler.as_ref(),
+                    self.is_scheduler_enabled(name),
+                    now,
+                )
+            })
+            .collect();
+        infos.sort_by(|a, b| match (a.next, b.next) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => a.name.cmp(&b.name),
+        });
+        infos
+    }
+
+    /// Gets the enabled scheduler which runs next, resolving simultaneous ones by [`Scheduler::priority()`].
+    pub fn get_next_scheduler(
+        &self,
+        now: &mut scheduler::LazyNow,
+    ) -> Option<scheduler::NextScheduler<'_>> {
+        let week_next = Scheduler::get_next(&self.week_scheduler, now);
+        let week_priority = self.week_scheduler.priority();
+
+        // `None` as the name is the week scheduler.
+        let mut candidates: Vec<(Option<&str>, NaiveDateTime, Command, i32)> = self
+            .schedulers
+            .iter()
+            .filter(|(name, _)| self.is_scheduler_enabled(name))
+            .map(|(name, s)| (Some(name.as_str()), s.get_next(now), s.priority()))
+            .chain(std::iter::once((None, week_next, week_priority)))
+            .filter_map(|(name, next, priority)| match next {
+                Next::At(date_time, cmd) => Some((name, date_time, cmd, priority)),
+                // Schedulers which don't know when they'll run next can't wake us.
+                Next::Unknown => None,
+            })
+            .collect();
+
+        let earliest = candidates.iter().map(|(_, d, _, _)| *d).min()?;
+        let window = earliest + chrono::Duration::seconds(scheduler::SIMULTANEOUS_SECONDS);
+        candidates.retain(|(_, d, _, _)| *d <= window);
+
+        // The highest priority wins. On a tie, take the earliest.
+        // Unwrap is ok; the earliest is always in the window.
+        let winner = candidates
+            .iter()
+            .enumerate()
+            .max_by(|(_, (_, d1, _, p1)), (_, (_, d2, _, p2))| p1.cmp(p2).then_with(|| d2.cmp(d1)))
+            .map(|(index, _)| index)
+            .unwrap();
+        let (name, at, command, _) = candidates.swap_remove(winner);
+
+        Some(scheduler::NextScheduler {
+            name,
+            at,
+            command,
+            suppressed: candidates
+                .into_iter()
+                .map(|(name, at, _, _)| (name, at))
+                .collect(),
+        })
+    }
+
+    /// Clone this to create [`scheduler::BedtimeScheduler`]s.
+    pub fn ref_bedtime(&self) -> &scheduler::Bedtime {
+        &self.bedtime
+    }
+}
+
+pub fn weekday_to_lowercase_str(weekday: &Weekday) -> &'static str {
+    match *weekday {
+        Weekday::Mon => "mon",
+        Weekday::Tue => "tue",
+        Weekday::Wed => "wed",
+        Weekday::Thu => "thu",
+        Weekday::Fri => "fri",
         Weekday::Sat => "sat",
         Weekday::Sun => "sun",
     }
@@ -297,117 +1662,1983 @@ pub fn weekday_to_lowercase_str(weekday: &Weekday) -> &'static str {
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 enum Sleeping {
     To(NaiveDateTime),
+    /// Between the steps of a transition. See [`scheduler::State::transition_interval()`].
+    Until(Instant),
     Wake,
     Forever,
 }
 
+/// Coalesces the [`Command::Set`]s sent in quick succession.
+/// See [`ControllerChannel::set_rate_limit()`].
+#[derive(Debug, Default)]
+struct SetLimiter {
+    window: Option<Duration>,
+    /// The latest [`Command::Set`] held back, applied when the window has passed.
+    pending: Option<Strength>,
+    last_sent: Option<Instant>,
+}
+impl SetLimiter {
+    fn within_window(&self) -> bool {
+        match (self.window, self.last_sent) {
+            (Some(window), Some(last_sent)) => last_sent.elapsed() < window,
+            _ => false,
+        }
+    }
+    /// Takes the pending strength if the window has passed.
+    fn take_due(&mut self) -> Option<Strength> {
+        if self.pending.is_none() || self.within_window() {
+            return None;
+        }
+        self.last_sent = Some(Instant::now());
+        self.pending.take()
+    }
+}
+
 pub fn has_occurred(date_time: NaiveDateTime) -> bool {
     let now = get_naive_now();
     (date_time - now) < chrono::Duration::zero()
 }
 
-/// The handler's job is to handle [`Scheduler`]s and transitions.
+/// What [`ChannelRunner::step()`] did.
+enum Step {
+    /// Nothing to do until later.
+    Idle,
+    Busy,
+    /// Got [`Command::Finish`].
+    Finished,
+}
+
+/// The controller thread's side of a [`ControllerChannel`].
+struct ChannelRunner {
+    receiver: mpsc::Receiver<Command>,
+    state: scheduler::State,
+    subscribers: Arc<Mutex<Vec<mpsc::Sender<Strength>>>>,
+    limiter: Arc<Mutex<SetLimiter>>,
+    clock: Arc<dyn scheduler::Clock>,
+    sleeping: Sleeping,
+    enabled: Option<f64>,
+    /// Handled before the received commands, to restore the state after a restart.
+    /// See [`ChannelRunner::restart()`].
+    replay: VecDeque<Command>,
+}
+impl ChannelRunner {
+    fn new(
+        channel: &ControllerChannel,
+        receiver: mpsc::Receiver<Command>,
+        clock: &Arc<dyn scheduler::Clock>,
+    ) -> Self {
+        Self {
+            receiver,
+            state: scheduler::State::with_clock(
+                Arc::clone(&channel.shared_state),
+                Arc::clone(clock),
+            )
+            .with_observers(Arc::clone(&channel.observers))
+            .with_listeners(Arc::clone(&channel.listeners)),
+            subscribers: Arc::clone(&channel.subscribers),
+            limiter: Arc::clone(&channel.limiter),
+            clock: Arc::clone(clock),
+            sleeping: Sleeping::Wake,
+            enabled: None,
+            replay: VecDeque::new(),
+        }
+    }
+    /// Handles the next command or wakes up, setting `output`.
+    fn step<T: VariableOut>(&mut self, output: &mut T) -> Step {
+        let command = match self
+            .replay
+            .pop_front()
+            .or_else(|| self.limiter.lock().unwrap().take_due().map(Command::Set))
+            .or_else(|| self.receiver.try_recv().ok())
+        {
+            Some(r) => {
+                self.sleeping = Sleeping::Wake;
+                Some(r)
+            }
+            None => match self.sleeping {
+                Sleeping::To(date_time) => match date_time < self.clock.now() {
+                    false => return Step::Idle,
+                    true => None,
+                },
+                Sleeping::Until(instant) => match instant <= Instant::now() {
+                    false => return Step::Idle,
+                    true => None,
+                },
+                Sleeping::Forever => return Step::Idle,
+                Sleeping::Wake => None,
+            },
+        };
+        let state = &mut self.state;
+        let action = match state.process(command) {
+            Action::SetColorTemp(strength, warmth) => {
+                if state.dry_run() {
+                    info!(warmth, "Dry run, not setting warmth");
+                } else if let Err(err) = output.set_warmth(warmth) {
+                    error!("Failed to set warmth: {}", err);
+                }
+                Action::Set(strength)
+            }
+            action => action,
+        };
+        match action {
+            Action::Wait(sleep_time) => match sleep_time {
+                scheduler::SleepTime::To(date_time) => {
+                    if self.enabled.map(|value| value == 0.0).unwrap_or(false) {
+                        output.disable();
+                        self.enabled = None;
+                    }
+                    debug!("Sleeping to {:?}", date_time);
+                    self.sleeping = Sleeping::To(date_time)
+                }
+                scheduler::SleepTime::Forever => self.sleeping = Sleeping::Forever,
+            },
+            Action::Set(s) if state.dry_run() => {
+                info!(strength = s.into_inner(), "Dry run, not setting output");
+                if let Some(interval) = state.transition_interval() {
+                    self.sleeping = Sleeping::Until(Instant::now() + interval);
+                }
+            }
+            Action::Set(s) => {
+                if self.enabled.unwrap_or(0.0) == 0.0 {
+                    output.enable();
+                }
+                let mut attempt = 1;
+                loop {
+                    match output.set(s) {
+                        Ok(()) => {
+                            debug!(strength = s.into_inner(), "Set output");
+                            self.enabled = Some(s.into_inner());
+                            // Drops the subscribers which have hung up.
+                            self.subscribers
+                                .lock()
+                                .unwrap()
+                                .retain(|subscriber| subscriber.send(s).is_ok());
+                            break;
+                        }
+                        Err(err) if attempt < OUTPUT_ATTEMPTS => {
+                            warn!("Failed to set output to {:?}, retrying: {}", s, err);
+                            attempt += 1;
+                            thread::sleep(Duration::from_millis(10));
+                        }
+                        Err(err) => {
+                            error!("Failed to set output to {:?}, skipping: {}", s, err);
+                            break;
+                        }
+                    }
+                }
+                if let Some(interval) = state.transition_interval() {
+                    self.sleeping = Sleeping::Until(Instant::now() + interval);
+                }
+            }
+            Action::SetRgb(red, green, blue) if state.dry_run() => {
+                info!(
+                    red = red.into_inner(),
+                    green = green.into_inner(),
+                    blue = blue.into_inner(),
+                    "Dry run, not setting color"
+                );
+            }
+            Action::SetRgb(red, green, blue) => {
+                if let Err(err) = output.set_rgb(red, green, blue) {
+                    error!("Failed to set color: {}", err);
+                }
+            }
+            Action::SetColorTemp(_, _) => {
+                unreachable!("the warmth is set and the action replaced above")
+            }
+            Action::Break => return Step::Finished,
+        }
+        Step::Busy
+    }
+    /// Steps until [`Command::Finish`], then gives back `output`.
+    fn run<T: VariableOut>(mut self, mut output: T) -> T {
+        output.prepare();
+        loop {
+            match self.step(&mut output) {
+                Step::Idle => thread::sleep(Duration::from_millis(1)),
+                Step::Busy => {}
+                // Drops the receiver, so sending to the channel fails.
+                Step::Finished => return output,
+            }
+        }
+    }
+    /// Like [`ChannelRunner::run()`], but if a step panics, it [restarts](Self::restart())
+    /// with a new output from `factory`. `restarts` counts how many times.
+    fn run_supervised<T: VariableOut, F: FnMut() -> T>(
+        mut self,
+        mut output: T,
+        mut factory: F,
+        restarts: &AtomicU32,
+    ) -> T {
+        output.prepare();
+        // If it panics again before the replay is done, the replay is the problem.
+        let mut replaying = false;
+        loop {
+            match panic::catch_unwind(AssertUnwindSafe(|| self.step(&mut output))) {
+                Ok(step) => {
+                    replaying &= !self.replay.is_empty();
+                    match step {
+                        Step::Idle => thread::sleep(Duration::from_millis(1)),
+                        Step::Busy => {}
+                        Step::Finished => return output,
+                    }
+                }
+                Err(_) => {
+                    if replaying {
+                        error!("Controller panicked again while restoring, starting over without");
+                    } else {
+                        error!("Controller panicked, restarting it with a new output");
+                    }
+                    self.restart(!replaying);
+                    replaying = !self.replay.is_empty();
+                    restarts.fetch_add(1, Ordering::Relaxed);
+                    output = factory();
+                    output.prepare();
+                }
+            }
+        }
+    }
+    /// Starts over with a new [`scheduler::State`] after a panic,
+    /// keeping the [`SharedState`], subscribers, observers and listeners.
+    /// If `replay`, what the old state was doing is restored. See [`scheduler::State::replay()`].
+    fn restart(&mut self, replay: bool) {
+        // The panic may have happened while holding a lock. The data is still usable.
+        self.state.clear_poison();
+        self.subscribers.clear_poison();
+        self.limiter.clear_poison();
+        self.replay = match replay {
+            true => self.state.replay(),
+            false => VecDeque::new(),
+        };
+        self.state = self.state.restarted();
+        self.sleeping = Sleeping::Wake;
+        self.enabled = None;
+    }
+}
+
+/// One output of a [`MultiController`], with its own [`SharedState`], schedulers and transitions.
 ///
-/// This is done by spawning a thread and running all code on it.
+/// Commands sent here only affect this channel's output.
 #[derive(Debug)]
-pub struct Controller<T: VariableOut + Send + 'static> {
+pub struct ControllerChannel {
     channel: mpsc::SyncSender<Command>,
-    handle: thread::JoinHandle<T>,
     shared_state: Arc<Mutex<SharedState>>,
+    subscribers: Arc<Mutex<Vec<mpsc::Sender<Strength>>>>,
+    observers: Arc<Mutex<Vec<mpsc::SyncSender<scheduler::SchedulerEvent>>>>,
+    clock: Arc<dyn scheduler::Clock>,
+    limiter: Arc<Mutex<SetLimiter>>,
+    listeners: Arc<Mutex<Vec<Box<dyn EventListener>>>>,
 }
-impl<T: VariableOut + Send + 'static> Controller<T> {
-    pub fn new(mut output: T, scheduler: WeekScheduler) -> Self {
+impl ControllerChannel {
+    fn new(
+        shared_state: Arc<Mutex<SharedState>>,
+        clock: Arc<dyn scheduler::Clock>,
+    ) -> (Self, mpsc::Receiver<Command>) {
         // make channel
         let (sender, receiver) = mpsc::sync_channel(2);
+        let channel = Self {
+            channel: sender,
+            shared_state,
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+            observers: Arc::new(Mutex::new(Vec::new())),
+            clock,
+            limiter: Arc::new(Mutex::new(SetLimiter::default())),
+            listeners: Arc::new(Mutex::new(Vec::new())),
+        };
+        (channel, receiver)
+    }
+    /// Errors with [`Error::ControllerStopped`] if the controller thread has stopped,
+    /// most likely from a panic. The command is given back in the error.
+    ///
+    /// [`Command::Set`] is dropped if the controller is busy, since a newer one will follow.
+    /// With a [rate limit](Self::set_rate_limit()), it's held back instead
+    /// if another was sent within the window.
+    ///
+    /// The `send_*` methods don't return the error; their receivers fail instead.
+    pub fn send(&self, command: Command) -> Result<(), Error> {
+        match &command {
+            Command::Set(strength) => {
+                {
+                    let mut limiter = self.limiter.lock().unwrap();
+                    if limiter.within_window() {
+                        // It wouldn't be sent later either.
+                        if !self.is_alive() {
+                            return Err(Error::ControllerStopped(Some(command)));
+                        }
+                        limiter.pending = Some(*strength);
+                        return Ok(());
+                    }
+                    limiter.pending = None;
+                    limiter.last_sent = Some(Instant::now());
+                }
+                match self.channel.try_send(command) {
+                    Ok(()) | Err(mpsc::TrySendError::Full(_)) => Ok(()),
+                    Err(mpsc::TrySendError::Disconnected(command)) => {
+                        Err(Error::ControllerStopped(Some(command)))
+                    }
+                }
+            }
+            _ => {
+                // Applies the held back strength first, keeping the order of the commands.
+                let pending = self.limiter.lock().unwrap().pending.take();
+                if let Some(strength) = pending {
+                    if self.channel.send(Command::Set(strength)).is_err() {
+                        return Err(Error::ControllerStopped(Some(command)));
+                    }
+                }
+                Ok(self.channel.send(command)?)
+            }
+        }
+    }
+    /// If the controller thread of this channel is still running. See [`MultiController::is_alive()`].
+    pub fn is_alive(&self) -> bool {
+        // The thread holds the only other reference to the limiter, and drops it when it stops.
+        Arc::strong_count(&self.limiter) > 1
+    }
+    /// Coalesces the [`Command::Set`]s sent within `window` of the last one sent,
+    /// so only the latest gets applied when the window has passed.
+    /// This keeps e.g. a dragged slider from flooding the controller.
+    ///
+    /// `None`, the default, turns it off.
+    pub fn set_rate_limit(&self, window: Option<Duration>) {
+        self.limiter.lock().unwrap().window = window;
+    }
 
-        let shared_state = Arc::new(Mutex::new(SharedState::new(scheduler)));
+    /// Starts `transition` and returns a receiver which gets exactly one [`TransitionOutcome`]
+    /// when it ends.
+    pub fn send_transition(&self, transition: Transition) -> mpsc::Receiver<TransitionOutcome> {
+        let (sender, receiver) = mpsc::channel();
+        // If the thread has stopped, the sender is dropped with the error and `recv` fails.
+        let _ = self.send(Command::SetTransitionWithCallback(transition, sender));
+        receiver
+    }
 
-        let shared = Arc::clone(&shared_state);
+    /// Applies a partial day to time map to the [`WeekScheduler`] and returns a receiver
+    /// which gets the days which actually changed.
+    pub fn send_schedule_diff(
+        &self,
+        diff: HashMap<Weekday, Option<NaiveTime>>,
+    ) -> mpsc::Receiver<Vec<Weekday>> {
+        let (sender, receiver) = mpsc::channel();
+        let _ = self.send(Command::ScheduleDiff(diff, sender));
+        receiver
+    }
 
-        let handle = thread::spawn(move || {
-            let receiver = receiver;
-            let mut state = scheduler::State::new(shared);
-            let mut sleeping: Sleeping = Sleeping::Wake;
-            let mut enabled = None;
+    /// Removes the scheduler named `name` and returns a receiver which gets
+    /// whether it existed.
+    pub fn send_remove_scheduler(&self, name: String) -> mpsc::Receiver<bool> {
+        let (sender, receiver) = mpsc::channel();
+        let _ = self.send(Command::RemoveSchedulerWithCallback(name, sender));
+        receiver
+    }
+    /// Enables or disables the scheduler named `name` and returns a receiver which gets
+    /// whether it exists. See [`Command::SetSchedulerEnabled`].
+    pub fn send_set_scheduler_enabled(&self, name: String, enabled: bool) -> mpsc::Receiver<bool> {
+        let (sender, receiver) = mpsc::channel();
+        let _ = self.send(Command::SetSchedulerEnabledWithCallback(
+            name, enabled, sender,
+        ));
+        receiver
+    }
 
-            output.prepare();
+    /// Recalls the scene named `name` and returns a receiver which gets
+    /// whether it existed. See [`Command::RecallScene`].
+    pub fn send_recall_scene(&self, name: String) -> mpsc::Receiver<bool> {
+        let (sender, receiver) = mpsc::channel();
+        let _ = self.send(Command::RecallSceneWithCallback(name, sender));
+        receiver
+    }
 
+    /// Removes the scene named `name` and returns a receiver which gets
+    /// whether it existed.
+    pub fn send_remove_scene(&self, name: String) -> mpsc::Receiver<bool> {
+        let (sender, receiver) = mpsc::channel();
+        let _ = self.send(Command::RemoveSceneWithCallback(name, sender));
+        receiver
+    }
+
+    /// Pauses and returns a receiver which gets whether it wasn't already paused.
+    /// See [`Command::Pause`].
+    pub fn send_pause(&self) -> mpsc::Receiver<bool> {
+        let (sender, receiver) = mpsc::channel();
+        let _ = self.send(Command::SetPausedWithCallback(true, sender));
+        receiver
+    }
+    /// Resumes and returns a receiver which gets whether it was paused.
+    pub fn send_resume(&self) -> mpsc::Receiver<bool> {
+        let (sender, receiver) = mpsc::channel();
+        let _ = self.send(Command::SetPausedWithCallback(false, sender));
+        receiver
+    }
+
+    /// Returns a receiver which gets the [`Status`] of the controller.
+    pub fn send_get_status(&self) -> mpsc::Receiver<Status> {
+        let (sender, receiver) = mpsc::channel();
+        let _ = self.send(Command::GetStatus(sender));
+        receiver
+    }
+
+    /// Returns a receiver which gets the transition the [`WeekScheduler`] runs today.
+    pub fn send_get_day_transition(&self) -> mpsc::Receiver<Transition> {
+        let (sender, receiver) = mpsc::channel();
+        let _ = self.send(Command::GetDayTransition(sender));
+        receiver
+    }
+
+    /// Returns a receiver which gets the named schedulers, sorted by when they next run.
+    pub fn send_list_schedulers(&self) -> mpsc::Receiver<Vec<scheduler::SchedulerInfo>> {
+        let (sender, receiver) = mpsc::channel();
+        let _ = self.send(Command::ListSchedulers(sender));
+        receiver
+    }
+
+    /// Returns a receiver which gets the last strengths set, oldest first.
+    /// At most [`scheduler::HISTORY_CAPACITY`] are kept.
+    pub fn send_get_history(&self) -> mpsc::Receiver<Vec<(Duration, Strength)>> {
+        let (sender, receiver) = mpsc::channel();
+        let _ = self.send(Command::GetHistory(sender));
+        receiver
+    }
+
+    /// Returns a receiver which gets every [`Strength`] successfully set on the output.
+    ///
+    /// Drop the receiver to unsubscribe; it's removed on the next set.
+    pub fn subscribe(&self) -> mpsc::Receiver<Strength> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Returns a receiver which gets a [`scheduler::SchedulerEvent`] every time a scheduler
+    /// (including the [`WeekScheduler`]) is due and its transition is applied.
+    ///
+    /// If more than [`scheduler::OBSERVER_CAPACITY`] events are unread, new ones are dropped.
+    /// Drop the receiver to stop observing.
+    pub fn observe_schedulers(&self) -> mpsc::Receiver<scheduler::SchedulerEvent> {
+        let (sender, receiver) = mpsc::sync_channel(scheduler::OBSERVER_CAPACITY);
+        self.observers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Calls `listener` on every event from now on. See [`EventListener`].
+    pub fn add_listener(&self, listener: impl EventListener + 'static) {
+        self.listeners.lock().unwrap().push(Box::new(listener));
+    }
+
+    /// Saves the current [`WeekScheduler`] to `path`. See [`WeekScheduler::save()`].
+    #[cfg(feature = "serde")]
+    pub fn save_week_scheduler<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        let scheduler = self
+            .shared_state
+            .lock()
+            .unwrap()
+            .ref_week_schedule()
+            .clone();
+        scheduler.save(path)
+    }
+    /// Spawns a thread which saves the [`WeekScheduler`] to `path` whenever it's changed.
+    ///
+    /// To not save for every command in a burst, it waits until nothing has changed for `debounce`.
+    /// Failures are logged and retried next time.
+    /// The thread stops when the [`SharedState`] is dropped.
+    #[cfg(feature = "serde")]
+    pub fn autosave_week_scheduler<P: Into<std::path::PathBuf>>(
+        &self,
+        path: P,
+        debounce: Duration,
+    ) -> thread::JoinHandle<()> {
+        let path = path.into();
+        let shared = Arc::downgrade(&self.shared_state);
+        let mut saved = self.shared_state.lock().unwrap().config_version();
+        thread::spawn(move || {
+            let mut seen = saved;
             loop {
-                let command = match receiver.try_recv().ok() {
-                    Some(r) => {
-                        sleeping = Sleeping::Wake;
-                        Some(r)
-                    }
-                    None => match sleeping {
-                        Sleeping::To(date_time) => match has_occurred(date_time) {
-                            false => {
-                                thread::sleep(Duration::from_millis(1));
-                                continue;
-                            }
-                            true => None,
-                        },
-                        Sleeping::Forever => {
-                            thread::sleep(Duration::from_millis(1));
-                            continue;
-                        }
-                        Sleeping::Wake => None,
-                    },
+                thread::sleep(debounce);
+                let shared = match shared.upgrade() {
+                    Some(shared) => shared,
+                    None => break,
                 };
-                let action = state.process(command);
-                match action {
-                    Action::Wait(sleep_time) => match sleep_time {
-                        scheduler::SleepTime::To(date_time) => {
-                            if enabled.map(|value| value == 0.0).unwrap_or(false) {
-                                output.disable();
-                                enabled = None;
-                            }
-                            println!("Sleeping to {:?}", date_time);
-                            sleeping = Sleeping::To(date_time)
-                        }
-                        scheduler::SleepTime::Forever => sleeping = Sleeping::Forever,
-                    },
-                    Action::Set(s) => {
-                        if enabled.unwrap_or(0.0) == 0.0 {
-                            output.enable();
-                        }
-                        output.set(s);
-                        enabled = Some(s.into_inner());
+                let lock = shared.lock().unwrap();
+                let version = lock.config_version();
+                if version != seen {
+                    // Still changing; wait for it to settle.
+                    seen = version;
+                    continue;
+                }
+                if version == saved {
+                    continue;
+                }
+                let scheduler = lock.ref_week_schedule().clone();
+                drop(lock);
+                match scheduler.save(&path) {
+                    Ok(()) => saved = version,
+                    Err(err) => error!("Failed to save schedule to {}: {}", path.display(), err),
+                }
+            }
+        })
+    }
+
+    /// Gets a reference counted [`SharedState`]
+    /// The value should not be mutated, since it'll be overriden by the other thread.
+    pub fn get_state(&self) -> Arc<Mutex<SharedState>> {
+        Arc::clone(&self.shared_state)
+    }
+    /// The clock the schedulers run on. See [`MultiController::with_clock()`].
+    pub fn clock(&self) -> Arc<dyn scheduler::Clock> {
+        Arc::clone(&self.clock)
+    }
+}
+
+/// The handler's job is to handle [`Scheduler`]s and transitions of `N` outputs.
+///
+/// This is done by spawning a thread for each output and running all its code on it.
+/// Each output is a [`ControllerChannel`] with its own state, so e.g. the lamps of a fixture
+/// can run independent transitions. Use [`Controller`] for a single output.
+#[derive(Debug)]
+pub struct MultiController<T: VariableOut + Send + 'static, const N: usize> {
+    channels: Vec<ControllerChannel>,
+    handles: Vec<thread::JoinHandle<T>>,
+}
+/// A [`MultiController`] with a single output.
+///
+/// The methods of the [`ControllerChannel`] are available directly on it.
+pub type Controller<T> = MultiController<T, 1>;
+impl<T: VariableOut + Send + 'static, const N: usize> MultiController<T, N> {
+    /// Drives `outputs`, each starting with the corresponding [`WeekScheduler`] in `schedulers`.
+    pub fn with_channels(outputs: [T; N], schedulers: [WeekScheduler; N]) -> Self {
+        Self::with_channels_and_clock(outputs, schedulers, Arc::new(scheduler::SystemClock))
+    }
+    /// Like [`MultiController::with_channels()`], but getting the time from `clock`.
+    pub fn with_channels_and_clock(
+        outputs: [T; N],
+        schedulers: [WeekScheduler; N],
+        clock: Arc<dyn scheduler::Clock>,
+    ) -> Self {
+        let (channels, receivers) = Vec::from(schedulers)
+            .into_iter()
+            .map(|scheduler| {
+                let shared_state = Arc::new(Mutex::new(SharedState::new(scheduler)));
+                ControllerChannel::new(shared_state, Arc::clone(&clock))
+            })
+            .unzip();
+        Self::spawn(outputs, channels, receivers, clock)
+    }
+    /// Spawns a thread for each of `outputs`, driven by the channel and receiver at the same index.
+    fn spawn(
+        outputs: [T; N],
+        channels: Vec<ControllerChannel>,
+        receivers: Vec<mpsc::Receiver<Command>>,
+        clock: Arc<dyn scheduler::Clock>,
+    ) -> Self {
+        let handles = Vec::from(outputs)
+            .into_iter()
+            .zip(channels.iter().zip(receivers))
+            .map(|(output, (channel, receiver))| {
+                let runner = ChannelRunner::new(channel, receiver, &clock);
+                thread::spawn(move || runner.run(output))
+            })
+            .collect();
+        Self { channels, handles }
+    }
+
+    /// Gets the channel driving output number `index`.
+    pub fn channel(&self, index: usize) -> Option<&ControllerChannel> {
+        self.channels.get(index)
+    }
+    /// All channels, in the order of the outputs.
+    pub fn channels(&self) -> &[ControllerChannel] {
+        &self.channels
+    }
+    /// Sends `command` to the channel `index`. See [`ControllerChannel::send()`].
+    pub fn send_to(&self, index: usize, command: Command) -> Result<(), Error> {
+        self.channel(index)
+            .ok_or(Error::UnknownChannel(index))?
+            .send(command)
+    }
+    /// Adds or replaces the scheduler `name` of the channel `index`.
+    /// The schedulers of each channel are separate; the same name can be used on several.
+    pub fn add_scheduler(
+        &self,
+        index: usize,
+        name: impl Into<String>,
+        scheduler: Box<dyn Scheduler>,
+    ) -> Result<(), Error> {
+        self.send_to(index, Command::AddReplaceScheduler(name.into(), scheduler))
+    }
+    /// If the threads of all channels are still running.
+    ///
+    /// A channel's thread stops when it's finished, or if it panics, e.g. in a [`VariableOut`].
+    /// Sending to it then errors with [`Error::ControllerStopped`] instead of panicking.
+    pub fn is_alive(&self) -> bool {
+        self.handles.iter().all(|handle| !handle.is_finished())
+    }
+
+    /// Will wait on any transitions on all channels to conclude and then give back the outputs.
+    pub fn finish_all(self) -> [T; N] {
+        for channel in &self.channels {
+            let _ = channel.send(Command::Finish);
+        }
+        // If a thread has stopped, `join` reports why.
+        let outputs: Vec<T> = self
+            .handles
+            .into_iter()
+            .map(|handle| handle.join().expect("child thread paniced"))
+            .collect();
+        outputs
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("there's a thread for each output"))
+    }
+}
+impl<T: VariableOut + Send + 'static> MultiController<T, 1> {
+    pub fn new(output: T, scheduler: WeekScheduler) -> Self {
+        Self::with_clock(output, scheduler, Arc::new(scheduler::SystemClock))
+    }
+    /// Like [`MultiController::new()`], but getting the time from `clock`.
+    pub fn with_clock(
+        output: T,
+        scheduler: WeekScheduler,
+        clock: Arc<dyn scheduler::Clock>,
+    ) -> Self {
+        Self::with_channels_and_clock([output], [scheduler], clock)
+    }
+
+    /// Will wait on any transitions to conclude and then give back the underlying object
+    pub fn finish(self) -> T {
+        let [output] = self.finish_all();
+        output
+    }
+}
+impl<T: VariableOut + Send + 'static> std::ops::Deref for MultiController<T, 1> {
+    type Target = ControllerChannel;
+    fn deref(&self) -> &Self::Target {
+        &self.channels[0]
+    }
+}
+
+/// A [`Controller`] which recovers from a panic on its thread, most likely in the output.
+///
+/// The output is lost in the panic, so `factory` makes a new one on the controller thread.
+/// The [`SharedState`], with the schedulers and scenes, is kept, as are the subscribers,
+/// observers and listeners. The strength, blend mode, layers, the running transition or effect,
+/// and whether it's paused are restored; queued transitions are lost.
+/// If it panics again while restoring them, it starts over without.
+///
+/// The methods of the [`Controller`] are available directly on it.
+#[derive(Debug)]
+pub struct SupervisedController<T: VariableOut + Send + 'static> {
+    controller: Controller<T>,
+    restarts: Arc<AtomicU32>,
+}
+impl<T: VariableOut + Send + 'static> SupervisedController<T> {
+    pub fn new<F: FnMut() -> T + Send + 'static>(factory: F, scheduler: WeekScheduler) -> Self {
+        Self::with_clock(factory, scheduler, Arc::new(scheduler::SystemClock))
+    }
+    /// Like [`SupervisedController::new()`], but getting the time from `clock`.
+    pub fn with_clock<F: FnMut() -> T + Send + 'static>(
+        mut factory: F,
+        scheduler: WeekScheduler,
+        clock: Arc<dyn scheduler::Clock>,
+    ) -> Self {
+        let output = factory();
+        let shared_state = Arc::new(Mutex::new(SharedState::new(scheduler)));
+        let (channel, receiver) = ControllerChannel::new(shared_state, Arc::clone(&clock));
+        let runner = ChannelRunner::new(&channel, receiver, &clock);
+        let restarts = Arc::new(AtomicU32::new(0));
+        let thread_restarts = Arc::clone(&restarts);
+        let handle =
+            thread::spawn(move || runner.run_supervised(output, factory, &thread_restarts));
+        Self {
+            controller: MultiController {
+                channels: vec![channel],
+                handles: vec![handle],
+            },
+            restarts,
+        }
+    }
+
+    /// How many times the controller has been restarted.
+    pub fn restarts(&self) -> u32 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+    /// Finishes the controller, see [`Controller::finish()`].
+    pub fn finish(self) -> T {
+        self.controller.finish()
+    }
+}
+impl<T: VariableOut + Send + 'static> std::ops::Deref for SupervisedController<T> {
+    type Target = Controller<T>;
+    fn deref(&self) -> &Self::Target {
+        &self.controller
+    }
+}
+
+/// Several [`Controller`]s by name, one for each lamp.
+///
+/// Each controller keeps its own thread and schedulers; this only routes commands to them.
+/// The controllers are kept in the order they were added.
+#[derive(Debug)]
+pub struct ControllerGroup<T: VariableOut + Send + 'static> {
+    controllers: Vec<(String, Controller<T>)>,
+}
+impl<T: VariableOut + Send + 'static> ControllerGroup<T> {
+    pub fn new() -> Self {
+        Self {
+            controllers: Vec::new(),
+        }
+    }
+    /// Adds `controller` as `name`, returning the controller it replaced, if any.
+    /// The replaced controller is still running; [`Controller::finish()`] it.
+    pub fn insert(
+        &mut self,
+        name: impl Into<String>,
+        controller: Controller<T>,
+    ) -> Option<Controller<T>> {
+        let name = name.into();
+        match self.controllers.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, old)) => Some(std::mem::replace(old, controller)),
+            None => {
+                self.controllers.push((name, controller));
+                None
+            }
+        }
+    }
+    /// Takes the controller out of the group. It's still running.
+    pub fn remove(&mut self, name: &str) -> Option<Controller<T>> {
+        let index = self.controllers.iter().position(|(n, _)| n == name)?;
+        Some(self.controllers.remove(index).1)
+    }
+    pub fn get(&self, name: &str) -> Option<&Controller<T>> {
+        self.controllers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, controller)| controller)
+    }
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.controllers.iter().map(|(name, _)| name.as_str())
+    }
+    pub fn len(&self) -> usize {
+        self.controllers.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.controllers.is_empty()
+    }
+
+    /// Sends `command` to the controller named `name`. See [`ControllerChannel::send()`].
+    pub fn send(&self, name: &str, command: Command) -> Result<(), Error> {
+        self.get(name)
+            .ok_or_else(|| Error::UnknownController(name.to_string()))?
+            .send(command)
+    }
+    /// Sends `command` to all controllers.
+    ///
+    /// All controllers get it even if sending to one fails; the first error is returned.
+    pub fn broadcast(&self, command: ClonableCommand) -> Result<(), Error> {
+        let mut result = Ok(());
+        for (name, controller) in &self.controllers {
+            if let Err(err) = controller.send(command.clone().into_inner()) {
+                warn!("Failed to send command to {:?}: {}", name, err);
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+        result
+    }
+    /// Starts `transition` on all controllers, timed from the same instant,
+    /// so they stay in step however late each gets the command. See [`Command::SetTransitionAt`].
+    ///
+    /// All controllers get it even if sending to one fails; the first error is returned.
+    pub fn broadcast_transition(&self, transition: Transition) -> Result<(), Error> {
+        let command = ClonableCommand::new(Command::SetTransitionAt(transition, Instant::now()))
+            .expect("`SetTransitionAt` can be cloned");
+        self.broadcast(command)
+    }
+    /// Finishes all controllers, see [`Controller::finish()`].
+    /// They're told to finish at once, then waited on in order.
+    pub fn finish(self) -> Vec<(String, T)> {
+        for (_, controller) in &self.controllers {
+            let _ = controller.send(Command::Finish);
+        }
+        self.controllers
+            .into_iter()
+            .map(|(name, controller)| (name, controller.finish()))
+            .collect()
+    }
+}
+impl<T: VariableOut + Send + 'static> Default for ControllerGroup<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transition(interpolation: TransitionInterpolation) -> Transition {
+        Transition::new(
+            Strength::new(0.2),
+            Strength::new(0.8),
+            Duration::from_secs(10),
+            interpolation,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn constant_holds_to() {
+        let transition = transition(TransitionInterpolation::Constant);
+        for secs in [0, 1, 5, 10, 3600] {
+            assert_eq!(
+                transition.strength_at(Duration::from_secs(secs)),
+                Strength::new(0.8)
+            );
+        }
+        assert_eq!(transition.total_duration(), Duration::new(0, 0));
+        assert_eq!(transition.progress_at(Duration::new(0, 0)), 1.0);
+
+        let instant = Transition::new(
+            Strength::off(),
+            Strength::full(),
+            Duration::new(0, 0),
+            TransitionInterpolation::Constant,
+        )
+        .unwrap();
+        assert_eq!(instant.strength_at(Duration::new(0, 0)), Strength::full());
+    }
+
+    #[test]
+    fn total_duration_of_each_interpolation() {
+        for (interpolation, total) in [
+            (TransitionInterpolation::Linear, 10.0),
+            (TransitionInterpolation::Sine, 10.0),
+            (
+                TransitionInterpolation::Custom(easing::CustomEasing::new(Cubed)),
+                10.0,
+            ),
+            (TransitionInterpolation::Constant, 0.0),
+            (TransitionInterpolation::LinearToAndBack(0.5), 15.0),
+            (TransitionInterpolation::SineToAndBack(2.0), 30.0),
+        ] {
+            let name = interpolation.as_str().to_string();
+            assert_eq!(
+                transition(interpolation).total_duration(),
+                Duration::from_secs_f64(total),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn progress_at_key_points() {
+        let progress = |interpolation, secs| {
+            transition(interpolation).progress_at(Duration::from_secs_f64(secs))
+        };
+        assert_eq!(progress(TransitionInterpolation::Linear, 0.0), 0.0);
+        assert_eq!(progress(TransitionInterpolation::Linear, 2.5), 0.25);
+        assert_eq!(progress(TransitionInterpolation::Linear, 10.0), 1.0);
+        assert_eq!(progress(TransitionInterpolation::Linear, 60.0), 1.0);
+        // Halfway at the peak.
+        assert_eq!(
+            progress(TransitionInterpolation::SineToAndBack(1.0), 10.0),
+            0.5
+        );
+        assert_eq!(
+            progress(TransitionInterpolation::SineToAndBack(1.0), 20.0),
+            1.0
+        );
+        let peak = progress(TransitionInterpolation::LinearToAndBack(0.5), 10.0);
+        assert!((peak - 1.0 / 1.5).abs() < 1e-9);
+        assert_eq!(progress(TransitionInterpolation::Constant, 0.0), 1.0);
+    }
+
+    #[test]
+    fn reversed_round_trips() {
+        for interpolation in [
+            TransitionInterpolation::Linear,
+            TransitionInterpolation::Sine,
+            TransitionInterpolation::LinearToAndBack(0.5),
+            TransitionInterpolation::SineToAndBack(4.0),
+            TransitionInterpolation::SineToAndBack(TO_AND_BACK_MULTIPLIER_MAX),
+            TransitionInterpolation::LinearToAndBack(TO_AND_BACK_MULTIPLIER_MIN),
+        ] {
+            let transition = transition(interpolation);
+            let reversed = transition.reversed();
+            assert_eq!(reversed.total_duration(), transition.total_duration());
+            // The reverse is valid too.
+            let checked = Transition::new(
+                reversed.from,
+                reversed.to,
+                reversed.time,
+                reversed.interpolation.clone(),
+            );
+            assert_eq!(checked.as_ref(), Ok(&reversed));
+            assert_eq!(reversed.reversed(), transition);
+        }
+
+        let reversed = transition(TransitionInterpolation::Linear).reversed();
+        assert_eq!(reversed.from, Strength::new(0.8));
+        assert_eq!(reversed.to, Strength::new(0.2));
+        // A to-and-back transition ends where it started either way.
+        let reversed = transition(TransitionInterpolation::LinearToAndBack(0.5)).reversed();
+        assert_eq!(reversed.from, Strength::new(0.2));
+        assert_eq!(reversed.to, Strength::new(0.8));
+        assert_eq!(
+            reversed.interpolation,
+            TransitionInterpolation::LinearToAndBack(2.0)
+        );
+    }
+
+    #[derive(Debug)]
+    struct Cubed;
+    impl easing::Easing for Cubed {
+        fn apply(&self, t: f64) -> f64 {
+            t * t * t
+        }
+        fn name(&self) -> &str {
+            "cubed"
+        }
+    }
+
+    #[test]
+    fn curves_at_elapsed_times() {
+        let cases = [
+            (
+                TransitionInterpolation::Linear,
+                [
+                    (0, 0.2),
+                    (2500, 0.35),
+                    (5000, 0.5),
+                    (10_000, 0.8),
+                    (20_000, 0.8),
+                ]
+                .as_slice(),
+            ),
+            (
+                TransitionInterpolation::Sine,
+                &[(0, 0.2), (5000, 0.5), (10_000, 0.8), (20_000, 0.8)],
+            ),
+            (
+                TransitionInterpolation::LinearToAndBack(1.0),
+                &[
+                    (0, 0.2),
+                    (5000, 0.5),
+                    (10_000, 0.8),
+                    (15_000, 0.5),
+                    (20_000, 0.2),
+                ],
+            ),
+            (
+                TransitionInterpolation::SineToAndBack(0.5),
+                &[
+                    (0, 0.2),
+                    (10_000, 0.8),
+                    (12_500, 0.5),
+                    (15_000, 0.2),
+                    (30_000, 0.2),
+                ],
+            ),
+            (TransitionInterpolation::Constant, &[(0, 0.8), (5000, 0.8)]),
+            (
+                TransitionInterpolation::Custom(easing::CustomEasing::new(Cubed)),
+                &[(0, 0.2), (5000, 0.275), (10_000, 0.8)],
+            ),
+        ];
+        for (interpolation, samples) in cases {
+            let transition = transition(interpolation);
+            for &(millis, expected) in samples {
+                let strength = transition.strength_at(Duration::from_millis(millis));
+                assert!(
+                    (strength.into_inner() - expected).abs() < 1e-9,
+                    "{:?} at {}ms: {:?}",
+                    transition.interpolation,
+                    millis,
+                    strength
+                );
+            }
+        }
+        // Sine eases in, so it's behind linear in the first half.
+        let sine = transition(TransitionInterpolation::Sine);
+        assert!(sine.strength_at(Duration::from_millis(2500)) < Strength::new(0.35));
+    }
+
+    #[test]
+    fn to_and_back_with_half_multiplier() {
+        for interpolation in [
+            TransitionInterpolation::LinearToAndBack(0.5),
+            TransitionInterpolation::SineToAndBack(0.5),
+        ] {
+            let transition = transition(interpolation);
+            // The way back takes half as long as the way there.
+            assert_eq!(transition.total_duration(), Duration::from_secs(15));
+            assert_eq!(transition.strength_at(Duration::ZERO), Strength::new(0.2));
+            assert_eq!(
+                transition.strength_at(Duration::from_secs(10)),
+                Strength::new(0.8)
+            );
+            assert_eq!(
+                transition.strength_at(Duration::from_secs(15)),
+                Strength::new(0.2)
+            );
+            for millis in (0..=20_000).step_by(250) {
+                let strength = transition
+                    .strength_at(Duration::from_millis(millis))
+                    .into_inner();
+                assert!(
+                    (0.2 - 1e-9..=0.8 + 1e-9).contains(&strength),
+                    "{:?} at {}ms: {}",
+                    transition.interpolation,
+                    millis,
+                    strength
+                );
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct Steps;
+    impl easing::Easing for Steps {
+        fn apply(&self, t: f64) -> f64 {
+            (t * 4.0).floor() / 4.0
+        }
+        fn name(&self) -> &str {
+            "steps"
+        }
+    }
+
+    #[test]
+    fn registered_easing_is_parsed() {
+        assert!(easing::register(Steps));
+        let interpolation = TransitionInterpolation::from_str("steps", &[] as &[&str]).unwrap();
+        assert_eq!(interpolation.as_str(), "steps");
+        let transition = transition(interpolation);
+        assert_eq!(
+            transition.strength_at(Duration::from_millis(2000)),
+            Strength::new(0.2)
+        );
+        assert_eq!(
+            transition.strength_at(Duration::from_millis(5500)),
+            Strength::new(0.5)
+        );
+        assert_eq!(
+            transition.strength_at(Duration::from_secs(10)),
+            Strength::new(0.8)
+        );
+        #[cfg(feature = "json")]
+        {
+            let json = serde_json::to_value(&transition).unwrap();
+            let parsed: Transition = serde_json::from_value(json).unwrap();
+            assert_eq!(parsed, transition);
+        }
+
+        assert!(easing::unregister("steps"));
+        assert_eq!(
+            TransitionInterpolation::from_name("steps", None),
+            Err(InterpolationParseError::UnknownName("steps".to_string()))
+        );
+        // The built-in curves can't be replaced.
+        assert!(!easing::register(BuiltIn));
+    }
+
+    #[derive(Debug)]
+    struct BuiltIn;
+    impl easing::Easing for BuiltIn {
+        fn apply(&self, t: f64) -> f64 {
+            t
+        }
+        fn name(&self) -> &str {
+            "sine"
+        }
+    }
+
+    #[test]
+    fn interpolation_extras() {
+        let none: [&str; 0] = [];
+        assert_eq!(
+            TransitionInterpolation::from_str("sine-extra", &none),
+            Err(InterpolationParseError::WrongExtrasCount {
+                expected: 1,
+                got: 0
+            })
+        );
+        assert_eq!(
+            TransitionInterpolation::from_str("linear-extra", &["1", "2"]),
+            Err(InterpolationParseError::WrongExtrasCount {
+                expected: 1,
+                got: 2
+            })
+        );
+        assert_eq!(
+            TransitionInterpolation::from_str("linear-extra", &["half"]),
+            Err(InterpolationParseError::UnparseableMultiplier(
+                "half".to_string()
+            ))
+        );
+        for multiplier in ["0", "-1", "NaN", "0.005"] {
+            assert!(matches!(
+                TransitionInterpolation::from_str("sine-extra", &[multiplier]),
+                Err(InterpolationParseError::MultiplierOutOfRange(_))
+            ));
+        }
+        let too_large = (TO_AND_BACK_MULTIPLIER_MAX * 2.0).to_string();
+        assert_eq!(
+            TransitionInterpolation::from_str("sine-extra", &[too_large]),
+            Err(InterpolationParseError::MultiplierOutOfRange(
+                TO_AND_BACK_MULTIPLIER_MAX * 2.0
+            ))
+        );
+        assert_eq!(
+            TransitionInterpolation::from_str("sine-extra", &["0.5"]),
+            Ok(TransitionInterpolation::SineToAndBack(0.5))
+        );
+        // Extras are ignored by the others.
+        assert_eq!(
+            TransitionInterpolation::from_str("linear", &["0.5"]),
+            Ok(TransitionInterpolation::Linear)
+        );
+    }
+
+    /// Fails the first `failures` times it's set, then records what it's set to.
+    #[derive(Debug, Default)]
+    struct Flaky {
+        failures: u32,
+        attempts: u32,
+        set: Vec<Strength>,
+    }
+    impl VariableOut for Flaky {
+        fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+            self.attempts += 1;
+            if self.attempts <= self.failures {
+                return Err(OutputError::Other("unplugged".into()));
+            }
+            self.set.push(value);
+            Ok(())
+        }
+        fn enable(&mut self) {}
+        fn disable(&mut self) {}
+        fn prepare(&mut self) {}
+    }
+
+    struct Panicking;
+    impl VariableOut for Panicking {
+        fn set(&mut self, _value: Strength) -> Result<(), OutputError> {
+            panic!("the output broke");
+        }
+        fn enable(&mut self) {}
+        fn disable(&mut self) {}
+        fn prepare(&mut self) {}
+    }
+
+    #[test]
+    fn send_to_stopped_controller() {
+        let controller = Controller::new(Panicking, WeekScheduler::empty(Transition::default()));
+        controller.send(Command::Set(Strength::full())).unwrap();
+        for _ in 0..100 {
+            if !controller.is_alive() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!controller.is_alive());
+        assert!(matches!(
+            controller.send(Command::Set(Strength::off())),
+            Err(Error::ControllerStopped(Some(Command::Set(_))))
+        ));
+        assert!(matches!(
+            controller.send(Command::Pause),
+            Err(Error::ControllerStopped(Some(Command::Pause)))
+        ));
+    }
+
+    #[test]
+    fn three_channels_run_independently() {
+        let controller = MultiController::with_channels(
+            [Flaky::default(), Flaky::default(), Flaky::default()],
+            [
+                WeekScheduler::empty(Transition::default()),
+                WeekScheduler::empty(Transition::default()),
+                WeekScheduler::empty(Transition::default()),
+            ],
+        );
+        let fade = |from, to, millis, interpolation| {
+            Transition::new(
+                Strength::new(from),
+                Strength::new(to),
+                Duration::from_millis(millis),
+                interpolation,
+            )
+            .unwrap()
+        };
+        let up = controller.channel(0).unwrap().send_transition(fade(
+            0.0,
+            1.0,
+            100,
+            TransitionInterpolation::Linear,
+        ));
+        let down = controller.channel(2).unwrap().send_transition(fade(
+            0.9,
+            0.1,
+            50,
+            TransitionInterpolation::Sine,
+        ));
+        controller
+            .send_to(1, Command::Set(Strength::new(0.5)))
+            .unwrap();
+        assert!(matches!(
+            controller.send_to(3, Command::Pause),
+            Err(Error::UnknownChannel(3))
+        ));
+
+        let timeout = Duration::from_secs(1);
+        assert_eq!(up.recv_timeout(timeout), Ok(TransitionOutcome::Completed));
+        assert_eq!(down.recv_timeout(timeout), Ok(TransitionOutcome::Completed));
+        assert!(controller.is_alive());
+        for (index, &expected) in [1.0, 0.5, 0.1].iter().enumerate() {
+            let shared = controller.channel(index).unwrap().get_state();
+            assert_eq!(
+                *shared.lock().unwrap().get_strength(),
+                Strength::new(expected)
+            );
+        }
+
+        let [first, second, third] = controller.finish_all();
+        assert_eq!(first.set.last(), Some(&Strength::full()));
+        assert!(first.set.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(second.set, [Strength::new(0.5)]);
+        assert_eq!(third.set.last(), Some(&Strength::new(0.1)));
+        assert!(third.set.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+
+    #[test]
+    fn channels_fire_on_their_own_schedules() {
+        let clock = Arc::new(scheduler::ManualClock::new(
+            NaiveDateTime::parse_from_str("2021-06-02 06:00:00", "%Y-%m-%d %H:%M:%S").unwrap(),
+        ));
+        let controller = MultiController::with_channels_and_clock(
+            [Flaky::default(), Flaky::default()],
+            [
+                WeekScheduler::empty(Transition::default()),
+                WeekScheduler::empty(Transition::default()),
+            ],
+            Arc::clone(&clock) as Arc<dyn scheduler::Clock>,
+        );
+        let wake = |expression, strength| {
+            Box::new(CronScheduler::new(
+                cron::CronSchedule::parse(expression).unwrap(),
+                "wake up".to_string(),
+                ClonableCommand::new(Command::Set(Strength::new(strength))).unwrap(),
+            ))
+        };
+        // The same name on both; they're separate.
+        controller
+            .add_scheduler(0, "wake", wake("30 6 * * *", 0.3))
+            .unwrap();
+        controller
+            .add_scheduler(1, "wake", wake("0 7 * * *", 0.7))
+            .unwrap();
+        assert!(matches!(
+            controller.add_scheduler(2, "wake", wake("0 8 * * *", 1.0)),
+            Err(Error::UnknownChannel(2))
+        ));
+        let strength = |index: usize| {
+            *controller
+                .channel(index)
+                .unwrap()
+                .get_state()
+                .lock()
+                .unwrap()
+                .get_strength()
+        };
+        let wait_for = |index, expected| {
+            for _ in 0..100 {
+                if strength(index) == expected {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            panic!("channel {} never reached {:?}", index, expected);
+        };
+
+        // Waits for the schedulers to be added before moving the clock.
+        for channel in controller.channels() {
+            let schedulers = channel.send_list_schedulers();
+            assert_eq!(
+                schedulers
+                    .recv_timeout(Duration::from_secs(1))
+                    .unwrap()
+                    .len(),
+                1
+            );
+        }
+        clock.advance(chrono::Duration::minutes(31));
+        wait_for(0, Strength::new(0.3));
+        assert_eq!(strength(1), Strength::off());
+
+        clock.advance(chrono::Duration::minutes(30));
+        wait_for(1, Strength::new(0.7));
+        assert_eq!(strength(0), Strength::new(0.3));
+
+        let [first, second] = controller.finish_all();
+        assert_eq!(first.set, [Strength::new(0.3)]);
+        assert_eq!(second.set, [Strength::new(0.7)]);
+    }
+
+    #[test]
+    fn panic_in_one_channel() {
+        let controller = MultiController::with_channels(
+            [Panicking, Panicking],
+            [
+                WeekScheduler::empty(Transition::default()),
+                WeekScheduler::empty(Transition::default()),
+            ],
+        );
+        assert!(controller.is_alive());
+        controller
+            .send_to(0, Command::Set(Strength::full()))
+            .unwrap();
+        for _ in 0..100 {
+            if !controller.is_alive() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!controller.is_alive());
+        assert!(matches!(
+            controller.send_to(0, Command::Pause),
+            Err(Error::ControllerStopped(Some(Command::Pause)))
+        ));
+        // The other channel runs on.
+        let paused = controller.channel(1).unwrap().send_pause();
+        assert_eq!(paused.recv_timeout(Duration::from_secs(1)), Ok(true));
+    }
+
+    /// Panics when setting the color. Every output made shares `set`.
+    struct Fragile {
+        set: Arc<Mutex<Vec<Strength>>>,
+    }
+    impl VariableOut for Fragile {
+        fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+            self.set.lock().unwrap().push(value);
+            Ok(())
+        }
+        fn set_rgb(
+            &mut self,
+            _red: Strength,
+            _green: Strength,
+            _blue: Strength,
+        ) -> Result<(), OutputError> {
+            panic!("the output broke");
+        }
+        fn enable(&mut self) {}
+        fn disable(&mut self) {}
+        fn prepare(&mut self) {}
+    }
+
+    #[test]
+    fn supervised_controller_restarts() {
+        let set = Arc::new(Mutex::new(Vec::new()));
+        let made = Arc::new(AtomicU32::new(0));
+        let factory = {
+            let set = Arc::clone(&set);
+            let made = Arc::clone(&made);
+            move || {
+                made.fetch_add(1, Ordering::Relaxed);
+                Fragile {
+                    set: Arc::clone(&set),
+                }
+            }
+        };
+        let controller =
+            SupervisedController::new(factory, WeekScheduler::empty(Transition::default()));
+        let slow = Transition::new(
+            Strength::new(0.2),
+            Strength::new(0.8),
+            Duration::from_secs(60),
+            TransitionInterpolation::Linear,
+        )
+        .unwrap();
+        controller
+            .send(Command::SetBlendMode(BlendMode::Sum))
+            .unwrap();
+        controller
+            .send(Command::SetLayer("night".to_string(), Strength::new(0.1)))
+            .unwrap();
+        controller
+            .send(Command::SetTransition(slow.clone()))
+            .unwrap();
+        let timeout = Duration::from_secs(1);
+        assert_eq!(controller.send_pause().recv_timeout(timeout), Ok(true));
+
+        controller
+            .send(Command::SetRgb(
+                Strength::full(),
+                Strength::off(),
+                Strength::off(),
+            ))
+            .unwrap();
+        for _ in 0..100 {
+            if controller.restarts() > 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(controller.restarts(), 1);
+        assert_eq!(made.load(Ordering::Relaxed), 2);
+        assert!(controller.is_alive());
+
+        // The paused transition is back where it was.
+        let status = controller.send_get_status().recv_timeout(timeout).unwrap();
+        assert!(status.paused);
+        assert_eq!(status.transition, Some(slow));
+        assert!(status.transition_progress.unwrap() < 0.1);
+        assert_eq!(controller.send_resume().recv_timeout(timeout), Ok(true));
+
+        // The layer is still added.
+        controller.send(Command::Set(Strength::new(0.5))).unwrap();
+        let output = controller.finish();
+        let last = *output.set.lock().unwrap().last().unwrap();
+        assert!((last.into_inner() - 0.6).abs() < 1e-9, "{:?}", last);
+    }
+
+    #[test]
+    fn command_display() {
+        assert_eq!(Command::Set(Strength::new(0.5)).to_string(), "Set 50%");
+        assert_eq!(
+            Command::ChangeDayTimer(Weekday::Mon, NaiveTime::from_hms_opt(7, 30, 0)).to_string(),
+            "ChangeDayTimer(Mon -> 07:30:00)"
+        );
+        assert_eq!(
+            Command::SetTransition(transition(TransitionInterpolation::Sine)).to_string(),
+            "SetTransition(20% -> 80% over 10s, sine)"
+        );
+        let (sender, _) = mpsc::channel();
+        assert_eq!(Command::GetStatus(sender).to_string(), "GetStatus");
+    }
+
+    #[test]
+    fn off_and_full() {
+        assert_eq!(Strength::off().into_inner(), 0.0);
+        assert_eq!(Strength::full().into_inner(), 1.0);
+        assert_eq!(Strength::off(), Strength::new(0.0));
+        assert_eq!(Strength::full(), Strength::new(1.0));
+    }
+
+    #[test]
+    fn strength_try_new() {
+        for value in [0.0, 0.25, 1.0] {
+            assert_eq!(Strength::try_new(value), Ok(Strength::new(value)));
+        }
+        assert_eq!(Strength::try_new(f64::NAN), Err(StrengthError::NaN));
+        assert_eq!(
+            Strength::try_new(-0.1),
+            Err(StrengthError::OutOfRange(-0.1))
+        );
+        assert_eq!(Strength::try_new(1.1), Err(StrengthError::OutOfRange(1.1)));
+        assert_eq!(
+            Strength::try_new(f64::INFINITY),
+            Err(StrengthError::OutOfRange(f64::INFINITY))
+        );
+
+        assert_eq!(Strength::new_clamped(f64::NAN), Strength::off());
+        assert_eq!(Strength::new_clamped(-0.1), Strength::off());
+        assert_eq!(Strength::new_clamped(1.1), Strength::full());
+        assert_eq!(Strength::new_clamped(0.25), Strength::new(0.25));
+    }
+
+    #[test]
+    fn error_conversions() {
+        fn strength(value: f64) -> Result<Strength, Error> {
+            Ok(Strength::try_new(value)?)
+        }
+        assert!(matches!(
+            strength(f64::NAN),
+            Err(Error::Strength(StrengthError::NaN))
+        ));
+        assert!(matches!(strength(0.5), Ok(s) if s == Strength::new(0.5)));
+
+        let (sender, receiver) = mpsc::channel::<()>();
+        let timeout = Duration::from_millis(1);
+        let err = Error::from(receiver.recv_timeout(timeout).unwrap_err());
+        assert!(matches!(err, Error::Timeout));
+        drop(sender);
+        let err = Error::from(receiver.recv_timeout(timeout).unwrap_err());
+        assert!(matches!(err, Error::ControllerStopped(None)));
+
+        let err = Error::from(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert!(matches!(err, Error::Io(err) if err.kind() == std::io::ErrorKind::NotFound));
+    }
+
+    /// Records the level and message of every event.
+    #[derive(Default)]
+    struct Capture(Arc<Mutex<Vec<(tracing::Level, String)>>>);
+    impl tracing::Subscriber for Capture {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, _span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            tracing::span::Id::from_u64(1)
+        }
+        fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) {}
+        fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+        fn event(&self, event: &tracing::Event<'_>) {
+            struct Message<'a>(&'a mut String);
+            impl tracing::field::Visit for Message<'_> {
+                fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+                    if field.name() == "message" {
+                        *self.0 = format!("{:?}", value);
                     }
-                    Action::Break => break,
                 }
             }
-            output
+            let mut message = String::new();
+            event.record(&mut Message(&mut message));
+            self.0
+                .lock()
+                .unwrap()
+                .push((*event.metadata().level(), message));
+        }
+        fn enter(&self, _span: &tracing::span::Id) {}
+        fn exit(&self, _span: &tracing::span::Id) {}
+    }
+
+    #[test]
+    fn zero_time_is_rejected() {
+        let new = |interpolation| {
+            Transition::new(
+                Strength::off(),
+                Strength::full(),
+                Duration::new(0, 0),
+                interpolation,
+            )
+        };
+        assert_eq!(
+            new(TransitionInterpolation::Linear),
+            Err(TransitionError::ZeroTime)
+        );
+        assert_eq!(
+            new(TransitionInterpolation::SineToAndBack(1.0)),
+            Err(TransitionError::ZeroTime)
+        );
+        // It's instant anyway.
+        assert!(new(TransitionInterpolation::Constant).is_ok());
+    }
+
+    #[test]
+    fn equal_endpoints_warn() {
+        let capture = Capture::default();
+        let events = Arc::clone(&capture.0);
+        let transition = tracing::subscriber::with_default(capture, || {
+            Transition::new(
+                Strength::new(0.4),
+                Strength::new(0.4),
+                Duration::from_secs(1),
+                TransitionInterpolation::Linear,
+            )
         });
-        // spawn thread, moving `pwm`
-        // return Self with the channel and JoinHandle
-        Self {
-            channel: sender,
-            handle,
-            shared_state,
+        assert_eq!(
+            transition.unwrap().strength_at_progress(0.5),
+            Strength::new(0.4)
+        );
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].0, tracing::Level::WARN);
+    }
+
+    #[test]
+    fn print_out_traces_sets() {
+        let capture = Capture::default();
+        let events = Arc::clone(&capture.0);
+        tracing::subscriber::with_default(capture, || {
+            PrintOut.set(Strength::new(0.5)).unwrap();
+        });
+        let events = events.lock().unwrap();
+        assert_eq!(
+            *events,
+            [(
+                tracing::Level::DEBUG,
+                "Got strength Strength(0.5)".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn failing_output_is_survived() {
+        let output = Flaky {
+            failures: OUTPUT_ATTEMPTS + 2,
+            ..Default::default()
+        };
+        let controller = Controller::new(output, WeekScheduler::empty(Transition::default()));
+        // Fails every attempt, so it's skipped.
+        controller.send(Command::Set(Strength::new(0.3))).unwrap();
+        // Fails twice, then succeeds.
+        controller.send(Command::Set(Strength::new(0.7))).unwrap();
+        let output = controller.finish();
+        assert_eq!(output.attempts, OUTPUT_ATTEMPTS + 3);
+        assert_eq!(output.set, vec![Strength::new(0.7)]);
+    }
+
+    #[test]
+    fn subscribers_get_every_set() {
+        let controller = Controller::new(
+            Flaky::default(),
+            WeekScheduler::empty(Transition::default()),
+        );
+        let first = controller.subscribe();
+        let second = controller.subscribe();
+        let gone = controller.subscribe();
+        drop(gone);
+
+        controller.send(Command::Set(Strength::new(0.4))).unwrap();
+        let timeout = Duration::from_secs(1);
+        assert_eq!(first.recv_timeout(timeout), Ok(Strength::new(0.4)));
+        assert_eq!(second.recv_timeout(timeout), Ok(Strength::new(0.4)));
+        // The one which hung up is dropped.
+        assert_eq!(controller.subscribers.lock().unwrap().len(), 2);
+
+        drop(second);
+        controller.send(Command::Set(Strength::full())).unwrap();
+        assert_eq!(first.recv_timeout(timeout), Ok(Strength::full()));
+        controller.finish();
+    }
+
+    #[test]
+    fn update_interval_bounds_sets() {
+        let controller = Controller::new(
+            Flaky::default(),
+            WeekScheduler::empty(Transition::default()),
+        );
+        controller
+            .send(Command::SetUpdateInterval(Duration::from_millis(50)))
+            .unwrap();
+        controller
+            .send(Command::SetTransition(
+                Transition::new(
+                    Strength::off(),
+                    Strength::full(),
+                    Duration::from_millis(500),
+                    TransitionInterpolation::Linear,
+                )
+                .unwrap(),
+            ))
+            .unwrap();
+        let output = controller.finish();
+        // One every 50ms over 500ms, plus the last one. Never more, fewer if the thread lags.
+        assert!(
+            (6..=12).contains(&output.set.len()),
+            "{} sets",
+            output.set.len()
+        );
+        assert_eq!(output.set.last(), Some(&Strength::full()));
+    }
+
+    #[test]
+    fn transition_completes_once() {
+        let controller = Controller::new(
+            Flaky::default(),
+            WeekScheduler::empty(Transition::default()),
+        );
+        let short = Transition::new(
+            Strength::off(),
+            Strength::full(),
+            Duration::from_millis(50),
+            TransitionInterpolation::Linear,
+        )
+        .unwrap();
+        let completed = controller.send_transition(short.clone());
+        let timeout = Duration::from_secs(1);
+        assert_eq!(
+            completed.recv_timeout(timeout),
+            Ok(TransitionOutcome::Completed)
+        );
+        let cancelled = controller.send_transition(short);
+        controller.send(Command::Set(Strength::off())).unwrap();
+        assert_eq!(
+            cancelled.recv_timeout(timeout),
+            Ok(TransitionOutcome::Cancelled)
+        );
+        controller.finish();
+        // Nothing more once the callbacks are resolved.
+        assert!(completed.try_recv().is_err());
+        assert!(cancelled.try_recv().is_err());
+    }
+
+    #[test]
+    fn dry_run_leaves_output_alone() {
+        let controller = Controller::new(
+            Flaky::default(),
+            WeekScheduler::empty(Transition::default()),
+        );
+        controller.send(Command::SetDryRun(true)).unwrap();
+        controller.send(Command::Set(Strength::new(0.5))).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        controller.send(Command::GetStatus(sender)).unwrap();
+        let status = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(status.dry_run);
+        assert_eq!(status.strength, Strength::new(0.5));
+        assert_eq!(controller.finish().set, []);
+    }
+
+    #[test]
+    fn burst_of_sets_is_coalesced() {
+        let controller = Controller::new(
+            Flaky::default(),
+            WeekScheduler::empty(Transition::default()),
+        );
+        controller.set_rate_limit(Some(Duration::from_millis(200)));
+        for value in [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9] {
+            controller.send(Command::Set(Strength::new(value))).unwrap();
         }
+        std::thread::sleep(Duration::from_millis(400));
+        // The first goes through at once, the last when the window has passed.
+        assert_eq!(
+            controller.finish().set,
+            [Strength::new(0.1), Strength::new(0.9)]
+        );
     }
 
-    pub fn send(&self, command: Command) {
-        match &command {
-            Command::Set(_) => {
-                let _ = self.channel.try_send(command);
+    #[test]
+    fn coalesced_set_to_stopped_controller() {
+        let controller = Controller::new(Panicking, WeekScheduler::empty(Transition::default()));
+        controller.set_rate_limit(Some(Duration::from_secs(60)));
+        controller.send(Command::Set(Strength::full())).unwrap();
+        for _ in 0..100 {
+            if !controller.is_alive() {
+                break;
             }
-            _ => self
-                .channel
-                .send(command)
-                .expect("failed to send message on channel"),
+            thread::sleep(Duration::from_millis(10));
         }
+        assert!(!controller.channel(0).unwrap().is_alive());
+        // Within the window, but it would never be sent.
+        assert!(matches!(
+            controller.send(Command::Set(Strength::off())),
+            Err(Error::ControllerStopped(Some(Command::Set(_))))
+        ));
     }
 
-    /// Will wait on any transitions to conclude and then give back the underlying object
-    pub fn finish(self) -> T {
-        self.send(Command::Finish);
-        self.handle.join().expect("child thread paniced")
+    /// Records the name of every callback, with the strength or where the transition goes.
+    #[derive(Debug, Default, Clone)]
+    struct Recording(Arc<Mutex<Vec<String>>>);
+    impl EventListener for Recording {
+        fn on_set(&self, strength: Strength) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("set {}", strength.into_inner()));
+        }
+        fn on_transition_start(&self, transition: &Transition) {
+            let to = transition.to.into_inner();
+            self.0.lock().unwrap().push(format!("start {}", to));
+        }
+        fn on_transition_complete(&self, transition: &Transition) {
+            let to = transition.to.into_inner();
+            self.0.lock().unwrap().push(format!("complete {}", to));
+        }
     }
 
-    /// Gets a reference counted [`SharedState`]
-    /// The value should not be mutated, since it'll be overriden by the other thread.
-    pub fn get_state(&self) -> Arc<Mutex<SharedState>> {
-        Arc::clone(&self.shared_state)
+    #[test]
+    fn listeners_in_order() {
+        let controller = Controller::new(
+            Flaky::default(),
+            WeekScheduler::empty(Transition::default()),
+        );
+        let recording = Recording::default();
+        controller.add_listener(recording.clone());
+        let outcome = controller.send_transition(
+            Transition::new(
+                Strength::off(),
+                Strength::full(),
+                Duration::from_millis(50),
+                TransitionInterpolation::Linear,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            outcome.recv_timeout(Duration::from_secs(1)),
+            Ok(TransitionOutcome::Completed)
+        );
+        controller.finish();
+
+        let events = recording.0.lock().unwrap();
+        assert_eq!(events.first().map(String::as_str), Some("start 1"));
+        // The last strength is set before the transition completes.
+        assert_eq!(&events[events.len() - 2..], ["set 1", "complete 1"]);
+        assert!(events[1..events.len() - 1]
+            .iter()
+            .all(|event| event.starts_with("set ")));
+    }
+
+    #[test]
+    fn delete_scheduler() {
+        let controller = Controller::new(
+            Flaky::default(),
+            WeekScheduler::empty(Transition::default()),
+        );
+        let scheduler = CronScheduler::new(
+            cron::CronSchedule::parse("0 7 * * *").unwrap(),
+            "morning".to_string(),
+            ClonableCommand::new(Command::Set(Strength::full())).unwrap(),
+        );
+        controller
+            .send(Command::AddReplaceScheduler(
+                "morning".to_string(),
+                Box::new(scheduler),
+            ))
+            .unwrap();
+        let timeout = Duration::from_secs(1);
+        let deleted = controller.send_remove_scheduler("morning".to_string());
+        assert_eq!(deleted.recv_timeout(timeout), Ok(true));
+        let deleted = controller.send_remove_scheduler("morning".to_string());
+        assert_eq!(deleted.recv_timeout(timeout), Ok(false));
+        assert!(controller
+            .get_state()
+            .lock()
+            .unwrap()
+            .ref_schedulers()
+            .is_empty());
+        controller.finish();
+    }
+
+    #[test]
+    fn broadcast_reaches_all() {
+        let mut group = ControllerGroup::new();
+        for name in ["kitchen", "hall"] {
+            let controller = Controller::new(
+                Flaky::default(),
+                WeekScheduler::empty(Transition::default()),
+            );
+            assert!(group.insert(name, controller).is_none());
+        }
+        group
+            .broadcast(ClonableCommand::new(Command::Set(Strength::new(0.4))).unwrap())
+            .unwrap();
+        group
+            .send("hall", Command::Set(Strength::new(0.6)))
+            .unwrap();
+        assert!(matches!(
+            group.send("attic", Command::Set(Strength::full())),
+            Err(Error::UnknownController(name)) if name == "attic"
+        ));
+
+        let outputs = group.finish();
+        let names: Vec<_> = outputs.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["kitchen", "hall"]);
+        assert_eq!(outputs[0].1.set, [Strength::new(0.4)]);
+        assert_eq!(outputs[1].1.set, [Strength::new(0.4), Strength::new(0.6)]);
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn status_json() {
+        let week = WeekScheduler::builder(Transition::default())
+            .day(Weekday::Mon, NaiveTime::from_hms_opt(7, 0, 0).unwrap())
+            .build();
+        let controller = Controller::new(Flaky::default(), week);
+        let scheduler = CronScheduler::new(
+            cron::CronSchedule::parse("0 7 * * *").unwrap(),
+            "morning".to_string(),
+            ClonableCommand::new(Command::Set(Strength::full())).unwrap(),
+        );
+        controller
+            .send(Command::AddReplaceScheduler(
+                "morning".to_string(),
+                Box::new(scheduler),
+            ))
+            .unwrap();
+        controller.send(Command::Set(Strength::new(0.4))).unwrap();
+        let timeout = Duration::from_secs(1);
+        assert_eq!(controller.send_pause().recv_timeout(timeout), Ok(true));
+
+        let status = controller.send_get_status().recv_timeout(timeout).unwrap();
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["strength"], 0.4);
+        assert!(json["transition"].is_null());
+        assert!(json["transition_progress"].is_null());
+        assert_eq!(json["week_scheduler"]["mon"], "07:00:00");
+        assert!(json["week_scheduler"]["tue"].is_null());
+        assert_eq!(json["schedulers"][0]["name"], "morning");
+        assert_eq!(json["schedulers"][0]["enabled"], true);
+        assert_eq!(json["paused"], true);
+        assert_eq!(json["dry_run"], false);
+        controller.finish();
+    }
+
+    #[test]
+    fn pause_resume_cycle() {
+        let controller = Controller::new(
+            Flaky::default(),
+            WeekScheduler::empty(Transition::default()),
+        );
+        let timeout = Duration::from_secs(1);
+        let paused = || {
+            let status = controller.send_get_status().recv_timeout(timeout).unwrap();
+            status.paused
+        };
+        assert!(!paused());
+        // `false` is what the endpoints answer with 409.
+        assert_eq!(controller.send_resume().recv_timeout(timeout), Ok(false));
+        assert_eq!(controller.send_pause().recv_timeout(timeout), Ok(true));
+        assert!(paused());
+        assert_eq!(controller.send_pause().recv_timeout(timeout), Ok(false));
+        assert_eq!(controller.send_resume().recv_timeout(timeout), Ok(true));
+        assert!(!paused());
+        controller.finish();
+    }
+
+    #[test]
+    fn day_transition_reads_back() {
+        let controller = Controller::new(
+            Flaky::default(),
+            WeekScheduler::empty(Transition::default()),
+        );
+        let fade = transition(TransitionInterpolation::SineToAndBack(0.5));
+        controller
+            .send(Command::ChangeDayTimerTransition(fade.clone()))
+            .unwrap();
+        let timeout = Duration::from_secs(1);
+        let read = controller
+            .send_get_day_transition()
+            .recv_timeout(timeout)
+            .unwrap();
+        assert_eq!(read, fade);
+        #[cfg(feature = "json")]
+        {
+            let json = serde_json::to_value(&read).unwrap();
+            assert_eq!(serde_json::from_value::<Transition>(json).unwrap(), fade);
+        }
+        controller.finish();
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn autosave_after_change() {
+        let path = std::env::temp_dir().join(format!("httpwm-autosave-{}.ron", std::process::id()));
+        let controller = Controller::new(
+            Flaky::default(),
+            WeekScheduler::empty(Transition::default()),
+        );
+        let saver = controller.autosave_week_scheduler(&path, Duration::from_millis(10));
+        let time = NaiveTime::from_hms_opt(6, 45, 0).unwrap();
+        controller
+            .send(Command::ChangeDayTimer(Weekday::Wed, Some(time)))
+            .unwrap();
+
+        let mut saved = None;
+        for _ in 0..100 {
+            thread::sleep(Duration::from_millis(10));
+            if let Ok(scheduler) = WeekScheduler::load(&path) {
+                saved = Some(scheduler);
+                break;
+            }
+        }
+        let saved = saved.expect("the schedule wasn't saved");
+        assert_eq!(saved.get(Weekday::Wed), &Some(time));
+        assert_eq!(
+            &saved,
+            controller.get_state().lock().unwrap().ref_week_schedule()
+        );
+
+        controller.finish();
+        saver.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
     }
 }
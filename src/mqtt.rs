@@ -0,0 +1,211 @@
+//! Publishing the output to MQTT.
+//!
+//! [`MqttOutput`] works with anything implementing [`MqttPublish`].
+//! [`MqttConnection`] is a minimal client publishing with QoS 0,
+//! so no MQTT library is needed for the common case.
+
+use crate::{OutputError, Strength, VariableOut};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+/// Something which can publish MQTT messages.
+///
+/// Implement this for your MQTT client of choice to use it with [`MqttOutput`].
+pub trait MqttPublish: Send {
+    fn publish(&mut self, topic: &str, payload: &[u8]) -> io::Result<()>;
+}
+
+/// A MQTT 3.1.1 connection which publishes with QoS 0.
+///
+/// It doesn't send keep alives, so the broker never disconnects it for being idle.
+/// It doesn't reconnect either; errors are returned from [`MqttPublish::publish()`].
+#[derive(Debug)]
+pub struct MqttConnection {
+    stream: TcpStream,
+}
+impl MqttConnection {
+    /// Connects with a clean session and waits for the broker to accept.
+    pub fn connect<A: ToSocketAddrs>(address: A, client_id: &str) -> io::Result<Self> {
+        let mut stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+        let mut body = Vec::with_capacity(12 + client_id.len());
+        write_string(&mut body, "MQTT");
+        // protocol level 4 (3.1.1), clean session, keep alive disabled
+        body.extend_from_slice(&[4, 0x02, 0, 0]);
+        write_string(&mut body, client_id);
+        stream.write_all(&packet(0x10, &body))?;
+
+        let mut connack = [0; 4];
+        stream.read_exact(&mut connack)?;
+        match connack {
+            [0x20, 2, _, 0] => Ok(Self { stream }),
+            [0x20, 2, _, code] => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("broker refused connection, code {}", code),
+            )),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected CONNACK from broker",
+            )),
+        }
+    }
+}
+impl MqttPublish for MqttConnection {
+    fn publish(&mut self, topic: &str, payload: &[u8]) -> io::Result<()> {
+        let mut body = Vec::with_capacity(2 + topic.len() + payload.len());
+        write_string(&mut body, topic);
+        body.extend_from_slice(payload);
+        self.stream.write_all(&packet(0x30, &body))
+    }
+}
+
+fn write_string(buffer: &mut Vec<u8>, string: &str) {
+    buffer.extend_from_slice(&(string.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(string.as_bytes());
+}
+/// Prepends the fixed header with the variable length encoded remaining length.
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(body.len() + 5);
+    packet.push(kind);
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// Publishes the strength as a percentage, e.g. `"42"`, to a topic.
+///
+/// Publishing happens on a separate thread, at most once every `interval`,
+/// so transitions don't flood the broker. The latest value is always published eventually.
+/// Failures are logged.
+#[derive(Debug)]
+pub struct MqttOutput {
+    sender: Option<mpsc::Sender<Strength>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+impl MqttOutput {
+    pub fn new<C: MqttPublish + 'static>(mut client: C, topic: String, interval: Duration) -> Self {
+        let (sender, receiver) = mpsc::channel::<Strength>();
+        let handle = thread::spawn(move || {
+            let mut last = None;
+            // Ends when the sender is dropped, after publishing the last value.
+            while let Ok(mut value) = receiver.recv() {
+                // Skip to the newest
+                while let Ok(newer) = receiver.try_recv() {
+                    value = newer;
+                }
+                let payload = Self::payload(value);
+                if last.as_ref() != Some(&payload) {
+                    if let Err(err) = client.publish(&topic, payload.as_bytes()) {
+                        warn!("Failed to publish to MQTT: {}", err);
+                    }
+                    last = Some(payload);
+                }
+                thread::sleep(interval);
+            }
+        });
+        Self {
+            sender: Some(sender),
+            handle: Some(handle),
+        }
+    }
+    /// The payload published for `value`.
+    pub fn payload(value: Strength) -> String {
+        format!("{}", (value.into_inner() * 100.0).round() as u8)
+    }
+}
+impl VariableOut for MqttOutput {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        if let Some(sender) = self.sender.as_ref() {
+            // Only fails if the thread panicked, which it has already told about.
+            let _ = sender.send(value);
+        }
+        Ok(())
+    }
+    fn enable(&mut self) {}
+    fn disable(&mut self) {}
+    fn prepare(&mut self) {}
+}
+impl Drop for MqttOutput {
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Records what's published, failing for the payload `"50"`.
+    #[derive(Debug, Clone, Default)]
+    struct MockClient(Arc<Mutex<Vec<(String, String)>>>);
+    impl MqttPublish for MockClient {
+        fn publish(&mut self, topic: &str, payload: &[u8]) -> io::Result<()> {
+            let payload = String::from_utf8(payload.to_vec()).unwrap();
+            if payload == "50" {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "broker is gone"));
+            }
+            self.0.lock().unwrap().push((topic.to_string(), payload));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn publishes_percentages() {
+        assert_eq!(MqttOutput::payload(Strength::off()), "0");
+        assert_eq!(MqttOutput::payload(Strength::new(0.424)), "42");
+        assert_eq!(MqttOutput::payload(Strength::full()), "100");
+
+        let client = MockClient::default();
+        let mut output = MqttOutput::new(
+            client.clone(),
+            "home/light".to_string(),
+            Duration::from_millis(20),
+        );
+        output.set(Strength::new(0.5)).unwrap();
+        thread::sleep(Duration::from_millis(5));
+        // Sent during the interval, so only the last is published.
+        for value in [0.1, 0.2, 0.3] {
+            output.set(Strength::new(value)).unwrap();
+        }
+        thread::sleep(Duration::from_millis(50));
+        output.set(Strength::new(0.3)).unwrap();
+        output.set(Strength::full()).unwrap();
+        drop(output);
+
+        let published = client.0.lock().unwrap();
+        let payloads: Vec<&str> = published
+            .iter()
+            .map(|(topic, payload)| {
+                assert_eq!(topic, "home/light");
+                payload.as_str()
+            })
+            .collect();
+        // The failure is logged, and the values sent during the interval are skipped.
+        // How many of the rest are published depends on the timing, but never the same twice.
+        assert_eq!(payloads.last(), Some(&"100"));
+        assert!(payloads.len() <= 2);
+        assert!(payloads
+            .iter()
+            .all(|payload| *payload == "30" || *payload == "100"));
+    }
+}
@@ -0,0 +1,1558 @@
+//! [`VariableOut`]s built on top of other outputs.
+
+use crate::{get_naive_now, OutputError, Strength, StrengthError, VariableOut};
+use rppal::gpio::OutputPin;
+use rppal::pwm::Pwm;
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+/// The pulse width giving a duty cycle of `value` with `period`.
+pub fn pulse_width(period: Duration, value: Strength) -> Duration {
+    period.mul_f64(value.0)
+}
+pub(crate) fn set_pulse_width(
+    pwm: &mut Pwm,
+    period: Duration,
+    value: Strength,
+) -> Result<(), OutputError> {
+    pwm.set_pulse_width(pulse_width(period, value))?;
+    thread::sleep(Duration::from_millis(10));
+    Ok(())
+}
+pub(crate) fn prepare_period(pwm: &mut Pwm, period: Duration) {
+    pwm.set_pulse_width(Duration::new(0, 0))
+        .expect("failed to set pulse width to guarantee period");
+    pwm.set_period(period)
+        .expect("failed to set period in `prepare()`");
+}
+
+/// Hardware PWM with a configurable period.
+/// The [`VariableOut`] impl of [`Pwm`] uses [`crate::DEFAULT_PWM_PERIOD`].
+///
+/// The period is set in [`VariableOut::prepare()`], which the controller calls when starting.
+#[derive(Debug)]
+pub struct PwmOutput {
+    pwm: Pwm,
+    period: Duration,
+}
+impl PwmOutput {
+    pub fn new(pwm: Pwm, period: Duration) -> Self {
+        Self { pwm, period }
+    }
+    /// # Panics
+    ///
+    /// Panics if `frequency` isn't positive.
+    pub fn with_frequency(pwm: Pwm, frequency: f64) -> Self {
+        assert!(frequency > 0.0, "frequency must be positive");
+        Self::new(pwm, Duration::from_secs_f64(1.0 / frequency))
+    }
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+    pub fn into_inner(self) -> Pwm {
+        self.pwm
+    }
+}
+impl VariableOut for PwmOutput {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        set_pulse_width(&mut self.pwm, self.period, value)
+    }
+    fn enable(&mut self) {
+        info!("Enabling hardware PWM.");
+        self.pwm.enable().expect("failed to enable hardware PWM");
+    }
+    fn disable(&mut self) {
+        info!("Disabling hardware PWM.");
+        self.pwm.disable().expect("failed to disable hardware PWM");
+    }
+    fn prepare(&mut self) {
+        prepare_period(&mut self.pwm, self.period)
+    }
+}
+
+/// rppal's software PWM on a GPIO pin, with a configurable period.
+/// The [`VariableOut`] impl of [`OutputPin`] uses [`crate::DEFAULT_PWM_PERIOD`].
+#[derive(Debug)]
+pub struct PinPwmOutput {
+    pin: OutputPin,
+    period: Duration,
+}
+impl PinPwmOutput {
+    pub fn new(pin: OutputPin, period: Duration) -> Self {
+        Self { pin, period }
+    }
+    /// # Panics
+    ///
+    /// Panics if `frequency` isn't positive.
+    pub fn with_frequency(pin: OutputPin, frequency: f64) -> Self {
+        assert!(frequency > 0.0, "frequency must be positive");
+        Self::new(pin, Duration::from_secs_f64(1.0 / frequency))
+    }
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+    pub fn into_inner(self) -> OutputPin {
+        self.pin
+    }
+}
+impl VariableOut for PinPwmOutput {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        self.pin
+            .set_pwm(self.period, pulse_width(self.period, value))?;
+        Ok(())
+    }
+    fn enable(&mut self) {}
+    fn disable(&mut self) {
+        self.pin.clear_pwm().expect("failed to stop software PWM")
+    }
+    fn prepare(&mut self) {}
+}
+
+/// Three outputs driving the channels of an RGB strip.
+///
+/// Transitions and [`VariableOut::set()`] control the brightness.
+/// Each channel is set to the brightness times its part of the color, which is set by
+/// [`crate::Command::SetRgb`] through [`VariableOut::set_rgb()`]. The color starts out white.
+///
+/// Use [`RgbOutput::set_channels()`] to set the channels directly.
+#[derive(Debug)]
+pub struct RgbOutput<R, G, B> {
+    red: R,
+    green: G,
+    blue: B,
+    color: (Strength, Strength, Strength),
+    brightness: Strength,
+}
+impl<R: VariableOut, G: VariableOut, B: VariableOut> RgbOutput<R, G, B> {
+    pub fn new(red: R, green: G, blue: B) -> Self {
+        Self {
+            red,
+            green,
+            blue,
+            color: (Strength::full(), Strength::full(), Strength::full()),
+            brightness: Strength::off(),
+        }
+    }
+    /// Sets each channel, ignoring the brightness and color.
+    /// They are overridden by the next call to [`VariableOut::set()`].
+    pub fn set_channels(
+        &mut self,
+        red: Strength,
+        green: Strength,
+        blue: Strength,
+    ) -> Result<(), OutputError> {
+        self.red.set(red)?;
+        self.green.set(green)?;
+        self.blue.set(blue)
+    }
+    pub fn color(&self) -> (Strength, Strength, Strength) {
+        self.color
+    }
+    pub fn into_inner(self) -> (R, G, B) {
+        (self.red, self.green, self.blue)
+    }
+
+    fn apply(&mut self) -> Result<(), OutputError> {
+        let brightness = self.brightness.0;
+        let (red, green, blue) = self.color;
+        self.set_channels(
+            Strength::new_clamped(red.0 * brightness),
+            Strength::new_clamped(green.0 * brightness),
+            Strength::new_clamped(blue.0 * brightness),
+        )
+    }
+}
+impl<R: VariableOut, G: VariableOut, B: VariableOut> VariableOut for RgbOutput<R, G, B> {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        self.brightness = value;
+        self.apply()
+    }
+    fn set_rgb(
+        &mut self,
+        red: Strength,
+        green: Strength,
+        blue: Strength,
+    ) -> Result<(), OutputError> {
+        self.color = (red, green, blue);
+        self.apply()
+    }
+    fn enable(&mut self) {
+        self.red.enable();
+        self.green.enable();
+        self.blue.enable();
+    }
+    fn disable(&mut self) {
+        self.red.disable();
+        self.green.disable();
+        self.blue.disable();
+    }
+    fn prepare(&mut self) {
+        self.red.prepare();
+        self.green.prepare();
+        self.blue.prepare();
+    }
+}
+
+/// Moves `warmth` into `[0, 1]`. NaN is taken as `0`, like [`Strength::new_clamped()`].
+pub(crate) fn clamp_warmth(warmth: f64) -> f64 {
+    match warmth.is_nan() {
+        true => 0.0,
+        false => warmth.clamp(0.0, 1.0),
+    }
+}
+
+/// A tunable white fixture with a warm and a cool channel.
+///
+/// The brightness from [`VariableOut::set()`] is split between the channels by the warmth
+/// from [`VariableOut::set_warmth()`]; `1` is only the warm channel and `0` only the cool one.
+/// The channels always add up to the brightness, so the light doesn't get brighter or dimmer
+/// when only the warmth changes. The warmth defaults to `0.5`.
+#[derive(Debug)]
+pub struct ColorTempOutput<W, C> {
+    warm: W,
+    cool: C,
+    warmth: f64,
+    brightness: Strength,
+}
+impl<W: VariableOut, C: VariableOut> ColorTempOutput<W, C> {
+    pub fn new(warm: W, cool: C) -> Self {
+        Self {
+            warm,
+            cool,
+            warmth: 0.5,
+            brightness: Strength::off(),
+        }
+    }
+    pub fn warmth(&self) -> f64 {
+        self.warmth
+    }
+    /// The strengths of the warm and cool channel.
+    pub fn split(brightness: Strength, warmth: f64) -> (Strength, Strength) {
+        let warmth = clamp_warmth(warmth);
+        (
+            Strength::new_clamped(brightness.0 * warmth),
+            Strength::new_clamped(brightness.0 * (1.0 - warmth)),
+        )
+    }
+    pub fn into_inner(self) -> (W, C) {
+        (self.warm, self.cool)
+    }
+
+    fn apply(&mut self) -> Result<(), OutputError> {
+        let (warm, cool) = Self::split(self.brightness, self.warmth);
+        self.warm.set(warm)?;
+        self.cool.set(cool)
+    }
+}
+impl<W: VariableOut, C: VariableOut> VariableOut for ColorTempOutput<W, C> {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        self.brightness = value;
+        self.apply()
+    }
+    fn set_warmth(&mut self, warmth: f64) -> Result<(), OutputError> {
+        self.warmth = clamp_warmth(warmth);
+        self.apply()
+    }
+    fn enable(&mut self) {
+        self.warm.enable();
+        self.cool.enable();
+    }
+    fn disable(&mut self) {
+        self.warm.disable();
+        self.cool.disable();
+    }
+    fn prepare(&mut self) {
+        self.warm.prepare();
+        self.cool.prepare();
+    }
+}
+
+/// Inverts the value before passing it on, for common-anode hardware
+/// where a duty cycle of `0` is full brightness.
+#[derive(Debug)]
+pub struct Inverted<T>(pub T);
+impl<T> Inverted<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+impl<T: VariableOut> VariableOut for Inverted<T> {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        self.0.set(Strength::new_clamped(1.0 - value.0))
+    }
+    fn set_rgb(
+        &mut self,
+        red: Strength,
+        green: Strength,
+        blue: Strength,
+    ) -> Result<(), OutputError> {
+        self.0.set_rgb(red, green, blue)
+    }
+    fn set_warmth(&mut self, warmth: f64) -> Result<(), OutputError> {
+        self.0.set_warmth(warmth)
+    }
+    fn enable(&mut self) {
+        self.0.enable()
+    }
+    /// Not passed on; the inner output is at full duty cycle when this is off,
+    /// and disabling it could turn the light on.
+    fn disable(&mut self) {}
+    fn prepare(&mut self) {
+        self.0.prepare()
+    }
+}
+
+/// Keeps the value within `[min, max]` before passing it on.
+/// Unlike rescaling, every value below `min` becomes `min`, and above `max` becomes `max`.
+///
+/// Useful for drivers which flicker at low duty cycles or overheat at high.
+/// Note that `0` also becomes `min`, though the controller still disables the output
+/// when it's been `0` for a while. See [`VariableOut::disable()`].
+#[derive(Debug)]
+pub struct Clamped<T> {
+    min: Strength,
+    max: Strength,
+    inner: T,
+}
+impl<T> Clamped<T> {
+    /// # Panics
+    ///
+    /// Panics if `min > max`.
+    pub fn new(inner: T, min: Strength, max: Strength) -> Self {
+        assert!(min <= max, "min must not be larger than max");
+        Self { min, max, inner }
+    }
+    pub fn min(&self) -> Strength {
+        self.min
+    }
+    pub fn max(&self) -> Strength {
+        self.max
+    }
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+impl<T: VariableOut> VariableOut for Clamped<T> {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        let value = value.0.max(self.min.0).min(self.max.0);
+        self.inner.set(Strength(value))
+    }
+    fn set_rgb(
+        &mut self,
+        red: Strength,
+        green: Strength,
+        blue: Strength,
+    ) -> Result<(), OutputError> {
+        self.inner.set_rgb(red, green, blue)
+    }
+    fn set_warmth(&mut self, warmth: f64) -> Result<(), OutputError> {
+        self.inner.set_warmth(warmth)
+    }
+    fn enable(&mut self) {
+        self.inner.enable()
+    }
+    fn disable(&mut self) {
+        self.inner.disable()
+    }
+    fn prepare(&mut self) {
+        self.inner.prepare()
+    }
+}
+
+/// Raises any value between `0` and `floor` to `floor`, for drivers which flicker or cut out
+/// at very low duty cycles. Exactly `0` is passed on, so the output can still turn off.
+///
+/// Unlike [`Clamped`], off stays off.
+#[derive(Debug)]
+pub struct Floored<T> {
+    floor: Strength,
+    inner: T,
+}
+impl<T> Floored<T> {
+    pub fn new(inner: T, floor: Strength) -> Self {
+        Self { floor, inner }
+    }
+    pub fn floor(&self) -> Strength {
+        self.floor
+    }
+    /// The value passed on for `value`.
+    pub fn apply(&self, value: Strength) -> Strength {
+        match value.is_off() {
+            true => value,
+            false => Strength(value.0.max(self.floor.0)),
+        }
+    }
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+impl<T: VariableOut> VariableOut for Floored<T> {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        let value = self.apply(value);
+        self.inner.set(value)
+    }
+    fn set_rgb(
+        &mut self,
+        red: Strength,
+        green: Strength,
+        blue: Strength,
+    ) -> Result<(), OutputError> {
+        self.inner.set_rgb(red, green, blue)
+    }
+    fn set_warmth(&mut self, warmth: f64) -> Result<(), OutputError> {
+        self.inner.set_warmth(warmth)
+    }
+    fn enable(&mut self) {
+        self.inner.enable()
+    }
+    fn disable(&mut self) {
+        self.inner.disable()
+    }
+    fn prepare(&mut self) {
+        self.inner.prepare()
+    }
+}
+
+/// Maps the value through a lookup table before passing it on,
+/// for fixtures needing a calibration curve a formula can't describe.
+///
+/// The entries are spread evenly over `[0, 1]`: the first is the output at `0`,
+/// the last at `1`. Values between entries are interpolated linearly.
+/// 256 entries is usually plenty.
+#[derive(Debug)]
+pub struct LutOutput<T> {
+    table: Vec<Strength>,
+    inner: T,
+}
+impl<T> LutOutput<T> {
+    /// Errors if any entry isn't in `[0, 1]`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `table` has less than two entries.
+    pub fn new(inner: T, table: Vec<f64>) -> Result<Self, StrengthError> {
+        assert!(table.len() >= 2, "the table needs at least two entries");
+        let table = table
+            .into_iter()
+            .map(Strength::try_new)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { table, inner })
+    }
+    pub fn table(&self) -> &[Strength] {
+        &self.table
+    }
+    /// The corrected value of `value`.
+    pub fn lookup(&self, value: Strength) -> Strength {
+        let position = value.0 * (self.table.len() - 1) as f64;
+        let index = (position.floor() as usize).min(self.table.len() - 2);
+        let progress = position - index as f64;
+        let from = self.table[index].0;
+        let to = self.table[index + 1].0;
+        Strength::new_clamped(from + (to - from) * progress)
+    }
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+impl<T: VariableOut> VariableOut for LutOutput<T> {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        let value = self.lookup(value);
+        self.inner.set(value)
+    }
+    fn set_rgb(
+        &mut self,
+        red: Strength,
+        green: Strength,
+        blue: Strength,
+    ) -> Result<(), OutputError> {
+        self.inner.set_rgb(red, green, blue)
+    }
+    fn set_warmth(&mut self, warmth: f64) -> Result<(), OutputError> {
+        self.inner.set_warmth(warmth)
+    }
+    fn enable(&mut self) {
+        self.inner.enable()
+    }
+    fn disable(&mut self) {
+        self.inner.disable()
+    }
+    fn prepare(&mut self) {
+        self.inner.prepare()
+    }
+}
+
+/// Something which can only be on or off, such as a relay on a GPIO pin.
+///
+/// Implement this for other digital outputs to use them with [`ThresholdOutput`].
+pub trait Switch: Send {
+    fn switch(&mut self, on: bool) -> Result<(), OutputError>;
+}
+impl Switch for OutputPin {
+    fn switch(&mut self, on: bool) -> Result<(), OutputError> {
+        match on {
+            true => self.set_high(),
+            false => self.set_low(),
+        }
+        Ok(())
+    }
+}
+
+/// Turns a [`Switch`] on at `threshold`, for lamps which can't be dimmed.
+///
+/// It's turned off again only when the value drops below `threshold - hysteresis`,
+/// so values hovering around the threshold don't make it chatter.
+/// A transition then switches it once.
+#[derive(Debug)]
+pub struct ThresholdOutput<T> {
+    inner: T,
+    threshold: Strength,
+    hysteresis: f64,
+    on: bool,
+}
+impl<T: Switch> ThresholdOutput<T> {
+    /// The switch is assumed to be off.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hysteresis` is negative or NaN.
+    pub fn new(inner: T, threshold: Strength, hysteresis: f64) -> Self {
+        assert!(hysteresis >= 0.0, "hysteresis must not be negative");
+        Self {
+            inner,
+            threshold,
+            hysteresis,
+            on: false,
+        }
+    }
+    pub fn threshold(&self) -> Strength {
+        self.threshold
+    }
+    pub fn hysteresis(&self) -> f64 {
+        self.hysteresis
+    }
+    /// If the switch is on.
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+impl<T: Switch> VariableOut for ThresholdOutput<T> {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        let on = match self.on {
+            true => value.0 >= self.threshold.0 - self.hysteresis,
+            false => value.0 >= self.threshold.0,
+        };
+        if on != self.on {
+            self.inner.switch(on)?;
+            self.on = on;
+        }
+        Ok(())
+    }
+    fn enable(&mut self) {}
+    fn disable(&mut self) {
+        if let Err(err) = self.inner.switch(false) {
+            error!("Failed to switch off: {}", err);
+        }
+        self.on = false;
+    }
+    fn prepare(&mut self) {}
+}
+
+/// Software PWM on any GPIO pin, for when the hardware PWM channels are taken.
+///
+/// A thread toggles the pin at the frequency given in [`SoftPwm::new()`].
+/// Any other [`Switch`] can be toggled in its place.
+/// [`VariableOut::set()`] only changes the duty cycle the thread reads each period.
+/// The thread is stopped when this is dropped.
+///
+/// The timing is only as good as the OS scheduler; expect some flicker at high frequencies.
+#[derive(Debug)]
+pub struct SoftPwm<P: Switch + 'static = OutputPin> {
+    /// The bits of a `f64` in `[0, 1]`.
+    duty_cycle: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<P>>,
+}
+impl<P: Switch + 'static> SoftPwm<P> {
+    /// # Panics
+    ///
+    /// Panics if `frequency` isn't positive.
+    pub fn new(mut pin: P, frequency: f64) -> Self {
+        assert!(frequency > 0.0, "frequency must be positive");
+        let period = Duration::from_secs_f64(1.0 / frequency);
+        let duty_cycle = Arc::new(AtomicU64::new(0.0_f64.to_bits()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let duty_cycle = Arc::clone(&duty_cycle);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                // Only the first of consecutive errors is logged, since it switches every period.
+                let mut failing = false;
+                let mut switch = |pin: &mut P, on| match pin.switch(on) {
+                    Ok(()) => failing = false,
+                    Err(err) => {
+                        if !failing {
+                            error!("Failed to switch software PWM pin: {}", err);
+                        }
+                        failing = true;
+                    }
+                };
+                while !stop.load(Ordering::Relaxed) {
+                    let duty_cycle = f64::from_bits(duty_cycle.load(Ordering::Relaxed));
+                    if duty_cycle <= 0.0 {
+                        switch(&mut pin, false);
+                        thread::sleep(period);
+                    } else if duty_cycle >= 1.0 {
+                        switch(&mut pin, true);
+                        thread::sleep(period);
+                    } else {
+                        switch(&mut pin, true);
+                        thread::sleep(period.mul_f64(duty_cycle));
+                        switch(&mut pin, false);
+                        thread::sleep(period.mul_f64(1.0 - duty_cycle));
+                    }
+                }
+                switch(&mut pin, false);
+                pin
+            })
+        };
+
+        Self {
+            duty_cycle,
+            stop,
+            handle: Some(handle),
+        }
+    }
+    /// The duty cycle last set.
+    pub fn duty_cycle(&self) -> f64 {
+        f64::from_bits(self.duty_cycle.load(Ordering::Relaxed))
+    }
+    /// Stops the thread and gives back the pin, set low.
+    pub fn into_inner(mut self) -> P {
+        self.stop_thread()
+            .expect("the thread is only stopped once, in `into_inner` or `drop`")
+    }
+
+    fn stop_thread(&mut self) -> Option<P> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle
+            .take()
+            .map(|handle| handle.join().expect("software PWM thread panicked"))
+    }
+}
+impl<P: Switch + 'static> VariableOut for SoftPwm<P> {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        self.duty_cycle
+            .store(value.into_inner().to_bits(), Ordering::Relaxed);
+        Ok(())
+    }
+    fn enable(&mut self) {}
+    fn disable(&mut self) {
+        self.duty_cycle.store(0.0_f64.to_bits(), Ordering::Relaxed);
+    }
+    fn prepare(&mut self) {}
+}
+impl<P: Switch + 'static> Drop for SoftPwm<P> {
+    fn drop(&mut self) {
+        self.stop_thread();
+    }
+}
+
+/// How often [`Smoothed`] moves the output towards the target.
+pub const SMOOTHING_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Ramps towards the latest value at a limited rate instead of jumping to it,
+/// so rapid changes (e.g. from a slider) fade instead of flicker.
+///
+/// The controller only calls [`VariableOut::set()`] when it has something new,
+/// so this ramps on its own thread, every [`SMOOTHING_INTERVAL`].
+/// `set` only changes the target. The thread is stopped when this is dropped.
+/// Errors from the inner output are logged, since they happen on the thread.
+#[derive(Debug)]
+pub struct Smoothed<T> {
+    inner: Arc<Mutex<T>>,
+    /// The bits of a `f64` in `[0, 1]`.
+    target: Arc<AtomicU64>,
+    /// The bits of a `f64` in `[0, 1]`.
+    current: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+impl<T: VariableOut + Send + 'static> Smoothed<T> {
+    /// `rate` is the max change of strength per second;
+    /// `1.0` fades from off to full in a second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` isn't positive.
+    pub fn new(inner: T, rate: f64) -> Self {
+        assert!(rate > 0.0, "rate must be positive");
+        let inner = Arc::new(Mutex::new(inner));
+        let target = Arc::new(AtomicU64::new(0.0_f64.to_bits()));
+        let current = Arc::new(AtomicU64::new(0.0_f64.to_bits()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let inner = Arc::clone(&inner);
+            let target = Arc::clone(&target);
+            let current = Arc::clone(&current);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let mut last = Instant::now();
+                while !stop.load(Ordering::Relaxed) {
+                    thread::sleep(SMOOTHING_INTERVAL);
+                    let now = Instant::now();
+                    let max_step = rate * (now - last).as_secs_f64();
+                    last = now;
+
+                    let target = f64::from_bits(target.load(Ordering::Relaxed));
+                    let old = f64::from_bits(current.load(Ordering::Relaxed));
+                    let new = Self::step(old, target, max_step);
+                    if new == old {
+                        continue;
+                    }
+                    current.store(new.to_bits(), Ordering::Relaxed);
+                    let mut inner = inner.lock().unwrap();
+                    if let Err(err) = inner.set(Strength::new_clamped(new)) {
+                        error!("Failed to set smoothed output: {}", err);
+                    }
+                }
+            })
+        };
+
+        Self {
+            inner,
+            target,
+            current,
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+impl<T> Smoothed<T> {
+    /// Moves `current` towards `target`, at most `max_step`.
+    pub fn step(current: f64, target: f64, max_step: f64) -> f64 {
+        if (target - current).abs() <= max_step {
+            target
+        } else if target > current {
+            current + max_step
+        } else {
+            current - max_step
+        }
+    }
+    /// The value last given to the inner output.
+    pub fn current(&self) -> Strength {
+        Strength::new_clamped(f64::from_bits(self.current.load(Ordering::Relaxed)))
+    }
+    /// The value being ramped towards.
+    pub fn target(&self) -> Strength {
+        Strength::new_clamped(f64::from_bits(self.target.load(Ordering::Relaxed)))
+    }
+    /// Stops the thread and gives back the inner output, at [`Self::current()`].
+    pub fn into_inner(mut self) -> T {
+        self.stop_thread();
+        let inner = Arc::clone(&self.inner);
+        drop(self);
+        match Arc::try_unwrap(inner) {
+            Ok(inner) => inner.into_inner().unwrap(),
+            Err(_) => unreachable!("the thread has stopped, so this is the last reference"),
+        }
+    }
+
+    fn stop_thread(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+impl<T: VariableOut> VariableOut for Smoothed<T> {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        self.target
+            .store(value.into_inner().to_bits(), Ordering::Relaxed);
+        Ok(())
+    }
+    fn enable(&mut self) {
+        self.inner.lock().unwrap().enable()
+    }
+    fn disable(&mut self) {
+        self.inner.lock().unwrap().disable()
+    }
+    fn prepare(&mut self) {
+        self.inner.lock().unwrap().prepare()
+    }
+}
+impl<T> Drop for Smoothed<T> {
+    fn drop(&mut self) {
+        self.stop_thread();
+    }
+}
+
+/// How often [`Dithered`] sets the inner output.
+pub const DITHER_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Temporal dithering, giving the inner output more resolution at low brightness
+/// than its duty cycle steps allow.
+///
+/// Every [`DITHER_INTERVAL`], the inner output is set to the target rounded down to one of
+/// `levels` steps, with the remainder carried over to the next frame (error diffusion).
+/// The average over time is then the target.
+///
+/// This relies on a steady tick; the controller only calls [`VariableOut::set()`] when it
+/// has something new, so the frames are set on a thread, like [`Smoothed`].
+/// A value a tenth of a step above a level repeats every 10 frames, 100Hz at the 1ms interval;
+/// finer remainders than that can be seen flickering.
+/// `set` only changes the target. The thread is stopped when this is dropped.
+#[derive(Debug)]
+pub struct Dithered<T> {
+    inner: Arc<Mutex<T>>,
+    /// The bits of a `f64` in `[0, 1]`.
+    target: Arc<AtomicU64>,
+    levels: u32,
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+impl<T: VariableOut + Send + 'static> Dithered<T> {
+    /// `levels` is the number of steps of the inner output, e.g. `255` for 8 bits.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `levels` is `0`.
+    pub fn new(inner: T, levels: u32) -> Self {
+        assert!(levels > 0, "levels must be positive");
+        let inner = Arc::new(Mutex::new(inner));
+        let target = Arc::new(AtomicU64::new(0.0_f64.to_bits()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let inner = Arc::clone(&inner);
+            let target = Arc::clone(&target);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let mut error = 0.0;
+                let mut last = None;
+                while !stop.load(Ordering::Relaxed) {
+                    thread::sleep(DITHER_INTERVAL);
+                    let target = f64::from_bits(target.load(Ordering::Relaxed));
+                    let frame = Self::frame(target, &mut error, levels);
+                    if last == Some(frame) {
+                        continue;
+                    }
+                    last = Some(frame);
+                    let mut inner = inner.lock().unwrap();
+                    if let Err(err) = inner.set(Strength::new_clamped(frame)) {
+                        error!("Failed to set dithered output: {}", err);
+                    }
+                }
+            })
+        };
+
+        Self {
+            inner,
+            target,
+            levels,
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+impl<T> Dithered<T> {
+    /// The value of the next frame for `target`, quantized to `levels` steps.
+    /// `error` is the remainder carried between frames; start it at `0`.
+    pub fn frame(target: f64, error: &mut f64, levels: u32) -> f64 {
+        let levels = f64::from(levels);
+        let wanted = target + *error;
+        let frame = (wanted * levels).floor() / levels;
+        // `clamp` would keep NaN.
+        let frame = match frame.is_nan() {
+            true => 0.0,
+            false => frame.clamp(0.0, 1.0),
+        };
+        *error = wanted - frame;
+        frame
+    }
+    pub fn levels(&self) -> u32 {
+        self.levels
+    }
+    /// The value averaged over time.
+    pub fn target(&self) -> Strength {
+        Strength::new_clamped(f64::from_bits(self.target.load(Ordering::Relaxed)))
+    }
+    /// Stops the thread and gives back the inner output.
+    pub fn into_inner(mut self) -> T {
+        self.stop_thread();
+        let inner = Arc::clone(&self.inner);
+        drop(self);
+        match Arc::try_unwrap(inner) {
+            Ok(inner) => inner.into_inner().unwrap(),
+            Err(_) => unreachable!("the thread has stopped, so this is the last reference"),
+        }
+    }
+
+    fn stop_thread(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+impl<T: VariableOut> VariableOut for Dithered<T> {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        self.target
+            .store(value.into_inner().to_bits(), Ordering::Relaxed);
+        Ok(())
+    }
+    fn enable(&mut self) {
+        self.inner.lock().unwrap().enable()
+    }
+    fn disable(&mut self) {
+        self.inner.lock().unwrap().disable()
+    }
+    fn prepare(&mut self) {
+        self.inner.lock().unwrap().prepare()
+    }
+}
+impl<T> Drop for Dithered<T> {
+    fn drop(&mut self) {
+        self.stop_thread();
+    }
+}
+
+/// How often [`DmxOutput`] sends a frame. DMX512 needs a steady stream of them.
+pub const DMX_REFRESH_INTERVAL: Duration = Duration::from_millis(25);
+
+/// Drives a single DMX512 channel, through a USB-DMX interface compatible with the
+/// Enttec DMX USB Pro (the widget handles the timing of the break).
+///
+/// The port is anything [`Write`], usually the opened serial device, such as `/dev/ttyUSB0`.
+/// A thread sends the latest frame every [`DMX_REFRESH_INTERVAL`];
+/// [`VariableOut::set()`] only updates the value, so it never waits on the port.
+/// The thread is stopped when this is dropped.
+#[derive(Debug)]
+pub struct DmxOutput {
+    channel: u16,
+    value: Arc<AtomicU8>,
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+impl DmxOutput {
+    /// # Panics
+    ///
+    /// Panics if `channel` isn't in `1..=512`.
+    pub fn new<W: Write + Send + 'static>(mut port: W, channel: u16) -> Self {
+        assert!(
+            (1..=512).contains(&channel),
+            "DMX channels are numbered 1 to 512"
+        );
+        let value = Arc::new(AtomicU8::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let value = Arc::clone(&value);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                // Only log when it starts failing, not every frame.
+                let mut failing = false;
+                while !stop.load(Ordering::Relaxed) {
+                    let packet = Self::packet(channel, value.load(Ordering::Relaxed));
+                    match port.write_all(&packet).and_then(|()| port.flush()) {
+                        Ok(()) => failing = false,
+                        Err(err) => {
+                            if !failing {
+                                error!("Failed to write DMX frame: {}", err);
+                            }
+                            failing = true;
+                        }
+                    }
+                    thread::sleep(DMX_REFRESH_INTERVAL);
+                }
+            })
+        };
+
+        Self {
+            channel,
+            value,
+            stop,
+            handle: Some(handle),
+        }
+    }
+    pub fn channel(&self) -> u16 {
+        self.channel
+    }
+    /// The DMX value, `0-255`, of `value`.
+    pub fn dmx_value(value: Strength) -> u8 {
+        (value.into_inner() * 255.0).round() as u8
+    }
+    /// A "send DMX" packet to the widget, with all channels but `channel` at `0`.
+    pub fn packet(channel: u16, value: u8) -> Vec<u8> {
+        // start code and the 512 channels
+        let length: u16 = 1 + 512;
+        let mut packet = Vec::with_capacity(length as usize + 5);
+        packet.extend_from_slice(&[0x7e, 6, (length & 0xff) as u8, (length >> 8) as u8]);
+        // start code
+        packet.push(0);
+        packet.extend((1..=512).map(|current| if current == channel { value } else { 0 }));
+        packet.push(0xe7);
+        packet
+    }
+}
+impl VariableOut for DmxOutput {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        self.value.store(Self::dmx_value(value), Ordering::Relaxed);
+        Ok(())
+    }
+    fn enable(&mut self) {}
+    fn disable(&mut self) {}
+    fn prepare(&mut self) {}
+}
+impl Drop for DmxOutput {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// How often [`Logging`] flushes its sink.
+pub const LOGGING_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Writes a `timestamp,strength` line for every value before passing it on.
+/// The timestamp is local time, as `2021-03-01T07:00:00.250`. No header is written.
+///
+/// The sink is buffered and flushed every [`LOGGING_FLUSH_INTERVAL`], and when this is dropped.
+/// Write errors are logged once and don't affect the inner output.
+#[derive(Debug)]
+pub struct Logging<T, W: Write> {
+    inner: T,
+    sink: BufWriter<W>,
+    last_flush: Instant,
+    failing: bool,
+}
+impl<T, W: Write> Logging<T, W> {
+    pub fn new(inner: T, sink: W) -> Self {
+        Self {
+            inner,
+            sink: BufWriter::new(sink),
+            last_flush: Instant::now(),
+            failing: false,
+        }
+    }
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        self.sink.flush()
+    }
+    /// Flushes the sink and gives back the output and sink.
+    pub fn into_inner(self) -> io::Result<(T, W)> {
+        let sink = self.sink.into_inner().map_err(|err| err.into_error())?;
+        Ok((self.inner, sink))
+    }
+
+    fn log(&mut self, value: Strength) -> io::Result<()> {
+        writeln!(
+            self.sink,
+            "{},{}",
+            get_naive_now().format("%Y-%m-%dT%H:%M:%S%.3f"),
+            value.into_inner()
+        )?;
+        if self.last_flush.elapsed() >= LOGGING_FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+}
+impl<T: VariableOut, W: Write> VariableOut for Logging<T, W> {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        match self.log(value) {
+            Ok(()) => self.failing = false,
+            Err(err) => {
+                if !self.failing {
+                    error!("Failed to log strength: {}", err);
+                }
+                self.failing = true;
+            }
+        }
+        self.inner.set(value)
+    }
+    fn set_rgb(
+        &mut self,
+        red: Strength,
+        green: Strength,
+        blue: Strength,
+    ) -> Result<(), OutputError> {
+        self.inner.set_rgb(red, green, blue)
+    }
+    fn set_warmth(&mut self, warmth: f64) -> Result<(), OutputError> {
+        self.inner.set_warmth(warmth)
+    }
+    fn enable(&mut self) {
+        self.inner.enable()
+    }
+    fn disable(&mut self) {
+        // A good time to catch up, since nothing happens for a while.
+        let _ = self.flush();
+        self.inner.disable()
+    }
+    fn prepare(&mut self) {
+        self.inner.prepare()
+    }
+}
+
+/// Passes everything on to several outputs, in order.
+///
+/// Every output is set even if an earlier one fails.
+/// The errors are then returned together in [`OutputError::Composite`].
+#[derive(Default)]
+pub struct CompositeOutput {
+    outputs: Vec<Box<dyn VariableOut + Send>>,
+}
+impl CompositeOutput {
+    pub fn new(outputs: Vec<Box<dyn VariableOut + Send>>) -> Self {
+        Self { outputs }
+    }
+    pub fn with<T: VariableOut + Send + 'static>(mut self, output: T) -> Self {
+        self.outputs.push(Box::new(output));
+        self
+    }
+    pub fn ref_outputs(&self) -> &Vec<Box<dyn VariableOut + Send>> {
+        &self.outputs
+    }
+    pub fn mut_outputs(&mut self) -> &mut Vec<Box<dyn VariableOut + Send>> {
+        &mut self.outputs
+    }
+    pub fn into_inner(self) -> Vec<Box<dyn VariableOut + Send>> {
+        self.outputs
+    }
+
+    fn for_each<F: FnMut(&mut Box<dyn VariableOut + Send>) -> Result<(), OutputError>>(
+        &mut self,
+        mut f: F,
+    ) -> Result<(), OutputError> {
+        let errors: Vec<_> = self
+            .outputs
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, output)| f(output).err().map(|err| (index, err)))
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(OutputError::Composite(errors))
+        }
+    }
+}
+impl std::fmt::Debug for CompositeOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompositeOutput")
+            .field("outputs", &self.outputs.len())
+            .finish()
+    }
+}
+impl VariableOut for CompositeOutput {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        self.for_each(|output| output.set(value))
+    }
+    fn set_rgb(
+        &mut self,
+        red: Strength,
+        green: Strength,
+        blue: Strength,
+    ) -> Result<(), OutputError> {
+        self.for_each(|output| output.set_rgb(red, green, blue))
+    }
+    fn set_warmth(&mut self, warmth: f64) -> Result<(), OutputError> {
+        self.for_each(|output| output.set_warmth(warmth))
+    }
+    fn enable(&mut self) {
+        self.outputs.iter_mut().for_each(|output| output.enable())
+    }
+    fn disable(&mut self) {
+        self.outputs.iter_mut().for_each(|output| output.disable())
+    }
+    fn prepare(&mut self) {
+        self.outputs.iter_mut().for_each(|output| output.prepare())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records what it's set to.
+    #[derive(Debug, Default)]
+    struct Recorder {
+        values: Vec<Strength>,
+        enabled: bool,
+    }
+    impl Recorder {
+        fn last(&self) -> Strength {
+            *self.values.last().expect("it was never set")
+        }
+    }
+    impl VariableOut for Recorder {
+        fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+            self.values.push(value);
+            Ok(())
+        }
+        fn enable(&mut self) {
+            self.enabled = true;
+        }
+        fn disable(&mut self) {
+            self.enabled = false;
+        }
+        fn prepare(&mut self) {}
+    }
+
+    #[test]
+    fn pulse_width_scales_with_period() {
+        for period in [Duration::from_millis(1), Duration::from_millis(10)] {
+            assert_eq!(pulse_width(period, Strength::off()), Duration::new(0, 0));
+            assert_eq!(pulse_width(period, Strength::new(0.25)), period / 4);
+            assert_eq!(pulse_width(period, Strength::full()), period);
+        }
+        assert_eq!(
+            pulse_width(crate::DEFAULT_PWM_PERIOD, Strength::new(0.5)),
+            crate::DEFAULT_PWM_PERIOD / 2
+        );
+    }
+
+    #[test]
+    fn rgb_channels() {
+        let mut rgb = RgbOutput::new(
+            Recorder::default(),
+            Recorder::default(),
+            Recorder::default(),
+        );
+        rgb.set_rgb(Strength::full(), Strength::new(0.5), Strength::off())
+            .unwrap();
+        rgb.set(Strength::new(0.8)).unwrap();
+        let (red, green, blue) = rgb.into_inner();
+        assert_eq!(red.last(), Strength::new(0.8));
+        assert_eq!(green.last(), Strength::new(0.4));
+        assert_eq!(blue.last(), Strength::off());
+
+        let mut rgb = RgbOutput::new(
+            Recorder::default(),
+            Recorder::default(),
+            Recorder::default(),
+        );
+        rgb.set_channels(Strength::new(0.1), Strength::new(0.2), Strength::new(0.3))
+            .unwrap();
+        let (red, green, blue) = rgb.into_inner();
+        assert_eq!(red.values, vec![Strength::new(0.1)]);
+        assert_eq!(green.values, vec![Strength::new(0.2)]);
+        assert_eq!(blue.values, vec![Strength::new(0.3)]);
+    }
+
+    #[test]
+    fn color_temp_split() {
+        let mut output = ColorTempOutput::new(Recorder::default(), Recorder::default());
+        output.set(Strength::new(0.8)).unwrap();
+        for (warmth, warm, cool) in [
+            (0.0, 0.0, 0.8),
+            (0.25, 0.2, 0.6),
+            (0.5, 0.4, 0.4),
+            (1.0, 0.8, 0.0),
+            // Out of range and NaN are clamped.
+            (2.0, 0.8, 0.0),
+            (f64::NAN, 0.0, 0.8),
+        ] {
+            output.set_warmth(warmth).unwrap();
+            assert!(
+                (output.warm.last().0 - warm).abs() < 1e-9,
+                "warm at {}",
+                warmth
+            );
+            assert!(
+                (output.cool.last().0 - cool).abs() < 1e-9,
+                "cool at {}",
+                warmth
+            );
+        }
+    }
+
+    #[test]
+    fn inverted() {
+        let mut inverted = Inverted(Recorder::default());
+        inverted.enable();
+        for value in [0.0, 0.25, 1.0] {
+            inverted.set(Strength::new(value)).unwrap();
+        }
+        inverted.disable();
+        let inner = inverted.into_inner();
+        assert_eq!(
+            inner.values,
+            vec![Strength::full(), Strength::new(0.75), Strength::off()]
+        );
+        // Disabling it would turn the light on.
+        assert!(inner.enabled);
+    }
+
+    #[test]
+    fn clamped() {
+        let mut clamped = Clamped::new(Recorder::default(), Strength::new(0.1), Strength::new(0.9));
+        for value in [0.0, 0.05, 0.1, 0.5, 0.9, 0.95, 1.0] {
+            clamped.set(Strength::new(value)).unwrap();
+        }
+        assert_eq!(
+            clamped.into_inner().values,
+            [0.1, 0.1, 0.1, 0.5, 0.9, 0.9, 0.9]
+                .iter()
+                .map(|value| Strength::new(*value))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn floored() {
+        let mut floored = Floored::new(Recorder::default(), Strength::new(0.02));
+        floored.set(Strength::off()).unwrap();
+        assert_eq!(floored.inner.last(), Strength::off());
+        floored.set(Strength::new(0.001)).unwrap();
+        assert_eq!(floored.inner.last(), Strength::new(0.02));
+        floored.set(Strength::new(0.5)).unwrap();
+        assert_eq!(floored.inner.last(), Strength::new(0.5));
+    }
+
+    /// Records whether it's switched on or off.
+    #[derive(Debug, Default)]
+    struct Toggles(Arc<Mutex<Vec<bool>>>);
+    impl Switch for Toggles {
+        fn switch(&mut self, on: bool) -> Result<(), OutputError> {
+            self.0.lock().unwrap().push(on);
+            Ok(())
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "test")]
+    fn soft_pwm_duty_cycles() {
+        let toggles = Toggles::default();
+        let log = Arc::clone(&toggles.0);
+        let mut pwm = SoftPwm::new(toggles, 100.0);
+        let mut requested = Vec::new();
+        for value in [0.25, 0.5, 1.0, 0.0] {
+            pwm.set(Strength::new(value)).unwrap();
+            requested.push(pwm.duty_cycle());
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(requested, vec![0.25, 0.5, 1.0, 0.0]);
+        pwm.disable();
+        assert_eq!(pwm.duty_cycle(), 0.0);
+
+        // The thread is stopped, leaving the pin off.
+        pwm.into_inner();
+        let log = log.lock().unwrap();
+        assert_eq!(log.last(), Some(&false));
+        assert!(log.contains(&true));
+    }
+
+    /// A serial port writing to memory.
+    #[derive(Debug, Clone, Default)]
+    struct Port(Arc<Mutex<Vec<u8>>>);
+    impl Write for Port {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn dmx_frames() {
+        assert_eq!(DmxOutput::dmx_value(Strength::off()), 0);
+        assert_eq!(DmxOutput::dmx_value(Strength::new(0.5)), 128);
+        assert_eq!(DmxOutput::dmx_value(Strength::full()), 255);
+
+        let port = Port::default();
+        let mut dmx = DmxOutput::new(port.clone(), 3);
+        dmx.set(Strength::new(0.5)).unwrap();
+        thread::sleep(DMX_REFRESH_INTERVAL * 3);
+        drop(dmx);
+
+        let written = port.0.lock().unwrap();
+        let frame_length = 4 + 1 + 512 + 1;
+        assert!(written.len() >= frame_length * 2);
+        assert_eq!(written.len() % frame_length, 0);
+        let last = &written[written.len() - frame_length..];
+        assert_eq!(&last[..5], &[0x7e, 6, 0x01, 0x02, 0]);
+        // The channels start after the start code.
+        assert_eq!(last[4 + 3], 128);
+        assert!(last[5..4 + 3].iter().all(|value| *value == 0));
+        assert!(last[4 + 4..4 + 513].iter().all(|value| *value == 0));
+        assert_eq!(last[frame_length - 1], 0xe7);
+    }
+
+    #[test]
+    fn logging_records_every_set() {
+        let mut logging = Logging::new(Recorder::default(), Vec::new());
+        let values = [0.0, 0.25, 0.5, 1.0];
+        for value in values {
+            logging.set(Strength::new(value)).unwrap();
+        }
+        let (inner, sink) = logging.into_inner().unwrap();
+        assert_eq!(inner.values.len(), values.len());
+
+        let csv = String::from_utf8(sink).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), values.len());
+        for (line, value) in lines.iter().zip(values) {
+            let (timestamp, strength) = line.split_once(',').unwrap();
+            chrono::NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S%.3f").unwrap();
+            assert_eq!(strength.parse::<f64>().unwrap(), value);
+        }
+    }
+
+    /// Records what it's set to where the test can see it, or fails if it's `None`.
+    #[derive(Debug)]
+    struct Shared(Option<Arc<Mutex<Vec<Strength>>>>);
+    impl VariableOut for Shared {
+        fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+            match &self.0 {
+                Some(values) => {
+                    values.lock().unwrap().push(value);
+                    Ok(())
+                }
+                None => Err(OutputError::Other("unplugged".into())),
+            }
+        }
+        fn enable(&mut self) {}
+        fn disable(&mut self) {}
+        fn prepare(&mut self) {}
+    }
+
+    #[test]
+    fn composite_sets_all() {
+        let first = Arc::new(Mutex::new(Vec::new()));
+        let second = Arc::new(Mutex::new(Vec::new()));
+        let mut composite = CompositeOutput::default()
+            .with(Shared(Some(Arc::clone(&first))))
+            .with(Shared(Some(Arc::clone(&second))));
+        composite.set(Strength::new(0.6)).unwrap();
+        assert_eq!(*first.lock().unwrap(), vec![Strength::new(0.6)]);
+        assert_eq!(*second.lock().unwrap(), vec![Strength::new(0.6)]);
+
+        // The outputs after the failing one are still set.
+        composite.mut_outputs().insert(0, Box::new(Shared(None)));
+        match composite.set(Strength::full()) {
+            Err(OutputError::Composite(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].0, 0);
+            }
+            result => panic!("expected the error of the first output, got {:?}", result),
+        }
+        assert_eq!(first.lock().unwrap().last(), Some(&Strength::full()));
+        assert_eq!(second.lock().unwrap().last(), Some(&Strength::full()));
+    }
+
+    #[test]
+    fn smoothed_ramps() {
+        assert_eq!(Smoothed::<Recorder>::step(0.0, 1.0, 0.1), 0.1);
+        assert_eq!(Smoothed::<Recorder>::step(0.5, 0.0, 0.1), 0.4);
+        assert_eq!(Smoothed::<Recorder>::step(0.95, 1.0, 0.1), 1.0);
+
+        let mut smoothed = Smoothed::new(Recorder::default(), 2.0);
+        smoothed.set(Strength::full()).unwrap();
+        assert_eq!(smoothed.target(), Strength::full());
+        thread::sleep(Duration::from_millis(100));
+        let current = smoothed.current().into_inner();
+        assert!(current > 0.0 && current < 1.0, "{}", current);
+
+        let start = Instant::now();
+        while smoothed.current() != Strength::full() {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "it never got there"
+            );
+            thread::sleep(SMOOTHING_INTERVAL);
+        }
+        let values = smoothed.into_inner().values;
+        assert!(values.len() > 3, "{:?}", values);
+        assert!(
+            values.windows(2).all(|pair| pair[0] < pair[1]),
+            "{:?}",
+            values
+        );
+        assert_eq!(values.last(), Some(&Strength::full()));
+    }
+
+    #[test]
+    fn threshold_switches_once() {
+        let toggles = Toggles::default();
+        let log = Arc::clone(&toggles.0);
+        let mut output = ThresholdOutput::new(toggles, Strength::new(0.5), 0.1);
+        // Up, wobbling around the threshold, then down.
+        for value in [
+            0.0, 0.2, 0.49, 0.5, 0.48, 0.52, 0.45, 0.8, 1.0, 0.6, 0.5, 0.41, 0.45, 0.39, 0.2, 0.0,
+        ] {
+            output.set(Strength::new(value)).unwrap();
+        }
+        assert_eq!(*log.lock().unwrap(), [true, false]);
+        assert!(!output.is_on());
+    }
+
+    #[test]
+    fn lookup_table() {
+        let mut identity = LutOutput::new(Recorder::default(), vec![0.0, 1.0]).unwrap();
+        let values = [0.0, 0.3, 0.75, 1.0];
+        for value in values {
+            identity.set(Strength::new(value)).unwrap();
+        }
+        let passed = identity.into_inner().values;
+        assert_eq!(passed.len(), values.len());
+        for (passed, value) in passed.iter().zip(values) {
+            assert!((passed.into_inner() - value).abs() < 1e-9);
+        }
+
+        let curve = LutOutput::new(Recorder::default(), vec![0.0, 0.1, 0.5, 1.0]).unwrap();
+        for (value, expected) in [
+            (0.0, 0.0),
+            (1.0 / 3.0, 0.1),
+            (0.5, 0.3),
+            (5.0 / 6.0, 0.75),
+            (1.0, 1.0),
+        ] {
+            let corrected = curve.lookup(Strength::new(value)).into_inner();
+            assert!(
+                (corrected - expected).abs() < 1e-9,
+                "{}: {}",
+                value,
+                corrected
+            );
+        }
+        assert!(LutOutput::new(Recorder::default(), vec![0.0, 1.5]).is_err());
+    }
+
+    #[test]
+    fn dithering_averages_to_target() {
+        let levels = 255;
+        let frames = 1000;
+        for target in [0.0, 0.001, 0.3, 0.5005, 1.0] {
+            let mut error = 0.0;
+            let mut sum = 0.0;
+            for _ in 0..frames {
+                let frame = Dithered::<Recorder>::frame(target, &mut error, levels);
+                // Every frame is one of the levels.
+                let steps = frame * f64::from(levels);
+                assert!((steps - steps.round()).abs() < 1e-6, "{}", frame);
+                sum += frame;
+            }
+            let average = sum / f64::from(frames);
+            assert!(
+                (average - target).abs() <= 1.0 / f64::from(levels * frames) + 1e-12,
+                "{}: {}",
+                target,
+                average
+            );
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Driving the channels of a PCA9685, the 16 channel, 12 bit PWM chip, over I2C.
+//!
+//! [`Pca9685Output`] works with anything implementing [`I2cBus`],
+//! which is implemented for [`rppal::i2c::I2c`].
+
+use crate::{OutputError, Strength, VariableOut};
+use rppal::i2c::I2c;
+use std::thread;
+use std::time::Duration;
+use tracing::error;
+
+/// The address of the chip with none of the address pins pulled high.
+pub const DEFAULT_ADDRESS: u16 = 0x40;
+/// The number of channels of the chip.
+pub const CHANNELS: u8 = 16;
+/// The number of steps of the duty cycle.
+pub const RESOLUTION: u16 = 4096;
+
+const MODE1: u8 = 0x00;
+const LED0_ON_L: u8 = 0x06;
+const PRE_SCALE: u8 = 0xFE;
+
+const MODE1_RESTART: u8 = 0x80;
+const MODE1_AUTO_INCREMENT: u8 = 0x20;
+const MODE1_SLEEP: u8 = 0x10;
+/// In the high bytes of the on and off times.
+const FULL: u8 = 0x10;
+
+const OSCILLATOR_FREQUENCY: f64 = 25_000_000.0;
+
+/// Something which can write to the registers of an I2C device.
+///
+/// Implement this to use another I2C bus with [`Pca9685Output`].
+pub trait I2cBus: Send {
+    /// Writes `values` to consecutive registers, starting at `register`.
+    fn write_registers(&mut self, register: u8, values: &[u8]) -> Result<(), OutputError>;
+}
+impl I2cBus for I2c {
+    fn write_registers(&mut self, register: u8, values: &[u8]) -> Result<(), OutputError> {
+        self.block_write(register, values)?;
+        Ok(())
+    }
+}
+
+/// A channel of a PCA9685.
+///
+/// The PWM frequency is set in [`VariableOut::prepare()`], which the controller calls when starting.
+/// It applies to all channels of the chip, so use the same for all outputs of a chip.
+#[derive(Debug)]
+pub struct Pca9685Output<B> {
+    bus: B,
+    channel: u8,
+    frequency: f64,
+}
+impl Pca9685Output<I2c> {
+    /// Opens the primary I2C bus of the Raspberry Pi, talking to the chip at `address`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` isn't less than [`CHANNELS`].
+    pub fn new(address: u16, channel: u8) -> Result<Self, OutputError> {
+        let mut bus = I2c::new()?;
+        bus.set_slave_address(address)?;
+        Ok(Self::with_bus(bus, channel))
+    }
+}
+impl<B: I2cBus> Pca9685Output<B> {
+    /// `bus` has to be set up to talk to the chip. The frequency defaults to 1kHz.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` isn't less than [`CHANNELS`].
+    pub fn with_bus(bus: B, channel: u8) -> Self {
+        assert!(
+            channel < CHANNELS,
+            "the PCA9685 only has {} channels",
+            CHANNELS
+        );
+        Self {
+            bus,
+            channel,
+            frequency: 1000.0,
+        }
+    }
+    /// The chip supports about 24Hz to 1526Hz; other frequencies are clamped to that.
+    pub fn with_frequency(mut self, frequency: f64) -> Self {
+        self.frequency = frequency;
+        self
+    }
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+    pub fn into_inner(self) -> B {
+        self.bus
+    }
+    /// The value of the pre-scale register giving `frequency`.
+    pub fn prescale(frequency: f64) -> u8 {
+        let prescale = (OSCILLATOR_FREQUENCY / (f64::from(RESOLUTION) * frequency)).round() - 1.0;
+        // The chip doesn't accept anything below 3. Also catches NaN.
+        if prescale >= 3.0 {
+            prescale.min(255.0) as u8
+        } else {
+            3
+        }
+    }
+    /// The on and off registers of a channel for `value`, low byte first.
+    ///
+    /// The pulse starts at the beginning of the period. `0` and `1` use the full off and full on bits,
+    /// since the 12 bit counter can't express a duty cycle of 100%.
+    pub fn registers(value: Strength) -> [u8; 4] {
+        let off = (value.into_inner() * f64::from(RESOLUTION)).round() as u16;
+        match off {
+            0 => [0, 0, 0, FULL],
+            off if off >= RESOLUTION => [0, FULL, 0, 0],
+            off => [0, 0, (off & 0xFF) as u8, (off >> 8) as u8],
+        }
+    }
+    fn write_channel(&mut self, registers: [u8; 4]) -> Result<(), OutputError> {
+        self.bus
+            .write_registers(LED0_ON_L + 4 * self.channel, &registers)
+    }
+    fn set_frequency(&mut self) -> Result<(), OutputError> {
+        // The pre-scale can only be changed while sleeping.
+        self.bus
+            .write_registers(MODE1, &[MODE1_SLEEP | MODE1_AUTO_INCREMENT])?;
+        self.bus
+            .write_registers(PRE_SCALE, &[Self::prescale(self.frequency)])?;
+        self.bus.write_registers(MODE1, &[MODE1_AUTO_INCREMENT])?;
+        // The oscillator needs 500µs to start.
+        thread::sleep(Duration::from_micros(500));
+        self.bus
+            .write_registers(MODE1, &[MODE1_RESTART | MODE1_AUTO_INCREMENT])
+    }
+}
+impl<B: I2cBus> VariableOut for Pca9685Output<B> {
+    fn set(&mut self, value: Strength) -> Result<(), OutputError> {
+        self.write_channel(Self::registers(value))
+    }
+    fn enable(&mut self) {}
+    fn disable(&mut self) {
+        if let Err(err) = self.write_channel(Self::registers(Strength::off())) {
+            error!(
+                "Failed to turn off PCA9685 channel {}: {}",
+                self.channel, err
+            );
+        }
+    }
+    fn prepare(&mut self) {
+        if let Err(err) = self.set_frequency() {
+            error!("Failed to set the frequency of the PCA9685: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records the writes.
+    #[derive(Debug, Default)]
+    struct Bus(Vec<(u8, Vec<u8>)>);
+    impl I2cBus for Bus {
+        fn write_registers(&mut self, register: u8, values: &[u8]) -> Result<(), OutputError> {
+            self.0.push((register, values.to_vec()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn register_writes() {
+        let mut output = Pca9685Output::with_bus(Bus::default(), 3).with_frequency(200.0);
+        output.prepare();
+        for value in [0.0, 0.25, 0.5, 1.0] {
+            output.set(Strength::new(value)).unwrap();
+        }
+        // The registers of channel 3 start at 0x06 + 4 * 3.
+        assert_eq!(
+            output.into_inner().0,
+            [
+                (MODE1, vec![0x30]),
+                (PRE_SCALE, vec![30]),
+                (MODE1, vec![0x20]),
+                (MODE1, vec![0xA0]),
+                (0x12, vec![0, 0, 0, 0x10]),
+                (0x12, vec![0, 0, 0x00, 0x04]),
+                (0x12, vec![0, 0, 0x00, 0x08]),
+                (0x12, vec![0, 0x10, 0, 0]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,43 @@
+//! A small, seedable pseudo-random number generator.
+//!
+//! Not suitable for anything security related; it's here to make lights feel less mechanical.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A xorshift64* generator.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+impl Rng {
+    /// The same seed always gives the same sequence. A seed of `0` is replaced, since it would only yield zeroes.
+    pub fn new(seed: u64) -> Self {
+        Self(if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        })
+    }
+    /// Seeds from the system clock.
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Self::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+    /// A number in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        // The top 53 bits fit exactly in the mantissa.
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+    /// A number in `[-1, 1)`.
+    pub fn next_signed_f64(&mut self) -> f64 {
+        self.next_f64() * 2.0 - 1.0
+    }
+}
@@ -1,11 +1,19 @@
 use std::fmt::Debug;
 
+use crate::random::Rng;
 use crate::{
-    get_naive_now, has_occurred, Action, Command, Duration, Instant, SharedState, Strength,
-    Transition, TransitionInterpolation,
+    get_naive_now, Action, BlendMode, ClonableCommand, Command, Duration, EventListener, Instant,
+    Repeat, SharedState, Status, Strength, Transition, TransitionInterpolation, TransitionOutcome,
+    TRANSITION_SPEED_MAX, TRANSITION_SPEED_MIN,
 };
 use chrono::prelude::*;
-use std::sync::{Arc, Mutex};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{mpsc, Arc, Mutex};
+#[cfg(feature = "serde")]
+use std::{io, path::Path};
+use tracing::{info, warn};
 
 pub enum Progress {
     Pending(Duration),
@@ -20,20 +28,154 @@ pub enum Next {
     At(NaiveDateTime, Command),
     Unknown,
 }
+/// The scheduler which runs next. See [`SharedState::get_next_scheduler()`].
+pub struct NextScheduler<'a> {
+    /// `None` is the [`WeekScheduler`].
+    pub name: Option<&'a str>,
+    pub at: NaiveDateTime,
+    pub command: Command,
+    /// Schedulers due at the same time, but with lower priority, and when.
+    /// They are skipped when this runs.
+    pub suppressed: Vec<(Option<&'a str>, NaiveDateTime)>,
+}
+/// A source of the current time.
+///
+/// [`State`] gets the time from this, so a [`ManualClock`] can be used to test scheduling deterministically.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> NaiveDateTime;
+    /// The local time `duration` of real time before `local`.
+    ///
+    /// Defaults to subtracting, which is off by an hour when a DST change is in between.
+    fn before(&self, local: NaiveDateTime, duration: chrono::Duration) -> NaiveDateTime {
+        local - duration
+    }
+}
+/// The local time of the system, from [`get_naive_now()`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        get_naive_now()
+    }
+    fn before(&self, local: NaiveDateTime, duration: chrono::Duration) -> NaiveDateTime {
+        (resolve_local(&Local, local) - duration).naive_local()
+    }
+}
+/// When `local` is in `time_zone`.
+///
+/// A time which doesn't exist, since it's skipped when the clocks are set forward,
+/// resolves to the first minute after the skip.
+/// A time which occurs twice, when the clocks are set back, resolves to the first.
+pub fn resolve_local<Tz: TimeZone>(time_zone: &Tz, local: NaiveDateTime) -> DateTime<Tz> {
+    match time_zone.from_local_datetime(&local) {
+        chrono::LocalResult::Single(date_time) => return date_time,
+        chrono::LocalResult::Ambiguous(earliest, _) => return earliest,
+        chrono::LocalResult::None => {}
+    }
+    // Skips are on whole minutes and last a few hours at most.
+    let mut minute = local
+        .with_second(0)
+        .unwrap_or(local)
+        .with_nanosecond(0)
+        .unwrap_or(local);
+    for _ in 0..(24 * 60) {
+        minute += chrono::Duration::minutes(1);
+        if let Some(date_time) = time_zone.from_local_datetime(&minute).earliest() {
+            return date_time;
+        }
+    }
+    // Not a DST change; treat it as UTC.
+    time_zone.from_utc_datetime(&local)
+}
+/// The current time in `time_zone`, regardless of the system's time zone.
+/// [`SystemClock`] uses the system's.
+#[derive(Debug, Clone)]
+pub struct ZonedClock<Tz: TimeZone> {
+    time_zone: Tz,
+}
+impl<Tz: TimeZone> ZonedClock<Tz> {
+    pub fn new(time_zone: Tz) -> Self {
+        Self { time_zone }
+    }
+    pub fn time_zone(&self) -> &Tz {
+        &self.time_zone
+    }
+}
+impl<Tz: TimeZone + Debug + Send + Sync> Clock for ZonedClock<Tz> {
+    fn now(&self) -> NaiveDateTime {
+        Utc::now().with_timezone(&self.time_zone).naive_local()
+    }
+    fn before(&self, local: NaiveDateTime, duration: chrono::Duration) -> NaiveDateTime {
+        (resolve_local(&self.time_zone, local) - duration).naive_local()
+    }
+}
+/// A clock which only moves when told to.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<NaiveDateTime>,
+}
+impl ManualClock {
+    pub fn new(now: NaiveDateTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+    pub fn set(&self, now: NaiveDateTime) {
+        *self.now.lock().unwrap() = now;
+    }
+    pub fn advance(&self, duration: chrono::Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += duration;
+    }
+}
+impl Clock for ManualClock {
+    fn now(&self) -> NaiveDateTime {
+        *self.now.lock().unwrap()
+    }
+}
+
 /// Now, represented as a [`chrono::NaiveDateTime`], being lazily evaluated.
 /// Should not be used long after it's initiation, since `now` stays the same after the first call to [`LazyNow::now()`].
 pub struct LazyNow {
     now: Option<NaiveDateTime>,
+    /// `None` when pretending; see [`LazyNow::at()`].
+    clock: Option<Arc<dyn Clock>>,
 }
 impl LazyNow {
+    /// Uses the [`SystemClock`].
     pub fn new() -> Self {
-        Self { now: None }
+        Self::from_clock(Arc::new(SystemClock))
+    }
+    pub fn from_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            now: None,
+            clock: Some(clock),
+        }
+    }
+    /// Pretends it's `now`. Used to get occurrences of a [`Scheduler`] after the next one.
+    ///
+    /// There's no time zone; [`LazyNow::before()`] ignores DST changes.
+    pub fn at(now: NaiveDateTime) -> Self {
+        Self {
+            now: Some(now),
+            clock: None,
+        }
+    }
+    /// See [`Clock::before()`].
+    pub fn before(&self, local: NaiveDateTime, duration: chrono::Duration) -> NaiveDateTime {
+        match &self.clock {
+            Some(clock) => clock.before(local, duration),
+            None => local - duration,
+        }
     }
     pub fn now(&mut self) -> NaiveDateTime {
         match self.now {
             Some(now) => now,
             None => {
-                self.now = Some(get_naive_now());
+                self.now = Some(match &self.clock {
+                    Some(clock) => clock.now(),
+                    None => get_naive_now(),
+                });
                 match self.now {
                     Some(now) => now,
                     // SAFETY: the code above just filled the option
@@ -43,11 +185,27 @@ impl LazyNow {
         }
     }
 }
+impl Default for LazyNow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 pub trait Scheduler: Debug + Send + Sync {
     /// Advances the internal state when the scheduled time in [`Scheduler::get_next()`] is reached.
     /// You can specify if you want to persist in the list of schedulers or be removed.
     fn advance(&mut self) -> Keep;
+    /// Same as [`Scheduler::advance()`], with the `occurrence` from [`Scheduler::get_next()`]
+    /// which was reached. This is what the controller calls.
+    ///
+    /// `get_next` is also called to show the schedulers, so it mustn't remember what it returned.
+    /// Schedulers which need to know which occurrence ran get it here instead.
+    ///
+    /// Defaults to [`Scheduler::advance()`].
+    fn advance_past(&mut self, occurrence: NaiveDateTime) -> Keep {
+        let _ = occurrence;
+        self.advance()
+    }
     /// Main function. It gets the time to the next occurrence of this Scheduler.
     fn get_next(&self, now: &mut LazyNow) -> Next;
     /// A description to show the user. Should contain information about what this scheduler wakes up to do.
@@ -56,21 +214,96 @@ pub trait Scheduler: Debug + Send + Sync {
     /// Which type this scheduler is of.
     /// Should be used as a tip for users.
     fn kind(&self) -> &str;
+    /// When several schedulers are due within [`SIMULTANEOUS_SECONDS`] of each other,
+    /// only the one with the highest priority runs. The others are advanced past that occurrence.
+    ///
+    /// Defaults to `0`.
+    fn priority(&self) -> i32 {
+        0
+    }
+    /// The transition to start when this runs, instead of the command from [`Scheduler::get_next()`].
+    /// Lets e.g. a sunset scheduler fade down while a sunrise scheduler fades up.
+    ///
+    /// Defaults to `None`, which runs the command.
+    fn transition(&self) -> Option<Transition> {
+        None
+    }
+}
+
+/// Schedulers due within this many seconds of each other are considered simultaneous.
+/// See [`Scheduler::priority()`].
+pub const SIMULTANEOUS_SECONDS: i64 = 1;
+/// How many of the last set strengths [`State`] remembers. See [`Command::GetHistory`].
+pub const HISTORY_CAPACITY: usize = 1024;
+/// How often the output is updated during transitions, unless changed by
+/// [`Command::SetUpdateInterval`]. 50Hz is smooth to the eye.
+pub const DEFAULT_UPDATE_INTERVAL: Duration = Duration::from_millis(20);
+/// How many commands [`Command::Undo`] can revert.
+pub const UNDO_CAPACITY: usize = 64;
+/// How many [`SchedulerEvent`]s an observer can fall behind before new ones are dropped for it.
+pub const OBSERVER_CAPACITY: usize = 64;
+/// The shortest time between the updates of an [`Effect`], so it can't flood the output.
+pub const EFFECT_INTERVAL_MIN: Duration = Duration::from_millis(10);
+/// The shortest period of [`Effect::Strobe`], so each half lasts [`EFFECT_INTERVAL_MIN`].
+/// Also bounds [`Effect::Breathing`].
+pub const STROBE_PERIOD_MIN: Duration = Duration::from_millis(20);
+
+impl<S: Scheduler + ?Sized> Scheduler for Box<S> {
+    fn advance(&mut self) -> Keep {
+        (**self).advance()
+    }
+    fn advance_past(&mut self, occurrence: NaiveDateTime) -> Keep {
+        (**self).advance_past(occurrence)
+    }
+    fn get_next(&self, now: &mut LazyNow) -> Next {
+        (**self).get_next(now)
+    }
+    fn description(&self) -> &str {
+        (**self).description()
+    }
+    fn kind(&self) -> &str {
+        (**self).kind()
+    }
+    fn priority(&self) -> i32 {
+        (**self).priority()
+    }
+    fn transition(&self) -> Option<Transition> {
+        (**self).transition()
+    }
 }
 
+/// With the `serde` feature, times are (de)serialized as `"HH:MM:SS"`.
+/// When it last ran isn't serialized.
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct WeekScheduler {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::opt_time"))]
     pub mon: Option<NaiveTime>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::opt_time"))]
     pub tue: Option<NaiveTime>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::opt_time"))]
     pub wed: Option<NaiveTime>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::opt_time"))]
     pub thu: Option<NaiveTime>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::opt_time"))]
     pub fri: Option<NaiveTime>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::opt_time"))]
     pub sat: Option<NaiveTime>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::opt_time"))]
     pub sun: Option<NaiveTime>,
+    /// The transition of days without their own in [`WeekScheduler::get_day_transition()`].
     pub transition: Transition,
+    /// Overrides of [`Self::transition`], indexed by [`Weekday::num_days_from_monday()`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    day_transitions: [Option<Transition>; 7],
+    #[cfg_attr(feature = "serde", serde(skip))]
     last: Option<NaiveDateTime>,
 }
 impl WeekScheduler {
+    /// Creates a [`WeekSchedulerBuilder`], where only the days you specify have a time.
+    pub fn builder(transition: Transition) -> WeekSchedulerBuilder {
+        WeekSchedulerBuilder(Self::empty(transition))
+    }
     pub fn empty(transition: Transition) -> Self {
         Self::same_with_day(None, transition)
     }
@@ -84,6 +317,7 @@ impl WeekScheduler {
             sat: time,
             sun: time,
             transition,
+            day_transitions: Default::default(),
             last: None,
         }
     }
@@ -91,6 +325,18 @@ impl WeekScheduler {
     pub fn same(time: NaiveTime, transition: Transition) -> Self {
         Self::same_with_day(Some(time), transition)
     }
+    /// Monday to Friday at `weekdays`, Saturday and Sunday at `weekends`.
+    /// `None` leaves those days without a time.
+    pub fn weekdays_weekends(
+        weekdays: Option<NaiveTime>,
+        weekends: Option<NaiveTime>,
+        transition: Transition,
+    ) -> Self {
+        let mut scheduler = Self::same_with_day(weekdays, transition);
+        scheduler.sat = weekends;
+        scheduler.sun = weekends;
+        scheduler
+    }
 
     pub fn get_next_from_day(&self, day: Weekday) -> Option<(&NaiveTime, u8)> {
         let mut day = day.pred();
@@ -125,43 +371,141 @@ impl WeekScheduler {
             Weekday::Sun => &mut self.sun,
         }
     }
+    /// Sets the time of `day`, returning the previous one.
+    pub fn set(&mut self, day: Weekday, time: Option<NaiveTime>) -> Option<NaiveTime> {
+        std::mem::replace(self.get_mut(day), time)
+    }
+    /// The time of every day, from Monday to Sunday.
+    pub fn all_days(&self) -> [(Weekday, Option<NaiveTime>); 7] {
+        [
+            (Weekday::Mon, self.mon),
+            (Weekday::Tue, self.tue),
+            (Weekday::Wed, self.wed),
+            (Weekday::Thu, self.thu),
+            (Weekday::Fri, self.fri),
+            (Weekday::Sat, self.sat),
+            (Weekday::Sun, self.sun),
+        ]
+    }
+    /// Sets the time of the days in `diff`, leaving the others as they are.
+    /// Returns the days which actually changed, from Monday to Sunday.
+    pub fn apply_diff(&mut self, diff: &HashMap<Weekday, Option<NaiveTime>>) -> Vec<Weekday> {
+        let mut changed = Vec::new();
+        let mut day = Weekday::Mon;
+        for _ in 0..7 {
+            if let Some(time) = diff.get(&day) {
+                let current = self.get_mut(day);
+                if current != time {
+                    *current = *time;
+                    changed.push(day);
+                }
+            }
+            day = day.succ();
+        }
+        changed
+    }
+    /// Writes the times and transitions to `path` as RON, which [`Self::load()`] reads back.
+    #[cfg(feature = "serde")]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let data = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        std::fs::write(path, data)
+    }
+    /// Reads a scheduler written by [`Self::save()`].
+    ///
+    /// A corrupt file gives an error of the kind [`io::ErrorKind::InvalidData`].
+    #[cfg(feature = "serde")]
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        ron::de::from_reader(file).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+    /// The transition ran on `day`. Falls back to [`Self::transition`].
+    pub fn get_transition(&self, day: Weekday) -> &Transition {
+        self.get_day_transition(day).unwrap_or(&self.transition)
+    }
+    /// The transition specific to `day`, if any.
+    pub fn get_day_transition(&self, day: Weekday) -> Option<&Transition> {
+        self.day_transitions[day.num_days_from_monday() as usize].as_ref()
+    }
+    /// Sets the transition specific to `day`. `None` makes it use [`Self::transition`].
+    pub fn set_day_transition(&mut self, day: Weekday, transition: Option<Transition>) {
+        self.day_transitions[day.num_days_from_monday() as usize] = transition;
+    }
+    /// Like [`Scheduler::advance()`], but treating `now` as the time it ran.
+    pub fn advance_at(&mut self, now: NaiveDateTime) {
+        self.last = Some(now);
+    }
+    /// Advances past the next occurrence, without running it. Returns when it would have started.
+    pub fn skip_next(&mut self, now: &mut LazyNow) -> Option<NaiveDateTime> {
+        match self.get_next(now) {
+            Next::At(at, _) => {
+                self.advance_at(at);
+                Some(at)
+            }
+            Next::Unknown => None,
+        }
+    }
 }
 impl Scheduler for WeekScheduler {
+    /// Takes the occurrence to be now on the [`SystemClock`]. The controller uses
+    /// [`Scheduler::advance_past()`], with the occurrence from its own clock.
     fn advance(&mut self) -> Keep {
-        self.last = Some(get_naive_now());
+        self.advance_past(SystemClock.now())
+    }
+    fn advance_past(&mut self, occurrence: NaiveDateTime) -> Keep {
+        self.advance_at(occurrence);
         Keep::Keep
     }
     fn get_next(&self, now: &mut LazyNow) -> Next {
-        let now = now.now();
-        // todo!("fix this to be naiveDateTime?");
-        let next_today = match self.get(now.weekday()) {
-            Some(t) => *t,
-            None => return Next::Unknown,
-        };
+        fn lead(transition: &Transition) -> chrono::Duration {
+            let lead = match transition.interpolation {
+                // Applied instantly, so it shouldn't start ahead of time.
+                TransitionInterpolation::Constant => Duration::new(0, 0),
+                _ => transition.time,
+            };
+            // if your transition time is larger than what std can handle, you have other problems
+            chrono::Duration::from_std(lead).unwrap()
+        }
 
-        // Check if last was not today, then abort.
-        let next = if now.time() < next_today
-            && self
-                .last
-                .map(|l| l.date() < get_naive_now().date())
-                .unwrap_or(true)
-        {
-            now.date().and_time(next_today)
-        } else {
-            // Unwrap is ok; we checked the same function above and return if it was `None`.
-            // If it returns `Some` for Weekday x, it will also return `Some` for Weekday x.succ(); it's a cycle.
-            let (time, day) = self.get_next_from_day(now.weekday().succ()).unwrap();
-            // Since we get the next day from function
-            let day = day + 1;
+        let lazy_now = now;
+        // The times are whole seconds, so a start in the current second is due now, not next week,
+        // even if part of the second has passed. `last` keeps it from running twice.
+        let now = lazy_now.now();
+        let now = now.with_nanosecond(0).unwrap_or(now);
+        let max_lead = self
+            .day_transitions
+            .iter()
+            .flatten()
+            .chain(std::iter::once(&self.transition))
+            .map(lead)
+            .max()
+            .unwrap();
 
-            now.date().and_time(*time) + chrono::Duration::days(day as i64)
-        };
-        // if your transition time is larger than what std can handle, you have other problems
-        let next = next - chrono::Duration::from_std(self.transition.time).unwrap();
-        Next::At(
-            next,
-            Command::SetTransition(Transition::clone(&self.transition)),
-        )
+        // Compare when the transition *starts*, not the time of day, since the transition can
+        // start the day before the time, across midnight.
+        // A start exactly at `now` is due; it's not pushed a week ahead.
+        // Days have different leads, so the earliest start isn't necessarily on the first day.
+        let mut next: Option<(NaiveDateTime, &Transition)> = None;
+        for day in 0..=(7 + max_lead.num_days()) {
+            let date = now.date() + chrono::Duration::days(day);
+            let time = match self.get(date.weekday()) {
+                Some(time) => *time,
+                None => continue,
+            };
+            let transition = self.get_transition(date.weekday());
+            // The lead is real time, across any DST change.
+            let start = lazy_now.before(date.and_time(time), lead(transition));
+            let already_ran = self.last.map(|last| start <= last).unwrap_or(false);
+            if start >= now && !already_ran && next.map(|(at, _)| start < at).unwrap_or(true) {
+                next = Some((start, transition));
+            }
+        }
+        match next {
+            Some((start, transition)) => {
+                Next::At(start, Command::SetTransition(Transition::clone(transition)))
+            }
+            None => Next::Unknown,
+        }
     }
 
     fn description(&self) -> &str {
@@ -178,6 +522,401 @@ impl Default for WeekScheduler {
     }
 }
 
+/// Builds a [`WeekScheduler`] day by day. Days you don't specify have no time.
+///
+/// Use [`WeekScheduler::same()`] if every day should be the same.
+#[derive(Debug, Clone)]
+pub struct WeekSchedulerBuilder(WeekScheduler);
+impl WeekSchedulerBuilder {
+    /// Runs the default transition at `time` on `day`.
+    pub fn day(mut self, day: Weekday, time: NaiveTime) -> Self {
+        *self.0.get_mut(day) = Some(time);
+        self
+    }
+    /// Runs `transition` at `time` on `day`.
+    pub fn day_with_transition(
+        mut self,
+        day: Weekday,
+        time: NaiveTime,
+        transition: Transition,
+    ) -> Self {
+        *self.0.get_mut(day) = Some(time);
+        self.0.set_day_transition(day, Some(transition));
+        self
+    }
+    pub fn build(self) -> WeekScheduler {
+        self.0
+    }
+}
+
+/// The time the user goes to bed, shared between [`SharedState`] and every [`BedtimeScheduler`].
+///
+/// Change it through [`Command::SetBedtime`] so the controller recalculates when to wake up.
+#[derive(Debug, Clone)]
+pub struct Bedtime(Arc<Mutex<NaiveTime>>);
+impl Bedtime {
+    pub fn new(time: NaiveTime) -> Self {
+        Self(Arc::new(Mutex::new(time)))
+    }
+    pub fn get(&self) -> NaiveTime {
+        *self.0.lock().unwrap()
+    }
+    pub fn set(&self, time: NaiveTime) {
+        *self.0.lock().unwrap() = time;
+    }
+}
+impl Default for Bedtime {
+    fn default() -> Self {
+        Self::new(NaiveTime::from_hms_opt(22, 0, 0).unwrap())
+    }
+}
+
+/// Runs every day at an offset from [`Bedtime`], so moving the bedtime moves all of these.
+#[derive(Debug)]
+pub struct BedtimeScheduler {
+    bedtime: Bedtime,
+    offset: chrono::Duration,
+    description: String,
+    command: ClonableCommand,
+    priority: i32,
+}
+impl BedtimeScheduler {
+    /// A negative `offset` runs before the bedtime.
+    pub fn new(
+        bedtime: Bedtime,
+        offset: chrono::Duration,
+        description: String,
+        command: ClonableCommand,
+    ) -> Self {
+        Self {
+            bedtime,
+            offset,
+            description,
+            command,
+            priority: 0,
+        }
+    }
+    /// See [`Scheduler::priority()`].
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+    /// The time of day this runs at. Wraps around midnight.
+    pub fn time(&self) -> NaiveTime {
+        self.bedtime.get() + self.offset
+    }
+}
+impl Scheduler for BedtimeScheduler {
+    fn advance(&mut self) -> Keep {
+        Keep::Keep
+    }
+    fn get_next(&self, now: &mut LazyNow) -> Next {
+        let now = now.now();
+        let time = self.time();
+        // If it's due right now, it runs today.
+        let date = if now.time() <= time {
+            now.date()
+        } else {
+            match now.date().succ_opt() {
+                Some(date) => date,
+                None => return Next::Unknown,
+            }
+        };
+        Next::At(
+            date.and_time(time),
+            ClonableCommand::clone(&self.command).into_inner(),
+        )
+    }
+    fn description(&self) -> &str {
+        self.description.as_str()
+    }
+    fn kind(&self) -> &str {
+        "Relative to bedtime"
+    }
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+}
+
+/// Wraps a [`Scheduler`], skipping any occurrences on the excluded dates, such as holidays.
+#[derive(Debug)]
+pub struct ExcludeDatesScheduler<S: Scheduler> {
+    inner: S,
+    excluded: HashSet<NaiveDate>,
+}
+impl<S: Scheduler> ExcludeDatesScheduler<S> {
+    pub fn new(inner: S, excluded: HashSet<NaiveDate>) -> Self {
+        Self { inner, excluded }
+    }
+    pub fn ref_excluded(&self) -> &HashSet<NaiveDate> {
+        &self.excluded
+    }
+    pub fn mut_excluded(&mut self) -> &mut HashSet<NaiveDate> {
+        &mut self.excluded
+    }
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+impl<S: Scheduler> Scheduler for ExcludeDatesScheduler<S> {
+    fn advance(&mut self) -> Keep {
+        self.inner.advance()
+    }
+    fn advance_past(&mut self, occurrence: NaiveDateTime) -> Keep {
+        self.inner.advance_past(occurrence)
+    }
+    fn get_next(&self, now: &mut LazyNow) -> Next {
+        let mut next = self.inner.get_next(now);
+        // Every excluded date can be skipped once, at most.
+        for _ in 0..=self.excluded.len() {
+            match &next {
+                Next::At(date_time, _) if self.excluded.contains(&date_time.date()) => {
+                    let next_day = match date_time
+                        .date()
+                        .succ_opt()
+                        .and_then(|date| date.and_hms_opt(0, 0, 0))
+                    {
+                        Some(next_day) => next_day,
+                        None => return Next::Unknown,
+                    };
+                    next = self.inner.get_next(&mut LazyNow::at(next_day));
+                }
+                _ => return next,
+            }
+        }
+        Next::Unknown
+    }
+    fn description(&self) -> &str {
+        self.inner.description()
+    }
+    fn kind(&self) -> &str {
+        self.inner.kind()
+    }
+    fn priority(&self) -> i32 {
+        self.inner.priority()
+    }
+    fn transition(&self) -> Option<Transition> {
+        self.inner.transition()
+    }
+}
+
+/// Wraps a [`Scheduler`], moving each occurrence by a random offset within `±max_jitter`.
+///
+/// A new offset is picked every time it runs. The result is never before now.
+#[derive(Debug)]
+pub struct JitterScheduler<S: Scheduler> {
+    inner: S,
+    max_jitter: chrono::Duration,
+    offset: chrono::Duration,
+    rng: Rng,
+    /// The occurrence of `inner` which last ran. Occurrences up to it don't run again.
+    /// With a negative offset, we run before it has passed, so it must not be run again.
+    fired: Option<NaiveDateTime>,
+}
+impl<S: Scheduler> JitterScheduler<S> {
+    pub fn new(inner: S, max_jitter: chrono::Duration) -> Self {
+        Self::with_rng(inner, max_jitter, Rng::from_time())
+    }
+    /// Use a seeded [`Rng`] to get the same offsets every time.
+    pub fn with_rng(inner: S, max_jitter: chrono::Duration, mut rng: Rng) -> Self {
+        let offset = Self::roll(&mut rng, max_jitter);
+        Self {
+            inner,
+            max_jitter,
+            offset,
+            rng,
+            fired: None,
+        }
+    }
+    fn roll(rng: &mut Rng, max_jitter: chrono::Duration) -> chrono::Duration {
+        let max = max_jitter.num_milliseconds().abs() as f64;
+        chrono::Duration::milliseconds((rng.next_signed_f64() * max).round() as i64)
+    }
+    /// The offset applied to the next occurrence.
+    pub fn offset(&self) -> chrono::Duration {
+        self.offset
+    }
+    pub fn max_jitter(&self) -> chrono::Duration {
+        self.max_jitter
+    }
+}
+impl<S: Scheduler> Scheduler for JitterScheduler<S> {
+    fn advance(&mut self) -> Keep {
+        self.offset = Self::roll(&mut self.rng, self.max_jitter);
+        self.inner.advance()
+    }
+    fn advance_past(&mut self, occurrence: NaiveDateTime) -> Keep {
+        // The occurrence of `inner`, unless it was moved up to now since it was already late.
+        // Then, it's after the one of `inner`, which is skipped all the same.
+        let inner = occurrence - self.offset;
+        self.fired = Some(inner);
+        self.offset = Self::roll(&mut self.rng, self.max_jitter);
+        self.inner.advance_past(inner)
+    }
+    fn get_next(&self, now: &mut LazyNow) -> Next {
+        let mut next = self.inner.get_next(now);
+        if let (Next::At(at, _), Some(fired)) = (&next, self.fired) {
+            if *at <= fired {
+                next = self
+                    .inner
+                    .get_next(&mut LazyNow::at(fired + chrono::Duration::seconds(1)));
+            }
+        }
+        match next {
+            Next::At(at, command) => {
+                let now = now.now();
+                let jittered = at + self.offset;
+                Next::At(if jittered < now { now } else { jittered }, command)
+            }
+            Next::Unknown => Next::Unknown,
+        }
+    }
+    fn description(&self) -> &str {
+        self.inner.description()
+    }
+    fn kind(&self) -> &str {
+        self.inner.kind()
+    }
+    fn priority(&self) -> i32 {
+        self.inner.priority()
+    }
+    fn transition(&self) -> Option<Transition> {
+        self.inner.transition()
+    }
+}
+
+/// Runs whichever of several [`Scheduler`]s is due first.
+///
+/// When they're due at the same time, the first in the list runs.
+/// Schedulers which want to be removed are; it asks to be removed once all of them are gone.
+#[derive(Debug)]
+pub struct EarliestScheduler {
+    schedulers: Vec<Box<dyn Scheduler>>,
+    description: String,
+}
+impl EarliestScheduler {
+    pub fn new(schedulers: Vec<Box<dyn Scheduler>>, description: String) -> Self {
+        Self {
+            schedulers,
+            description,
+        }
+    }
+    pub fn ref_schedulers(&self) -> &[Box<dyn Scheduler>] {
+        &self.schedulers
+    }
+    pub fn into_inner(self) -> Vec<Box<dyn Scheduler>> {
+        self.schedulers
+    }
+    /// The index of the scheduler due first, with its occurrence.
+    fn earliest(&self, now: &mut LazyNow) -> Option<(usize, NaiveDateTime, Command)> {
+        let mut earliest: Option<(usize, NaiveDateTime, Command)> = None;
+        for (index, scheduler) in self.schedulers.iter().enumerate() {
+            if let Next::At(at, command) = scheduler.get_next(now) {
+                let earlier = match &earliest {
+                    Some((_, earliest, _)) => at < *earliest,
+                    None => true,
+                };
+                if earlier {
+                    earliest = Some((index, at, command));
+                }
+            }
+        }
+        earliest
+    }
+}
+impl Scheduler for EarliestScheduler {
+    /// Advances the scheduler due first from now on the [`SystemClock`]. The controller uses
+    /// [`Scheduler::advance_past()`], which knows which occurrence ran.
+    fn advance(&mut self) -> Keep {
+        self.advance_past(SystemClock.now())
+    }
+    fn advance_past(&mut self, occurrence: NaiveDateTime) -> Keep {
+        // The schedulers are due at or after `now`, so the one which ran is the first at `occurrence`.
+        if let Some((index, _, _)) = self.earliest(&mut LazyNow::at(occurrence)) {
+            if let Keep::Remove = self.schedulers[index].advance_past(occurrence) {
+                self.schedulers.remove(index);
+            }
+        }
+        match self.schedulers.is_empty() {
+            true => Keep::Remove,
+            false => Keep::Keep,
+        }
+    }
+    /// The command is the [transition](Scheduler::transition()) of the scheduler due first,
+    /// if it has one, since it can't be known later which one it was.
+    fn get_next(&self, now: &mut LazyNow) -> Next {
+        match self.earliest(now) {
+            Some((index, at, command)) => match self.schedulers[index].transition() {
+                Some(transition) => Next::At(at, Command::SetTransition(transition)),
+                None => Next::At(at, command),
+            },
+            None => Next::Unknown,
+        }
+    }
+    fn description(&self) -> &str {
+        &self.description
+    }
+    fn kind(&self) -> &str {
+        "earliest"
+    }
+    /// The highest priority of the schedulers.
+    fn priority(&self) -> i32 {
+        self.schedulers
+            .iter()
+            .map(|scheduler| scheduler.priority())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// A scheduler became due and its transition was applied.
+/// See [`crate::ControllerChannel::observe_schedulers()`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SchedulerEvent {
+    /// The name of the scheduler, or `None` for the [`WeekScheduler`].
+    pub name: Option<String>,
+    /// When it was due. It fires slightly after.
+    pub at: NaiveDateTime,
+}
+
+/// An [`EventListener`] call held back by [`State`].
+#[derive(Debug)]
+enum ListenerEvent {
+    TransitionStart(Transition),
+    TransitionComplete(Transition),
+}
+
+/// A snapshot of a named [`Scheduler`], for showing to users.
+/// See [`Command::ListSchedulers`].
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SchedulerInfo {
+    pub name: String,
+    /// See [`Scheduler::kind()`].
+    pub kind: String,
+    pub description: String,
+    /// From [`Scheduler::get_next()`]. `None` if it's unknown.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialize::opt_date_time"))]
+    pub next: Option<NaiveDateTime>,
+    pub enabled: bool,
+    pub priority: i32,
+}
+impl SchedulerInfo {
+    pub fn new(name: String, scheduler: &dyn Scheduler, enabled: bool, now: &mut LazyNow) -> Self {
+        Self {
+            name,
+            kind: scheduler.kind().to_string(),
+            description: scheduler.description().to_string(),
+            next: match scheduler.get_next(now) {
+                Next::At(date_time, _) => Some(date_time),
+                Next::Unknown => None,
+            },
+            enabled,
+            priority: scheduler.priority(),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, PartialOrd, Clone)]
 pub enum TransitionStateOut {
     Ongoing(Strength),
@@ -230,66 +969,128 @@ impl TransitionState {
         }
     }
 
-    pub fn process(&mut self, delta_time: &Duration) -> TransitionStateOut {
-        let delta_progress = self.calculate_delta_progress(delta_time);
-        const HALF_PI: f64 = core::f64::consts::PI / 2.0;
-        const PI: f64 = core::f64::consts::PI;
-
+    pub fn get_transition(&self) -> &Transition {
+        &self.transition
+    }
+    /// How far the transition has come, from `0.0` to `1.0`.
+    /// For the to-and-back interpolations, the way back is included.
+    /// See [`Transition::progress_at()`].
+    pub fn progress(&self) -> f64 {
+        self.transition.progress_at(self.elapsed())
+    }
+    /// How long the transition has run, at its current speed.
+    pub fn elapsed(&self) -> Duration {
+        self.transition.time.mul_f64(self.progress)
+    }
+    /// The `progress` at which the transition is finished.
+    fn end(&self) -> f64 {
         match self.transition.interpolation {
-            TransitionInterpolation::Linear => {
-                self.standard_interpolation(|progress| progress, delta_progress)
-            }
-            TransitionInterpolation::Sine => self.standard_interpolation(
-                |progress| ((progress * PI - HALF_PI).sin() + 1.0) / 2.0,
-                delta_progress,
-            ),
+            TransitionInterpolation::LinearToAndBack(multiplier)
+            | TransitionInterpolation::SineToAndBack(multiplier) => multiplier + 1.0,
+            _ => 1.0,
+        }
+    }
+    /// Scales the speed of the rest of the transition by `factor`.
+    ///
+    /// Since `progress` is relative to [`Transition::time`], only the time has to change
+    /// for the output to stay continuous.
+    pub fn set_speed(&mut self, factor: f64) {
+        self.transition.time = self.transition.time.div_f64(factor);
+    }
 
-            TransitionInterpolation::LinearToAndBack(multiplier) => {
-                self.and_back_interpolation(|zero_to_one| zero_to_one, delta_progress, multiplier)
-            }
-            TransitionInterpolation::SineToAndBack(multiplier) => self.and_back_interpolation(
-                |zero_to_one| ((zero_to_one * PI - HALF_PI).sin() + 1.0) / 2.0,
-                delta_progress,
-                multiplier,
-            ),
+    pub fn process(&mut self, delta_time: &Duration) -> TransitionStateOut {
+        if let TransitionInterpolation::Constant = self.transition.interpolation {
+            // Don't touch `time`; it can be zero, which would divide by zero below.
+            return TransitionStateOut::Finished(self.transition.to);
+        }
+        self.progress += self.calculate_delta_progress(delta_time);
+        let strength = self.transition.strength_at_progress(self.progress);
+        if self.progress >= self.end() {
+            TransitionStateOut::Finished(strength)
+        } else {
+            TransitionStateOut::Ongoing(strength)
         }
     }
     fn calculate_delta_progress(&self, delta_time: &Duration) -> f64 {
         delta_time.as_secs_f64() / self.transition.time.as_secs_f64()
     }
-    fn remap(zero_to_one: f64, zero: f64, one: f64) -> f64 {
-        zero_to_one * (one - zero) + zero
-    }
-    fn remap_and_check_finish(&self, strength: f64, finish: f64) -> TransitionStateOut {
-        let remapped = Self::remap(strength, self.transition.from.0, self.transition.to.0);
-        if self.progress >= finish {
-            TransitionStateOut::Finished(Strength::new_clamped(remapped))
-        } else {
-            TransitionStateOut::Ongoing(Strength::new(remapped))
+}
+
+/// A looping change of the strength, played instead of a transition until stopped.
+/// See [`Command::StartFlicker`], [`Command::StartStrobe`], [`Command::StartBreathing`],
+/// and [`Command::StopEffect`].
+#[derive(Debug, PartialEq, Clone)]
+pub enum Effect {
+    /// See [`Command::StartFlicker`].
+    Flicker {
+        base: Strength,
+        amplitude: f64,
+        rate: Duration,
+    },
+    /// See [`Command::StartStrobe`].
+    Strobe {
+        low: Strength,
+        high: Strength,
+        period: Duration,
+    },
+    /// See [`Command::StartBreathing`].
+    Breathing {
+        low: Strength,
+        high: Strength,
+        period: Duration,
+    },
+}
+impl Effect {
+    /// The command starting this effect.
+    pub fn command(&self) -> Command {
+        match self.clone() {
+            Self::Flicker {
+                base,
+                amplitude,
+                rate,
+            } => Command::StartFlicker {
+                base,
+                amplitude,
+                rate,
+            },
+            Self::Strobe { low, high, period } => Command::StartStrobe { low, high, period },
+            Self::Breathing { low, high, period } => Command::StartBreathing { low, high, period },
         }
     }
-    fn standard_interpolation<F: Fn(f64) -> f64>(
-        &mut self,
-        strength: F,
-        delta_progress: f64,
-    ) -> TransitionStateOut {
-        self.progress += delta_progress;
-        self.remap_and_check_finish(strength(self.progress), 1.0)
+    /// How long to wait between the updates.
+    /// Smooth effects are updated as often as transitions, every `update_interval`.
+    pub fn interval(&self, update_interval: Duration) -> Duration {
+        match self {
+            Self::Flicker { rate, .. } => (*rate).max(EFFECT_INTERVAL_MIN),
+            Self::Strobe { period, .. } => (*period).max(STROBE_PERIOD_MIN) / 2,
+            Self::Breathing { .. } => update_interval.max(EFFECT_INTERVAL_MIN),
+        }
     }
-    fn and_back_interpolation<F: Fn(f64) -> f64>(
-        &mut self,
-        function: F,
-        delta_progress: f64,
-        multiplier: f64,
-    ) -> TransitionStateOut {
-        self.progress += delta_progress;
-        let progress = self.progress.clamp(0.0, multiplier + 1.0);
-        let strength = if progress > 1.0 {
-            function(1.0 - ((progress - 1.0) / multiplier))
-        } else {
-            function(progress)
-        };
-        self.remap_and_check_finish(strength, multiplier + 1.0)
+    /// The strength of the `update`th update, `elapsed` after the effect started.
+    /// Random effects take the next values from `rng`.
+    ///
+    /// Effects which alternate count the updates, as the updates are a bit late every time.
+    pub fn strength_at(&self, elapsed: Duration, update: u64, rng: &mut Rng) -> Strength {
+        match self {
+            Self::Breathing { low, high, period } => {
+                // A sine there and back, taking as long each way, is one breath.
+                let breath = Transition {
+                    from: *low,
+                    to: *high,
+                    time: (*period).max(STROBE_PERIOD_MIN) / 2,
+                    interpolation: TransitionInterpolation::SineToAndBack(1.0),
+                };
+                let progress = elapsed.as_secs_f64() / breath.time.as_secs_f64();
+                breath.strength_at_progress(progress % 2.0)
+            }
+            Self::Strobe { low, high, .. } => match update % 2 {
+                0 => *high,
+                _ => *low,
+            },
+            Self::Flicker {
+                base, amplitude, ..
+            } => Strength::new_clamped(base.into_inner() + amplitude.abs() * rng.next_signed_f64()),
+        }
     }
 }
 
@@ -299,34 +1100,260 @@ pub enum SleepTime {
     Forever,
 }
 
+/// Answers a command's `callback`.
+fn reply<T>(callback: &mpsc::Sender<T>, value: T) {
+    // The receiver may have been dropped; they don't care then.
+    let _ = callback.send(value);
+}
+
 pub struct State {
     // Data
     shared: Arc<Mutex<SharedState>>,
+    clock: Arc<dyn Clock>,
 
     finish: bool,
     wake_up: Option<(NaiveDateTime, Command)>,
     transition: Option<TransitionState>,
+    /// Notified when the current `transition` ends.
+    transition_callback: Option<mpsc::Sender<TransitionOutcome>>,
+    /// Played after `transition`, in order. See [`Command::QueueTransition`].
+    transition_queue: VecDeque<Transition>,
+    /// How many more times `transition` is played after this time.
+    /// See [`Command::SetRepeatingTransition`].
+    repeats_left: Repeat,
+    /// The value of the ongoing transition when it was last processed.
+    transition_output: Option<Strength>,
+    /// See [`Command::Pause`].
+    paused: bool,
+    /// See [`Command::SetUpdateInterval`].
+    update_interval: Duration,
+    /// See [`Command::SetDryRun`].
+    dry_run: bool,
+    /// The warmth the current transition fades from and to. See [`Command::SetColorTempTransition`].
+    warmth_fade: Option<(f64, f64)>,
+    /// The running effect, when it started, and how many times it's been updated.
+    effect: Option<(Effect, Instant, u64)>,
+    /// Used by random effects. See [`State::with_rng()`].
+    rng: Rng,
+    /// See [`Command::SetLayer`].
+    layers: HashMap<String, Strength>,
+    blend_mode: BlendMode,
+    /// The last strengths set, oldest first. Holds at most [`HISTORY_CAPACITY`].
+    history: VecDeque<(Instant, Strength)>,
+    /// The commands reverting the last reversible ones, the latest last.
+    /// Holds at most [`UNDO_CAPACITY`]. See [`Command::Undo`].
+    undo: VecDeque<Command>,
     last_instance: Instant,
     last_scheduler: Option<String>,
+    /// Schedulers with lower priority due at the same time as `last_scheduler`, and when.
+    /// `None` is the week scheduler.
+    suppressed: Vec<(Option<String>, NaiveDateTime)>,
+    /// Notified of every [`SchedulerEvent`]. Full observers miss events.
+    observers: Arc<Mutex<Vec<mpsc::SyncSender<SchedulerEvent>>>>,
+    listeners: Arc<Mutex<Vec<Box<dyn EventListener>>>>,
+    /// Events which happened while getting the output, held back until it's set.
+    /// See [`State::process()`].
+    deferred: Vec<ListenerEvent>,
 }
 impl State {
     pub fn new(state: Arc<Mutex<SharedState>>) -> Self {
+        Self::with_clock(state, Arc::new(SystemClock))
+    }
+    pub fn with_clock(state: Arc<Mutex<SharedState>>, clock: Arc<dyn Clock>) -> Self {
         Self {
             shared: state,
+            clock,
             finish: false,
             wake_up: None,
             transition: None,
+            transition_callback: None,
+            transition_queue: VecDeque::new(),
+            repeats_left: Repeat::Times(0),
+            transition_output: None,
+            paused: false,
+            update_interval: DEFAULT_UPDATE_INTERVAL,
+            dry_run: false,
+            warmth_fade: None,
+            effect: None,
+            rng: Rng::from_time(),
+            layers: HashMap::new(),
+            blend_mode: BlendMode::default(),
+            history: VecDeque::new(),
+            undo: VecDeque::new(),
             last_instance: Instant::now(),
             last_scheduler: None,
+            suppressed: Vec::new(),
+            observers: Arc::new(Mutex::new(Vec::new())),
+            listeners: Arc::new(Mutex::new(Vec::new())),
+            deferred: Vec::new(),
         }
     }
+    /// Use a seeded [`Rng`] to get the same random effects every time.
+    pub fn with_rng(mut self, rng: Rng) -> Self {
+        self.rng = rng;
+        self
+    }
+    /// Notifies `observers` of every [`SchedulerEvent`].
+    /// Senders are removed once their receiver is dropped.
+    pub fn with_observers(
+        mut self,
+        observers: Arc<Mutex<Vec<mpsc::SyncSender<SchedulerEvent>>>>,
+    ) -> Self {
+        self.observers = observers;
+        self
+    }
 
-    pub fn process(&mut self, command: Option<Command>) -> Action {
-        match command {
+    /// Notifies `listeners` of the events, on the thread calling [`State::process()`].
+    pub fn with_listeners(mut self, listeners: Arc<Mutex<Vec<Box<dyn EventListener>>>>) -> Self {
+        self.listeners = listeners;
+        self
+    }
+    /// A new state with the same [`SharedState`], clock, observers and listeners as this one.
+    pub(crate) fn restarted(&self) -> Self {
+        Self::with_clock(Arc::clone(&self.shared), Arc::clone(&self.clock))
+            .with_observers(Arc::clone(&self.observers))
+            .with_listeners(Arc::clone(&self.listeners))
+    }
+    /// Clears the poison a panic while holding them left on the locks shared with other threads.
+    pub(crate) fn clear_poison(&self) {
+        self.shared.clear_poison();
+        self.observers.clear_poison();
+        self.listeners.clear_poison();
+    }
+    /// The commands bringing a [restarted](Self::restarted()) state to where this one is:
+    /// the dry run and update interval, the blend mode and layers, the strength,
+    /// the running transition or effect, and whether it's paused.
+    ///
+    /// Queued and repeated transitions and fading warmth aren't replayed.
+    pub(crate) fn replay(&self) -> VecDeque<Command> {
+        let mut commands = VecDeque::new();
+        if self.dry_run {
+            commands.push_back(Command::SetDryRun(true));
+        }
+        if self.update_interval != DEFAULT_UPDATE_INTERVAL {
+            commands.push_back(Command::SetUpdateInterval(self.update_interval));
+        }
+        commands.push_back(Command::SetBlendMode(self.blend_mode.clone()));
+        for (name, strength) in &self.layers {
+            commands.push_back(Command::SetLayer(name.clone(), *strength));
+        }
+        commands.push_back(Command::Set(*self.shared.lock().unwrap().get_strength()));
+        if let Some(transition) = &self.transition {
+            // Timed from when it started, so it continues where it was.
+            let start = Instant::now()
+                .checked_sub(transition.elapsed())
+                .unwrap_or_else(Instant::now);
+            commands.push_back(Command::SetTransitionAt(
+                Transition::clone(transition.get_transition()),
+                start,
+            ));
+        } else if let Some((effect, _, _)) = &self.effect {
+            commands.push_back(effect.command());
+        }
+        if self.paused {
+            commands.push_back(Command::Pause);
+        }
+        commands
+    }
+
+    pub fn process(&mut self, command: Option<Command>) -> Action {
+        let action = self.apply_command(command);
+        let action = match action {
+            Action::Set(base) => Action::Set(self.output_strength(base)),
+            Action::SetColorTemp(base, warmth) => {
+                Action::SetColorTemp(self.output_strength(base), warmth)
+            }
+            action => action,
+        };
+        // E.g. a transition completes after its last strength is set.
+        for event in std::mem::take(&mut self.deferred) {
+            match event {
+                ListenerEvent::TransitionStart(transition) => {
+                    self.notify_listeners(|listener| listener.on_transition_start(&transition))
+                }
+                ListenerEvent::TransitionComplete(transition) => {
+                    self.notify_listeners(|listener| listener.on_transition_complete(&transition))
+                }
+            }
+        }
+        action
+    }
+    /// Handles `command`, without blending the output with the layers.
+    fn apply_command(&mut self, command: Option<Command>) -> Action {
+        if let Some(command) = command.as_ref() {
+            info!("Handling {}", command);
+        }
+        let changes_config = command.as_ref().is_some_and(Command::changes_config);
+        let action = self.handle(command);
+        if changes_config {
+            self.shared.lock().unwrap().config_changed();
+        }
+        action
+    }
+    /// Records `base` and returns the blend with the layers, which is set on the output.
+    fn output_strength(&mut self, base: Strength) -> Strength {
+        // Other commands and transitions continue from the base, not the blend.
+        self.shared.lock().unwrap().set_output_strength(base);
+        let strength = self.blend_mode.blend(base, &self.layers);
+        if self.history.len() == HISTORY_CAPACITY {
+            self.history.pop_front();
+        }
+        self.history.push_back((Instant::now(), strength));
+        self.notify_listeners(|listener| listener.on_set(strength));
+        strength
+    }
+    /// If [`Action::Set`] should only be logged. See [`Command::SetDryRun`].
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+    /// How long to wait before processing the ongoing transition or [`Effect`] again, if there is one.
+    /// Commands should still be handled in the meantime.
+    pub fn transition_interval(&self) -> Option<Duration> {
+        if self.paused {
+            return None;
+        }
+        match (&self.transition, &self.effect) {
+            (Some(_), _) => Some(self.update_interval),
+            (None, Some((effect, _, _))) => Some(effect.interval(self.update_interval)),
+            (None, None) => None,
+        }
+    }
+    /// Handles the commands of a [`Command::Batch`] in order,
+    /// returning only the output after the last one.
+    fn handle_batch(&mut self, commands: Vec<Command>) -> Action {
+        let mut last = None;
+        let mut output = None;
+        for command in commands {
+            let action = self.handle(Some(command));
+            if let Action::Set(_) | Action::SetRgb(_, _, _) | Action::SetColorTemp(_, _) = action {
+                output = Some(action.clone());
+            }
+            last = Some(action);
+        }
+        match (last, output) {
+            // A later command, e.g. changing the schedule, mustn't hide an earlier `Set`.
+            (Some(Action::Wait(_)), Some(output)) => output,
+            (Some(action), _) => action,
+            (None, _) => self.get_next(),
+        }
+    }
+    fn push_undo(&mut self, command: Command) {
+        if self.undo.len() == UNDO_CAPACITY {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(command);
+    }
+    fn handle(&mut self, command: Option<Command>) -> Action {
+        match command {
             Some(command) => match command {
                 Command::Finish => {
                     // set finish flag
                     self.finish = true;
+                    // Else, the transition would never finish.
+                    self.set_paused(false);
+                    // Only let the current transition finish.
+                    self.transition_queue.clear();
+                    self.repeats_left = Repeat::Times(0);
                     match self.get_transition_output() {
                         // if no animation is going, return break
                         None => Action::Break,
@@ -336,8 +1363,15 @@ impl State {
                     }
                 }
                 Command::Set(strength) => {
+                    let previous = *self.shared.lock().unwrap().get_strength();
+                    self.push_undo(Command::Set(previous));
                     // clear animation
                     self.transition = None;
+                    self.transition_queue.clear();
+                    self.repeats_left = Repeat::Times(0);
+                    self.resolve_callback(TransitionOutcome::Cancelled);
+                    self.effect = None;
+                    self.warmth_fade = None;
                     self.shared
                         .lock()
                         .unwrap()
@@ -345,20 +1379,151 @@ impl State {
                     // send back set
                     Action::Set(strength)
                 }
+                Command::SetTransitionSpeed(factor) => {
+                    // Catch up on the time passed at the old speed.
+                    let output = self.get_transition_output();
+                    if let Some(transition) = self.transition.as_mut() {
+                        if !factor.is_nan() {
+                            transition.set_speed(
+                                factor.clamp(TRANSITION_SPEED_MIN, TRANSITION_SPEED_MAX),
+                            );
+                            let new = Transition::clone(transition.get_transition());
+                            self.shared.lock().unwrap().set_transition(Some(new));
+                        }
+                    }
+                    match output {
+                        Some(s) => Action::Set(s),
+                        None => self.get_next(),
+                    }
+                }
+                Command::SetUpdateInterval(interval) => {
+                    self.update_interval = interval;
+                    self.get_next()
+                }
+                Command::SetLayer(name, strength) => {
+                    self.layers.insert(name, strength);
+                    self.set_blended()
+                }
+                Command::RemoveLayer(name) => match self.layers.remove(&name) {
+                    Some(_) => self.set_blended(),
+                    None => self.get_next(),
+                },
+                Command::SetBlendMode(mode) => {
+                    self.blend_mode = mode;
+                    self.set_blended()
+                }
+                Command::SaveScene(name, scene) => {
+                    self.shared.lock().unwrap().mut_scenes().insert(name, scene);
+                    self.get_next()
+                }
+                Command::RecallScene(name) => match self.recall_scene(&name) {
+                    Some(action) => action,
+                    None => {
+                        warn!("No scene named {:?} to recall", name);
+                        self.get_next()
+                    }
+                },
+                Command::RecallSceneWithCallback(name, callback) => {
+                    let action = self.recall_scene(&name);
+                    reply(&callback, action.is_some());
+                    action.unwrap_or_else(|| self.get_next())
+                }
+                Command::RemoveScene(name) => {
+                    self.shared.lock().unwrap().mut_scenes().remove(&name);
+                    self.get_next()
+                }
+                Command::RemoveSceneWithCallback(name, callback) => {
+                    let removed = self.shared.lock().unwrap().mut_scenes().remove(&name);
+                    reply(&callback, removed.is_some());
+                    self.get_next()
+                }
+                Command::StartFlicker {
+                    base,
+                    amplitude,
+                    rate,
+                } => self.start_effect(Effect::Flicker {
+                    base,
+                    amplitude,
+                    rate,
+                }),
+                Command::StartStrobe { low, high, period } => {
+                    self.start_effect(Effect::Strobe { low, high, period })
+                }
+                Command::StartBreathing { low, high, period } => {
+                    self.start_effect(Effect::Breathing { low, high, period })
+                }
+                Command::SetColorTemp { brightness, warmth } => {
+                    let warmth = crate::output::clamp_warmth(warmth);
+                    self.shared.lock().unwrap().set_warmth(warmth);
+                    match self.handle(Some(Command::Set(brightness))) {
+                        Action::Set(strength) => Action::SetColorTemp(strength, warmth),
+                        action => action,
+                    }
+                }
+                Command::SetColorTempTransition { transition, warmth } => {
+                    let from = self.shared.lock().unwrap().get_warmth();
+                    let action = self.start_transition(transition, None);
+                    self.warmth_fade = Some((from, crate::output::clamp_warmth(warmth)));
+                    self.with_warmth(action)
+                }
+                Command::StopEffect => {
+                    self.effect = None;
+                    self.get_next()
+                }
+                Command::SetDryRun(enabled) => {
+                    let was_enabled = std::mem::replace(&mut self.dry_run, enabled);
+                    match was_enabled && !enabled {
+                        // Catch the output up with what was logged.
+                        true => Action::Set(*self.shared.lock().unwrap().get_strength()),
+                        false => self.get_next(),
+                    }
+                }
                 Command::ChangeDayTimer(day, time) => {
                     // change time of day
-                    {
+                    let previous = {
                         let mut lock = self.shared.lock().unwrap();
-                        *lock.mut_week_scheduler().get_mut(day) = time;
+                        let previous = lock.mut_week_scheduler().set(day, time);
                         lock.mut_week_scheduler().last = None;
+                        previous
+                    };
+                    self.push_undo(Command::ChangeDayTimer(day, previous));
+                    self.requeue_sleep();
+                    self.get_next()
+                }
+                Command::ScheduleDiff(diff, callback) => {
+                    let changed = {
+                        let mut lock = self.shared.lock().unwrap();
+                        let changed = lock.mut_week_scheduler().apply_diff(&diff);
+                        if !changed.is_empty() {
+                            lock.mut_week_scheduler().last = None;
+                        }
+                        changed
+                    };
+                    if !changed.is_empty() {
+                        self.requeue_sleep();
                     }
+                    reply(&callback, changed);
                     self.get_next()
                 }
                 Command::ChangeDayTimerTransition(new_transition) => {
+                    let previous = std::mem::replace(
+                        &mut self.shared.lock().unwrap().mut_week_scheduler().transition,
+                        new_transition,
+                    );
+                    self.push_undo(Command::ChangeDayTimerTransition(previous));
+                    // The lead of the transition moves when the day starts.
+                    self.requeue_sleep();
+                    self.get_next()
+                }
+                Command::ChangeDayTransition(day, transition) => {
                     {
-                        self.shared.lock().unwrap().mut_week_scheduler().transition =
-                            new_transition;
+                        self.shared
+                            .lock()
+                            .unwrap()
+                            .mut_week_scheduler()
+                            .set_day_transition(day, transition);
                     }
+                    self.requeue_sleep();
                     self.get_next()
                 }
                 Command::AddReplaceScheduler(name, scheduler) => {
@@ -367,53 +1532,251 @@ impl State {
                         .unwrap()
                         .mut_schedulers()
                         .insert(name, scheduler);
+                    // The pending wake-up may be the scheduler just replaced,
+                    // or come after the one just added.
+                    self.requeue_sleep();
                     self.get_next()
                 }
                 Command::RemoveScheduler(name) => {
-                    self.shared.lock().unwrap().mut_schedulers().remove(&name);
+                    self.remove_scheduler(&name);
+                    self.get_next()
+                }
+                Command::RemoveSchedulerWithCallback(name, callback) => {
+                    let removed = self.remove_scheduler(&name);
+                    reply(&callback, removed);
+                    self.get_next()
+                }
+                Command::SetSchedulerEnabled(name, enabled) => {
+                    self.set_scheduler_enabled(name, enabled);
+                    self.get_next()
+                }
+                Command::SetSchedulerEnabledWithCallback(name, enabled, callback) => {
+                    let exists = self.set_scheduler_enabled(name, enabled);
+                    reply(&callback, exists);
+                    self.get_next()
+                }
+                Command::ListSchedulers(callback) => {
+                    let infos = {
+                        let lock = self.shared.lock().unwrap();
+                        lock.scheduler_infos(&mut LazyNow::from_clock(Arc::clone(&self.clock)))
+                    };
+                    reply(&callback, infos);
+                    self.get_next()
+                }
+                Command::GetDayTransition(callback) => {
+                    let day = self.clock.now().weekday();
+                    let transition = Transition::clone(
+                        self.shared
+                            .lock()
+                            .unwrap()
+                            .ref_week_schedule()
+                            .get_transition(day),
+                    );
+                    reply(&callback, transition);
+                    self.get_next()
+                }
+                Command::GetStatus(callback) => {
+                    let status = {
+                        let lock = self.shared.lock().unwrap();
+                        Status {
+                            strength: *lock.get_strength(),
+                            transition: self
+                                .transition
+                                .as_ref()
+                                .map(|state| Transition::clone(state.get_transition())),
+                            transition_progress: self
+                                .transition
+                                .as_ref()
+                                .map(TransitionState::progress),
+                            week_scheduler: lock.ref_week_schedule().clone(),
+                            schedulers: lock
+                                .scheduler_infos(&mut LazyNow::from_clock(Arc::clone(&self.clock))),
+                            paused: self.paused,
+                            dry_run: self.dry_run,
+                        }
+                    };
+                    reply(&callback, status);
+                    self.get_next()
+                }
+                Command::GetHistory(callback) => {
+                    let now = Instant::now();
+                    let history = self
+                        .history
+                        .iter()
+                        .map(|(instant, strength)| (now - *instant, *strength))
+                        .collect();
+                    reply(&callback, history);
+                    self.get_next()
+                }
+                Command::SetRgb(red, green, blue) => {
+                    self.shared.lock().unwrap().set_rgb((red, green, blue));
+                    Action::SetRgb(red, green, blue)
+                }
+                Command::SetBedtime(time) => {
+                    self.shared.lock().unwrap().ref_bedtime().set(time);
+                    self.requeue_sleep();
                     self.get_next()
                 }
                 Command::ClearAllSchedulers => {
-                    self.shared.lock().unwrap().mut_schedulers().clear();
+                    {
+                        let mut lock = self.shared.lock().unwrap();
+                        lock.mut_schedulers().clear();
+                        lock.mut_disabled_schedulers().clear();
+                    }
+                    self.requeue_sleep();
                     self.get_next()
                 }
-                Command::SetTransition(transition) => {
-                    self.shared
+                Command::ReplaceConfig(week_scheduler, schedulers, disabled) => {
+                    {
+                        let mut lock = self.shared.lock().unwrap();
+                        *lock.mut_week_scheduler() = *week_scheduler;
+                        *lock.mut_schedulers() = schedulers;
+                        *lock.mut_disabled_schedulers() = disabled;
+                    }
+                    self.requeue_sleep();
+                    self.get_next()
+                }
+                Command::SkipNext => {
+                    let skipped = self
+                        .shared
                         .lock()
                         .unwrap()
-                        .set_transition(Some(Transition::clone(&transition)));
-                    self.transition = Some(TransitionState::new(transition));
-                    self.last_instance = Instant::now();
+                        .mut_week_scheduler()
+                        .skip_next(&mut LazyNow::from_clock(Arc::clone(&self.clock)));
+                    if let Some(skipped) = skipped {
+                        info!("Skipping the occurrence at {}", skipped);
+                    }
+                    // Sleep to the one after instead.
+                    self.requeue_sleep();
+                    self.get_next()
+                }
+                command @ Command::Batch(_) if !command.is_valid_batch() => {
+                    warn!(
+                        "Ignoring {}; batches can't contain batches or Finish",
+                        command
+                    );
+                    self.get_next()
+                }
+                Command::Batch(commands) => self.handle_batch(commands),
+                Command::Undo => match self.undo.pop_back() {
+                    Some(command) => {
+                        info!("Undoing with {}", command);
+                        // `process` only knows if the config changes after handling this.
+                        if command.changes_config() {
+                            self.shared.lock().unwrap().config_changed();
+                        }
+                        let action = self.handle(Some(command));
+                        // Handling it pushed the command undoing the undo.
+                        self.undo.pop_back();
+                        action
+                    }
+                    None => self.get_next(),
+                },
+                Command::SetTransition(transition) => self.start_transition(transition, None),
+                Command::SetTransitionWithCallback(transition, callback) => {
+                    self.start_transition(transition, Some(callback))
+                }
+                Command::SetTransitionAt(transition, start) => {
+                    self.start_transition_at(transition, None, start)
+                }
+                Command::QueueTransition(transition) => match self.transition {
+                    Some(_) => {
+                        self.transition_queue.push_back(transition);
+                        self.get_next()
+                    }
+                    None => self.start_transition(transition, None),
+                },
+                Command::SetRepeatingTransition(transition, repeat) => {
+                    // A constant transition finishes immediately; repeating it would never stop.
+                    let repeat = match (repeat, &transition.interpolation) {
+                        (_, TransitionInterpolation::Constant) => Repeat::Times(0),
+                        (Repeat::Times(times), _) => Repeat::Times(times.saturating_sub(1)),
+                        (Repeat::Forever, _) => Repeat::Forever,
+                    };
+                    self.resolve_callback(TransitionOutcome::Cancelled);
+                    self.transition_queue.clear();
+                    self.warmth_fade = None;
+                    self.begin_transition(transition);
+                    self.repeats_left = repeat;
                     // unwrap() is ok; we've just set transition to be `Some`
                     Action::Set(self.get_transition_output().unwrap())
                 }
+                Command::Pause => {
+                    self.set_paused(true);
+                    self.get_next()
+                }
+                Command::Resume => {
+                    self.set_paused(false);
+                    self.get_next()
+                }
+                Command::SetPausedWithCallback(paused, callback) => {
+                    let changed = self.set_paused(paused);
+                    reply(&callback, changed);
+                    self.get_next()
+                }
             },
+            // Don't wake up while paused; `get_next()` is called when resuming.
+            None if self.paused => Action::Wait(SleepTime::Forever),
             None => {
                 // check wake up Option<>
                 match self.wake() {
-                    Some(command) => {
+                    Some((at, mut command)) => {
+                        let name = self.last_scheduler.clone();
                         {
                             let mut lock = self.shared.lock().unwrap();
+                            let mut gone = false;
                             match self.last_scheduler.as_ref() {
                                 Some(name) => match lock.mut_schedulers().get_mut(name) {
-                                    Some(scheduler) => match scheduler.advance() {
-                                        Keep::Keep => {}
-                                        Keep::Remove => {
-                                            lock.mut_schedulers().remove(name);
+                                    Some(scheduler) => {
+                                        if let Some(transition) = scheduler.transition() {
+                                            command = Command::SetTransition(transition);
+                                        }
+                                        match scheduler.advance_past(at) {
+                                            Keep::Keep => {}
+                                            Keep::Remove => {
+                                                lock.mut_schedulers().remove(name);
+                                            }
                                         }
-                                    },
+                                    }
+                                    // Removing a scheduler drops its wake-up, so this shouldn't
+                                    // happen, but it's no reason to bring the controller down.
                                     None => {
-                                        panic!("attempting to get scheduler not existing. Did you clear the list?");
+                                        warn!("Scheduler {} was removed before it ran", name);
+                                        gone = true;
                                     }
                                 },
                                 None => {
-                                    // Discarding, because we know it'll want to continue.
-                                    lock.mut_week_scheduler().advance();
+                                    lock.mut_week_scheduler().advance_at(at);
+                                }
+                            }
+                            if gone {
+                                drop(lock);
+                                self.last_scheduler = None;
+                                self.suppressed.clear();
+                                return self.get_next();
+                            }
+                            for (name, at) in self.suppressed.drain(..) {
+                                match name {
+                                    Some(name) => {
+                                        if let Some(scheduler) =
+                                            lock.mut_schedulers().get_mut(&name)
+                                        {
+                                            if let Keep::Remove = scheduler.advance_past(at) {
+                                                lock.mut_schedulers().remove(&name);
+                                            }
+                                        }
+                                    }
+                                    None => {
+                                        lock.mut_week_scheduler().advance_at(at);
+                                    }
                                 }
                             }
                             self.last_scheduler = None;
                         }
-                        let action = self.process(Some(command));
+                        let event = SchedulerEvent { name, at };
+                        self.notify_listeners(|listener| listener.on_scheduler_fired(&event));
+                        let action = self.apply_command(Some(command));
+                        self.notify_observers(event);
 
                         action
                     }
@@ -424,8 +1787,111 @@ impl State {
         }
     }
 
+    fn start_transition(
+        &mut self,
+        transition: Transition,
+        callback: Option<mpsc::Sender<TransitionOutcome>>,
+    ) -> Action {
+        self.start_transition_at(transition, callback, Instant::now())
+    }
+    fn start_transition_at(
+        &mut self,
+        transition: Transition,
+        callback: Option<mpsc::Sender<TransitionOutcome>>,
+        start: Instant,
+    ) -> Action {
+        self.resolve_callback(TransitionOutcome::Cancelled);
+        self.transition_queue.clear();
+        self.repeats_left = Repeat::Times(0);
+        self.warmth_fade = None;
+        self.begin_transition(transition);
+        self.last_instance = start;
+        self.transition_callback = callback;
+        // unwrap() is ok; we've just set transition to be `Some`
+        Action::Set(self.get_transition_output().unwrap())
+    }
+    fn begin_transition(&mut self, transition: Transition) {
+        self.effect = None;
+        self.shared
+            .lock()
+            .unwrap()
+            .set_transition(Some(Transition::clone(&transition)));
+        // Keeps the order when the one before it completed in the same update.
+        if self.deferred.is_empty() {
+            self.notify_listeners(|listener| listener.on_transition_start(&transition));
+        } else {
+            let start = ListenerEvent::TransitionStart(Transition::clone(&transition));
+            self.deferred.push(start);
+        }
+        self.transition = Some(TransitionState::new(transition));
+        self.last_instance = Instant::now();
+    }
+    /// Returns whether it changed.
+    fn set_paused(&mut self, paused: bool) -> bool {
+        if self.paused == paused {
+            return false;
+        }
+        self.paused = paused;
+        if !paused {
+            // Don't count the time paused.
+            self.last_instance = Instant::now();
+        }
+        self.shared.lock().unwrap().set_paused(paused);
+        true
+    }
+    /// Returns whether the scheduler exists.
+    fn set_scheduler_enabled(&mut self, name: String, enabled: bool) -> bool {
+        {
+            let mut lock = self.shared.lock().unwrap();
+            if !lock.ref_schedulers().contains_key(&name) {
+                return false;
+            }
+            match enabled {
+                true => lock.mut_disabled_schedulers().remove(&name),
+                false => lock.mut_disabled_schedulers().insert(name),
+            };
+        }
+        // The pending wake-up may be the scheduler just disabled,
+        // or come after the one just enabled.
+        self.requeue_sleep();
+        true
+    }
+    /// Returns whether the scheduler existed.
+    fn remove_scheduler(&mut self, name: &str) -> bool {
+        let removed = {
+            let mut lock = self.shared.lock().unwrap();
+            lock.mut_disabled_schedulers().remove(name);
+            lock.mut_schedulers().remove(name).is_some()
+        };
+        // The pending wake-up may be the scheduler just removed.
+        if removed {
+            self.requeue_sleep();
+        }
+        removed
+    }
+    /// Starts the transition to the named scene, if it exists.
+    fn recall_scene(&mut self, name: &str) -> Option<Action> {
+        let transition = {
+            let lock = self.shared.lock().unwrap();
+            lock.ref_scenes()
+                .get(name)
+                .map(|scene| scene.transition_from(*lock.get_strength()))
+        };
+        transition.map(|transition| self.start_transition(transition, None))
+    }
+    /// Notifies whoever waits on the current transition, if anyone.
+    fn resolve_callback(&mut self, outcome: TransitionOutcome) {
+        if let Some(callback) = self.transition_callback.take() {
+            reply(&callback, outcome);
+        }
+    }
+
     fn get_delta_time(&mut self) -> Duration {
         let now = Instant::now();
+        // A transition set to start in the future; wait for it.
+        if now < self.last_instance {
+            return Duration::new(0, 0);
+        }
         let difference = now - self.last_instance;
         self.last_instance = now;
         difference
@@ -434,60 +1900,83 @@ impl State {
     fn get_transition_output(&mut self) -> Option<Strength> {
         if self.transition.is_some() {
             let delta_time = self.get_delta_time();
+            // Time stands still while paused.
+            let delta_time = if self.paused {
+                Duration::new(0, 0)
+            } else {
+                delta_time
+            };
             // unwrap() is ok, since transition.is_some()
             let transition = self.transition.as_mut().unwrap();
             match transition.process(&delta_time) {
                 TransitionStateOut::Finished(s) => {
+                    let replay = match &mut self.repeats_left {
+                        Repeat::Times(0) => false,
+                        Repeat::Times(times) => {
+                            *times -= 1;
+                            true
+                        }
+                        Repeat::Forever => true,
+                    };
+                    if replay {
+                        let transition = Transition::clone(transition.get_transition());
+                        self.transition = Some(TransitionState::new(transition));
+                        self.transition_output = Some(s);
+                        return Some(s);
+                    }
                     self.shared
                         .lock()
                         .unwrap()
                         .set_strength(Strength::clone(&s));
+                    let finished = Transition::clone(transition.get_transition());
+                    self.deferred
+                        .push(ListenerEvent::TransitionComplete(finished));
                     self.transition = None;
+                    self.resolve_callback(TransitionOutcome::Completed);
+                    // The warmth stays where it ended, even if a queued transition follows.
+                    if let Some((_, to)) = self.warmth_fade {
+                        self.warmth_fade = Some((to, to));
+                    }
+                    if let Some(mut next) = self.transition_queue.pop_front() {
+                        next.from = s;
+                        self.begin_transition(next);
+                    }
+                    Some(s)
+                }
+                TransitionStateOut::Ongoing(s) => {
+                    self.transition_output = Some(s);
                     Some(s)
                 }
-                TransitionStateOut::Ongoing(s) => Some(s),
             }
         } else {
             None
         }
     }
+    /// Drops the pending wake-up and looks for the next scheduler again.
+    /// Needed when the schedulers change while a transition runs,
+    /// since [`State::get_next()`] only queues a sleep once it's done.
+    fn requeue_sleep(&mut self) {
+        self.wake_up = None;
+        self.queue_sleep();
+    }
     fn queue_sleep(&mut self) -> SleepTime {
         self.last_scheduler = None;
+        self.suppressed.clear();
         let (date_time, cmd, name) = {
             let lock = self.shared.lock().unwrap();
 
-            let mut now = LazyNow::new();
-
-            let schedulers_next = lock
-                .ref_schedulers()
-                .iter()
-                .map(|(name, s)| (name, s.get_next(&mut now)))
-                .min_by_key(|(_, next)| match next {
-                    Next::At(d, _) => *d,
-                    Next::Unknown => unreachable!(".retain() call above"),
-                });
-
-            let week_next = Scheduler::get_next(lock.ref_week_schedule(), &mut now);
-            match week_next {
-                Next::At(week_dur, week_cmd) => match schedulers_next {
-                    Some((name, schedulers_next)) => match schedulers_next {
-                        Next::At(schedulers_dur, schedulers_cmd) => match schedulers_dur < week_dur
-                        {
-                            true => (schedulers_dur, schedulers_cmd, Some(name.to_string())),
-                            false => (week_dur, week_cmd, None),
-                        },
-                        Next::Unknown => unreachable!(".retain() call above"),
-                    },
-                    None => (week_dur, week_cmd, None),
-                },
-                Next::Unknown => match schedulers_next {
-                    Some((name, next)) => match next {
-                        Next::At(dur, cmd) => (dur, cmd, Some(name.to_string())),
-                        Next::Unknown => unreachable!(".retain() call above"),
-                    },
+            let next =
+                match lock.get_next_scheduler(&mut LazyNow::from_clock(Arc::clone(&self.clock))) {
+                    Some(next) => next,
                     None => return SleepTime::Forever,
-                },
-            }
+                };
+            self.suppressed = next
+                .suppressed
+                .into_iter()
+                .map(|(name, at)| (name.map(str::to_string), at))
+                .collect();
+
+            (next.at, next.command, next.name.map(str::to_string))
         };
 
         if let Some(name) = name {
@@ -498,19 +1987,1345 @@ impl State {
         SleepTime::To(date_time)
     }
     fn get_next(&mut self) -> Action {
+        if self.paused {
+            // Keep the output where it is, not touching the transition.
+            return Action::Wait(SleepTime::Forever);
+        }
         match self.get_transition_output() {
-            Some(s) => Action::Set(s),
-            // get_sleep
-            None => match self.finish {
-                true => Action::Break,
-                false => Action::Wait(self.queue_sleep()),
+            Some(s) => {
+                // A scheduler can be due while the transition runs.
+                if self.wake_up.is_none() {
+                    self.queue_sleep();
+                }
+                self.with_warmth(Action::Set(s))
+            }
+            None => match self.get_effect_output() {
+                Some(s) => Action::Set(s),
+                // get_sleep
+                None => match self.finish {
+                    true => Action::Break,
+                    false => Action::Wait(self.queue_sleep()),
+                },
             },
         }
     }
-    fn wake(&mut self) -> Option<Command> {
-        match has_occurred(self.wake_up.as_ref()?.0) {
+    /// Adds the faded warmth to `action`, if the transition fades it.
+    fn with_warmth(&mut self, action: Action) -> Action {
+        let (from, to) = match (self.warmth_fade, &action) {
+            (Some(fade), Action::Set(_)) => fade,
+            _ => return action,
+        };
+        let progress = match self.transition.as_ref() {
+            Some(transition) => transition.progress(),
+            None => {
+                self.warmth_fade = None;
+                1.0
+            }
+        };
+        let warmth = from + (to - from) * progress;
+        self.shared.lock().unwrap().set_warmth(warmth);
+        match action {
+            Action::Set(strength) => Action::SetColorTemp(strength, warmth),
+            action => action,
+        }
+    }
+    fn start_effect(&mut self, effect: Effect) -> Action {
+        self.resolve_callback(TransitionOutcome::Cancelled);
+        self.transition = None;
+        self.transition_queue.clear();
+        self.repeats_left = Repeat::Times(0);
+        self.warmth_fade = None;
+        self.shared.lock().unwrap().set_transition(None);
+        self.effect = Some((effect, Instant::now(), 0));
+        self.get_next()
+    }
+    fn get_effect_output(&mut self) -> Option<Strength> {
+        if self.finish {
+            return None;
+        }
+        let (effect, started, updates) = self.effect.as_mut()?;
+        let strength = effect.strength_at(started.elapsed(), *updates, &mut self.rng);
+        *updates += 1;
+        // Schedulers still run, ending the effect.
+        if self.wake_up.is_none() {
+            self.queue_sleep();
+        }
+        Some(strength)
+    }
+    /// Sets the output to the base strength blended with the layers again.
+    /// A running transition does so on its next update anyway.
+    fn set_blended(&mut self) -> Action {
+        match self.transition_interval() {
+            Some(_) => self.get_next(),
+            None => Action::Set(*self.shared.lock().unwrap().get_strength()),
+        }
+    }
+    fn wake(&mut self) -> Option<(NaiveDateTime, Command)> {
+        match self.wake_up.as_ref()?.0 < self.clock.now() {
             false => None,
-            true => Some(self.wake_up.take().unwrap().1),
+            true => self.wake_up.take(),
+        }
+    }
+    fn notify_listeners(&self, notify: impl Fn(&dyn EventListener)) {
+        for listener in self.listeners.lock().unwrap().iter() {
+            notify(listener.as_ref());
+        }
+    }
+    /// Never blocks; observers which are full miss the event.
+    fn notify_observers(&self, event: SchedulerEvent) {
+        self.observers
+            .lock()
+            .unwrap()
+            .retain(|observer| match observer.try_send(event.clone()) {
+                Ok(()) | Err(mpsc::TrySendError::Full(_)) => true,
+                Err(mpsc::TrySendError::Disconnected(_)) => false,
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linear(time: Duration) -> Transition {
+        Transition::new(
+            Strength::off(),
+            Strength::full(),
+            time,
+            TransitionInterpolation::Linear,
+        )
+        .unwrap()
+    }
+    fn strength(out: TransitionStateOut) -> f64 {
+        match out {
+            TransitionStateOut::Ongoing(strength) | TransitionStateOut::Finished(strength) => {
+                strength.into_inner()
+            }
+        }
+    }
+
+    fn at(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+    /// A [`State`] on a [`ManualClock`], with an empty week schedule.
+    fn state_at(now: &str) -> (State, Arc<Mutex<SharedState>>, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new(at(now)));
+        let shared = Arc::new(Mutex::new(SharedState::new(WeekScheduler::empty(
+            Transition::default(),
+        ))));
+        let state = State::with_clock(Arc::clone(&shared), Arc::clone(&clock) as Arc<dyn Clock>);
+        (state, shared, clock)
+    }
+    fn cron(expression: &str, priority: i32, strength: f64) -> Box<dyn Scheduler> {
+        Box::new(
+            crate::CronScheduler::new(
+                crate::cron::CronSchedule::parse(expression).unwrap(),
+                expression.to_string(),
+                ClonableCommand::new(Command::Set(Strength::new(strength))).unwrap(),
+            )
+            .with_priority(priority),
+        )
+    }
+
+    #[test]
+    fn week_scheduler_on_manual_clock() {
+        // A Wednesday.
+        let clock = Arc::new(ManualClock::new(at("2021-06-02 06:00:00")));
+        let week = WeekScheduler::builder(linear(Duration::from_secs(30 * 60)))
+            .day(Weekday::Wed, NaiveTime::from_hms_opt(7, 0, 0).unwrap())
+            .build();
+        let next = |clock: &Arc<ManualClock>| match week
+            .get_next(&mut LazyNow::from_clock(Arc::clone(clock) as Arc<dyn Clock>))
+        {
+            Next::At(date_time, _) => date_time - clock.now(),
+            Next::Unknown => panic!("the week scheduler always has a next time"),
+        };
+        // The transition starts half an hour early, to end at 07:00.
+        assert_eq!(next(&clock), chrono::Duration::minutes(30));
+        clock.advance(chrono::Duration::minutes(29));
+        assert_eq!(next(&clock), chrono::Duration::minutes(1));
+        clock.advance(chrono::Duration::minutes(2));
+        assert_eq!(
+            next(&clock),
+            chrono::Duration::days(7) - chrono::Duration::minutes(1)
+        );
+    }
+
+    #[test]
+    fn week_scheduler_advances_past_the_occurrence() {
+        let mut week = WeekScheduler::builder(linear(Duration::from_secs(30 * 60)))
+            .day(Weekday::Wed, NaiveTime::from_hms_opt(7, 0, 0).unwrap())
+            .build();
+        // Not by the wall clock, which is years later.
+        assert!(matches!(
+            week.advance_past(at("2021-06-02 06:30:00")),
+            Keep::Keep
+        ));
+        match week.get_next(&mut LazyNow::at(at("2021-06-02 06:30:00"))) {
+            Next::At(date_time, _) => assert_eq!(date_time, at("2021-06-09 06:30:00")),
+            Next::Unknown => panic!("it runs every Wednesday"),
+        }
+    }
+
+    #[test]
+    fn week_start_in_the_current_second_is_due() {
+        let instant = Transition::new(
+            Strength::off(),
+            Strength::full(),
+            Duration::new(0, 0),
+            TransitionInterpolation::Constant,
+        )
+        .unwrap();
+        let week = WeekScheduler::builder(instant)
+            .day(Weekday::Wed, NaiveTime::from_hms_opt(7, 0, 0).unwrap())
+            .build();
+        let next = |now: NaiveDateTime| match week.get_next(&mut LazyNow::at(now)) {
+            Next::At(date_time, _) => date_time,
+            Next::Unknown => panic!("the week scheduler always has a next time"),
+        };
+        assert_eq!(next(at("2021-06-02 07:00:00")), at("2021-06-02 07:00:00"));
+        assert_eq!(
+            next(at("2021-06-02 07:00:00") + chrono::Duration::milliseconds(500)),
+            at("2021-06-02 07:00:00")
+        );
+        assert_eq!(next(at("2021-06-02 07:00:01")), at("2021-06-09 07:00:00"));
+    }
+
+    #[test]
+    fn weekdays_differ_from_weekends() {
+        let weekdays = NaiveTime::from_hms_opt(7, 0, 0);
+        let weekends = NaiveTime::from_hms_opt(9, 30, 0);
+        let mut week = WeekScheduler::weekdays_weekends(weekdays, weekends, Transition::default());
+        for (day, time) in week.all_days() {
+            match day {
+                Weekday::Sat | Weekday::Sun => assert_eq!(time, weekends, "{}", day),
+                _ => assert_eq!(time, weekdays, "{}", day),
+            }
+        }
+
+        assert_eq!(week.set(Weekday::Fri, None), weekdays);
+        assert_eq!(week.fri, None);
+        assert_eq!(week.thu, weekdays);
+    }
+
+    #[test]
+    fn set_wednesday_and_read_back() {
+        let mut week = WeekScheduler::empty(Transition::default());
+        let time = NaiveTime::from_hms_opt(6, 15, 0);
+        assert_eq!(week.set(Weekday::Wed, time), None);
+        assert_eq!(week.get(Weekday::Wed), &time);
+        let days = week.all_days();
+        assert_eq!(days[2], (Weekday::Wed, time));
+        for (day, time) in days {
+            if day != Weekday::Wed {
+                assert_eq!(time, None, "{}", day);
+            }
+        }
+    }
+
+    #[test]
+    fn history_is_ordered_and_bounded() {
+        let (mut state, _, _) = state_at("2021-06-02 12:00:00");
+        let history = |state: &mut State| {
+            let (sender, receiver) = mpsc::channel();
+            state.process(Some(Command::GetHistory(sender)));
+            receiver.try_recv().unwrap()
+        };
+        for value in [0.1, 0.2, 0.3] {
+            state.process(Some(Command::Set(Strength::new(value))));
+        }
+        let recorded = history(&mut state);
+        let strengths: Vec<_> = recorded.iter().map(|(_, s)| s.into_inner()).collect();
+        assert_eq!(strengths, [0.1, 0.2, 0.3]);
+        // Oldest first, so the times ago decrease.
+        assert!(recorded.windows(2).all(|pair| pair[0].0 >= pair[1].0));
+
+        for i in 0..HISTORY_CAPACITY {
+            let value = (i % 10) as f64 / 10.0;
+            state.process(Some(Command::Set(Strength::new(value))));
+        }
+        let recorded = history(&mut state);
+        assert_eq!(recorded.len(), HISTORY_CAPACITY);
+        assert_eq!(recorded[0].1, Strength::new(0.0));
+        assert_eq!(recorded[HISTORY_CAPACITY - 1].1, Strength::new(0.3));
+    }
+
+    #[test]
+    fn undo_restores_the_previous_strength() {
+        let (mut state, shared, _) = state_at("2021-06-02 12:00:00");
+        let strength = |shared: &Arc<Mutex<SharedState>>| *shared.lock().unwrap().get_strength();
+        let initial = strength(&shared);
+        state.process(Some(Command::Set(Strength::new(0.3))));
+        state.process(Some(Command::Set(Strength::new(0.7))));
+        assert_eq!(strength(&shared), Strength::new(0.7));
+
+        state.process(Some(Command::Undo));
+        assert_eq!(strength(&shared), Strength::new(0.3));
+        state.process(Some(Command::Undo));
+        assert_eq!(strength(&shared), initial);
+        // Nothing left to undo.
+        state.process(Some(Command::Undo));
+        assert_eq!(strength(&shared), initial);
+    }
+
+    #[test]
+    fn shared_strength_follows_transition() {
+        let (mut state, shared, _) = state_at("2021-06-02 12:00:00");
+        state.process(Some(Command::SetTransition(linear(Duration::from_millis(
+            200,
+        )))));
+        std::thread::sleep(Duration::from_millis(100));
+        let strength = match state.process(None) {
+            Action::Set(strength) => strength,
+            action => panic!("expected a set, got {:?}", action),
+        };
+        assert!(strength > Strength::off() && strength < Strength::full());
+        // Readers see the value mid-transition, not only once it's done.
+        assert_eq!(*shared.lock().unwrap().get_strength(), strength);
+        let (sender, receiver) = mpsc::channel();
+        state.process(Some(Command::GetStatus(sender)));
+        assert_eq!(receiver.try_recv().unwrap().strength, strength);
+    }
+
+    #[test]
+    fn layers_blend() {
+        let (mut state, _, _) = state_at("2021-06-02 12:00:00");
+        state.process(Some(Command::Set(Strength::new(0.2))));
+        assert_eq!(
+            state.process(Some(Command::SetLayer(
+                "ambient".to_string(),
+                Strength::new(0.3)
+            ))),
+            Action::Set(Strength::new(0.3))
+        );
+        assert_eq!(
+            state.process(Some(Command::SetLayer(
+                "boost".to_string(),
+                Strength::new(0.4)
+            ))),
+            Action::Set(Strength::new(0.4))
+        );
+        match state.process(Some(Command::SetBlendMode(BlendMode::Sum))) {
+            Action::Set(strength) => assert!((strength.into_inner() - 0.9).abs() < 1e-9),
+            action => panic!("expected a set, got {:?}", action),
+        }
+        // Clamped.
+        assert_eq!(
+            state.process(Some(Command::SetLayer(
+                "boost".to_string(),
+                Strength::full()
+            ))),
+            Action::Set(Strength::full())
+        );
+        assert_eq!(
+            state.process(Some(Command::SetBlendMode(BlendMode::Max))),
+            Action::Set(Strength::full())
+        );
+        assert_eq!(
+            state.process(Some(Command::RemoveLayer("boost".to_string()))),
+            Action::Set(Strength::new(0.3))
+        );
+    }
+
+    #[test]
+    fn recall_scene_fades_from_current() {
+        let (mut state, _, _) = state_at("2021-06-02 12:00:00");
+        let scene = |strength, interpolation| crate::Scene {
+            strength: Strength::new(strength),
+            time: Duration::from_secs(5),
+            interpolation,
+        };
+        state.process(Some(Command::SaveScene(
+            "movie".to_string(),
+            scene(0.1, TransitionInterpolation::Sine),
+        )));
+        state.process(Some(Command::SaveScene(
+            "dinner".to_string(),
+            scene(0.6, TransitionInterpolation::Linear),
+        )));
+        state.process(Some(Command::Set(Strength::new(0.3))));
+
+        state.process(Some(Command::RecallScene("dinner".to_string())));
+        let running = state.transition.as_ref().unwrap().get_transition();
+        assert_eq!(running.from, Strength::new(0.3));
+        assert_eq!(running.to, Strength::new(0.6));
+        assert_eq!(running.time, Duration::from_secs(5));
+        assert_eq!(running.interpolation, TransitionInterpolation::Linear);
+
+        // Unknown scenes leave the transition running.
+        state.process(Some(Command::RecallScene("party".to_string())));
+        let running = state.transition.as_ref().unwrap().get_transition();
+        assert_eq!(running.to, Strength::new(0.6));
+    }
+
+    #[test]
+    fn flicker_stays_within_amplitude() {
+        let flicker = |seed| {
+            let (state, _, _) = state_at("2021-06-02 12:00:00");
+            let mut state = state.with_rng(crate::random::Rng::new(seed));
+            state.process(Some(Command::StartFlicker {
+                base: Strength::new(0.5),
+                amplitude: 0.1,
+                rate: Duration::from_millis(50),
+            }));
+            let values: Vec<f64> = (0..200)
+                .map(|_| match state.process(None) {
+                    Action::Set(strength) => strength.into_inner(),
+                    action => panic!("expected a set, got {:?}", action),
+                })
+                .collect();
+            (state, values)
+        };
+        let (mut state, values) = flicker(7);
+        assert!(values.iter().all(|value| (0.4..=0.6).contains(value)));
+        assert!(values.windows(2).any(|pair| pair[0] != pair[1]));
+        assert_eq!(flicker(7).1, values);
+
+        // A real command ends it.
+        state.process(Some(Command::Set(Strength::new(0.2))));
+        assert!(state.effect.is_none());
+        assert_eq!(state.transition_interval(), None);
+    }
+
+    #[test]
+    fn strobe_alternates() {
+        let (mut state, _, _) = state_at("2021-06-02 12:00:00");
+        let start = Command::StartStrobe {
+            low: Strength::new(0.1),
+            high: Strength::new(0.9),
+            period: Duration::from_millis(100),
+        };
+        // It starts high.
+        let mut values = vec![state.process(Some(start))];
+        assert_eq!(state.transition_interval(), Some(Duration::from_millis(50)));
+        values.extend((1..8).map(|_| state.process(None)));
+        let expected: Vec<Action> = [0.9, 0.1, 0.9, 0.1, 0.9, 0.1, 0.9, 0.1]
+            .iter()
+            .map(|value| Action::Set(Strength::new(*value)))
+            .collect();
+        assert_eq!(values, expected);
+
+        // Too fast a strobe is slowed down.
+        state.process(Some(Command::StartStrobe {
+            low: Strength::off(),
+            high: Strength::full(),
+            period: Duration::from_millis(1),
+        }));
+        assert_eq!(state.transition_interval(), Some(STROBE_PERIOD_MIN / 2));
+
+        state.process(Some(Command::StopEffect));
+        assert_eq!(state.transition_interval(), None);
+    }
+
+    #[test]
+    fn breathing_is_a_sine() {
+        let breathing = Effect::Breathing {
+            low: Strength::new(0.2),
+            high: Strength::new(0.8),
+            period: Duration::from_secs(4),
+        };
+        let mut rng = crate::random::Rng::new(0);
+        for step in 0..=40 {
+            let elapsed = Duration::from_millis(step * 100);
+            let phase = elapsed.as_secs_f64() / 4.0 * std::f64::consts::TAU;
+            let expected = 0.2 + 0.6 * (1.0 - phase.cos()) / 2.0;
+            let strength = breathing.strength_at(elapsed, step, &mut rng).into_inner();
+            assert!((strength - expected).abs() < 1e-9, "{:?}", elapsed);
+        }
+        // Seamlessly into the next breath.
+        let next = breathing.strength_at(Duration::from_millis(4100), 41, &mut rng);
+        let first = breathing.strength_at(Duration::from_millis(100), 1, &mut rng);
+        assert!((next.into_inner() - first.into_inner()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn shared_start_keeps_transitions_in_step() {
+        let start = Instant::now();
+        let command = || Command::SetTransitionAt(linear(Duration::from_secs(1)), start);
+        let strength_of = |action| match action {
+            Action::Set(strength) => strength.into_inner(),
+            action => panic!("expected a set, got {:?}", action),
+        };
+        let (mut first, _, _) = state_at("2021-06-02 12:00:00");
+        let (mut second, _, _) = state_at("2021-06-02 12:00:00");
+        first.process(Some(command()));
+        // The second gets it late, but catches up.
+        std::thread::sleep(Duration::from_millis(50));
+        second.process(Some(command()));
+        for _ in 0..3 {
+            std::thread::sleep(Duration::from_millis(20));
+            let first = strength_of(first.process(None));
+            let second = strength_of(second.process(None));
+            assert!((first - second).abs() < 0.01, "{} and {}", first, second);
+        }
+    }
+
+    #[test]
+    fn batch_applies_all_at_once() {
+        let (mut state, shared, _) = state_at("2021-06-02 06:00:00");
+        let eight = NaiveTime::from_hms_opt(8, 0, 0);
+        let action = state.process(Some(Command::Batch(vec![
+            Command::ChangeDayTimer(Weekday::Wed, eight),
+            Command::SetTransition(linear(Duration::from_secs(10))),
+        ])));
+        assert!(matches!(action, Action::Set(_)));
+        assert_eq!(shared.lock().unwrap().ref_week_schedule().wed, eight);
+        assert!(state.transition.is_some());
+
+        // Only the last set is applied.
+        state.process(Some(Command::Batch(vec![
+            Command::Set(Strength::new(0.2)),
+            Command::Set(Strength::new(0.4)),
+        ])));
+        let (sender, receiver) = mpsc::channel();
+        state.process(Some(Command::GetHistory(sender)));
+        let history: Vec<_> = receiver.try_recv().unwrap();
+        assert_eq!(history.last().unwrap().1, Strength::new(0.4));
+        assert!(!history
+            .iter()
+            .any(|(_, strength)| *strength == Strength::new(0.2)));
+
+        // Nesting and `Finish` aren't allowed, so nothing of it is applied.
+        for invalid in [
+            Command::Finish,
+            Command::Batch(vec![Command::Set(Strength::full())]),
+        ] {
+            state.process(Some(Command::Batch(vec![
+                Command::Set(Strength::new(0.6)),
+                invalid,
+            ])));
+            assert_eq!(*shared.lock().unwrap().get_strength(), Strength::new(0.4));
+        }
+    }
+
+    #[test]
+    fn queued_transition_starts_where_the_first_ended() {
+        let (mut state, _, _) = state_at("2021-06-02 12:00:00");
+        let first = Transition::new(
+            Strength::off(),
+            Strength::new(0.6),
+            Duration::from_millis(20),
+            TransitionInterpolation::Linear,
+        )
+        .unwrap();
+        let second = Transition::new(
+            Strength::full(),
+            Strength::new(0.2),
+            Duration::from_secs(10),
+            TransitionInterpolation::Linear,
+        )
+        .unwrap();
+        state.process(Some(Command::SetTransition(first)));
+        state.process(Some(Command::QueueTransition(second)));
+        // Still running the first.
+        assert_eq!(
+            state.transition.as_ref().unwrap().get_transition().to,
+            Strength::new(0.6)
+        );
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(state.process(None), Action::Set(Strength::new(0.6)));
+        let running = state.transition.as_ref().unwrap().get_transition();
+        assert_eq!(running.from, Strength::new(0.6));
+        assert_eq!(running.to, Strength::new(0.2));
+        match state.process(None) {
+            Action::Set(strength) => {
+                assert!(strength <= Strength::new(0.6) && strength > Strength::new(0.5))
+            }
+            action => panic!("expected a set, got {:?}", action),
+        }
+    }
+
+    #[test]
+    fn repeating_transition_crosses_midpoint_each_time() {
+        let (mut state, _, _) = state_at("2021-06-02 12:00:00");
+        let transition = linear(Duration::from_millis(100));
+        let mut previous = match state.process(Some(Command::SetRepeatingTransition(
+            transition,
+            Repeat::Times(3),
+        ))) {
+            Action::Set(strength) => strength.into_inner(),
+            action => panic!("expected a set, got {:?}", action),
+        };
+        let mut crossings = 0;
+        while state.transition.is_some() {
+            std::thread::sleep(Duration::from_millis(5));
+            if let Action::Set(strength) = state.process(None) {
+                let strength = strength.into_inner();
+                if previous < 0.5 && strength >= 0.5 {
+                    crossings += 1;
+                }
+                previous = strength;
+            }
+        }
+        assert_eq!(crossings, 3);
+        // It holds at the end.
+        assert_eq!(
+            *state.shared.lock().unwrap().get_strength(),
+            Strength::full()
+        );
+    }
+
+    #[test]
+    fn halving_speed_doubles_remaining() {
+        let mut state = TransitionState::new(linear(Duration::from_secs(10)));
+        let before = strength(state.process(&Duration::from_secs(5)));
+        assert!((before - 0.5).abs() < 1e-9);
+        let remaining = |state: &TransitionState| {
+            state
+                .get_transition()
+                .time
+                .mul_f64(1.0 - state.progress)
+                .as_secs_f64()
+        };
+        assert!((remaining(&state) - 5.0).abs() < 1e-9);
+
+        state.set_speed(0.5);
+        assert!((remaining(&state) - 10.0).abs() < 1e-9);
+        // Continuous at the point of change
+        let after = strength(state.process(&Duration::new(0, 0)));
+        assert!((after - before).abs() < 1e-9);
+        assert!((state.progress() - 0.5).abs() < 1e-9);
+
+        let later = strength(state.process(&Duration::from_secs(5)));
+        assert!((later - 0.75).abs() < 1e-9);
+        assert!(matches!(
+            state.process(&Duration::from_secs(5)),
+            TransitionStateOut::Finished(_)
+        ));
+    }
+
+    #[test]
+    fn speed_is_clamped() {
+        let (mut state, shared, _) = state_at("2021-06-02 12:00:00");
+        let transition = linear(Duration::from_secs(100));
+        state.process(Some(Command::SetTransition(transition)));
+        state.process(Some(Command::SetTransitionSpeed(1e9)));
+        let time = shared.lock().unwrap().get_transition().unwrap().time;
+        assert_eq!(time, Duration::from_secs(100).div_f64(TRANSITION_SPEED_MAX));
+
+        state.process(Some(Command::SetTransitionSpeed(f64::NAN)));
+        let unchanged = shared.lock().unwrap().get_transition().unwrap().time;
+        assert_eq!(unchanged, time);
+    }
+
+    #[test]
+    fn highest_priority_wins() {
+        let (mut state, _, clock) = state_at("2021-06-02 06:59:00");
+        state.process(Some(Command::AddReplaceScheduler(
+            "low".to_string(),
+            cron("0 7 * * *", 0, 0.2),
+        )));
+        state.process(Some(Command::AddReplaceScheduler(
+            "high".to_string(),
+            cron("0 7 * * *", 5, 0.8),
+        )));
+        assert_eq!(
+            state.process(None),
+            Action::Wait(SleepTime::To(at("2021-06-02 07:00:00")))
+        );
+
+        clock.set(at("2021-06-02 07:00:01"));
+        assert_eq!(state.process(None), Action::Set(Strength::new(0.8)));
+        // Neither runs again today.
+        assert_eq!(
+            state.process(None),
+            Action::Wait(SleepTime::To(at("2021-06-03 07:00:00")))
+        );
+    }
+
+    #[test]
+    fn week_scheduler_loses_a_tie_a_moment_later() {
+        let (mut state, _, clock) = state_at("2021-06-02 06:59:00");
+        let instant = Transition::new(
+            Strength::off(),
+            Strength::full(),
+            Duration::ZERO,
+            TransitionInterpolation::Constant,
+        )
+        .unwrap();
+        state.process(Some(Command::ChangeDayTimerTransition(instant)));
+        state.process(Some(Command::ChangeDayTimer(
+            Weekday::Wed,
+            NaiveTime::from_hms_opt(7, 0, 1),
+        )));
+        state.process(Some(Command::AddReplaceScheduler(
+            "high".to_string(),
+            cron("0 7 * * *", 5, 0.8),
+        )));
+
+        // The week scheduler is due a second after, when it's already been suppressed.
+        clock.set(at("2021-06-02 07:00:00") + chrono::Duration::milliseconds(500));
+        assert_eq!(state.process(None), Action::Set(Strength::new(0.8)));
+        // It isn't run after all.
+        assert_eq!(
+            state.process(None),
+            Action::Wait(SleepTime::To(at("2021-06-03 07:00:00")))
+        );
+    }
+
+    #[test]
+    fn callback_on_completion() {
+        let (mut state, _, _) = state_at("2021-06-02 12:00:00");
+        let (sender, receiver) = mpsc::channel();
+        let transition = linear(Duration::from_millis(20));
+        state.process(Some(Command::SetTransitionWithCallback(transition, sender)));
+        assert_eq!(receiver.try_recv(), Err(mpsc::TryRecvError::Empty));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(state.process(None), Action::Set(Strength::full()));
+        assert_eq!(receiver.try_recv(), Ok(TransitionOutcome::Completed));
+    }
+
+    #[test]
+    fn callback_on_cancel() {
+        let (mut state, _, _) = state_at("2021-06-02 12:00:00");
+        let (sender, receiver) = mpsc::channel();
+        let transition = linear(Duration::from_secs(100));
+        state.process(Some(Command::SetTransitionWithCallback(transition, sender)));
+        state.process(Some(Command::Set(Strength::new(0.3))));
+        assert_eq!(receiver.try_recv(), Ok(TransitionOutcome::Cancelled));
+        // Resolved once
+        assert_eq!(receiver.try_recv(), Err(mpsc::TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn add_remove_round_trip() {
+        let (mut state, shared, _) = state_at("2021-06-02 12:00:00");
+        state.process(Some(Command::AddReplaceScheduler(
+            "morning".to_string(),
+            cron("0 7 * * *", 0, 0.5),
+        )));
+        assert!(shared
+            .lock()
+            .unwrap()
+            .ref_schedulers()
+            .contains_key("morning"));
+
+        let (sender, receiver) = mpsc::channel();
+        state.process(Some(Command::RemoveSchedulerWithCallback(
+            "morning".to_string(),
+            sender.clone(),
+        )));
+        assert_eq!(receiver.try_recv(), Ok(true));
+        assert!(shared.lock().unwrap().ref_schedulers().is_empty());
+
+        // Removing it again, or one which never existed, does nothing.
+        state.process(Some(Command::RemoveSchedulerWithCallback(
+            "morning".to_string(),
+            sender,
+        )));
+        assert_eq!(receiver.try_recv(), Ok(false));
+        state.process(Some(Command::RemoveScheduler("missing".to_string())));
+        assert!(shared.lock().unwrap().ref_schedulers().is_empty());
+    }
+
+    #[test]
+    fn observers_see_schedulers_fire() {
+        let (state, _, clock) = state_at("2021-06-02 06:59:00");
+        let observers = Arc::new(Mutex::new(Vec::new()));
+        let mut state = state.with_observers(Arc::clone(&observers));
+        let (sender, receiver) = mpsc::sync_channel(OBSERVER_CAPACITY);
+        // Never read, so it's always full. It mustn't block the state.
+        let (full, _never_read) = mpsc::sync_channel(0);
+        observers.lock().unwrap().extend([sender, full]);
+        state.process(Some(Command::AddReplaceScheduler(
+            "morning".to_string(),
+            cron("0 7 * * *", 0, 0.5),
+        )));
+        assert!(receiver.try_recv().is_err());
+
+        clock.set(at("2021-06-02 07:00:01"));
+        assert_eq!(state.process(None), Action::Set(Strength::new(0.5)));
+        assert_eq!(
+            receiver.try_recv(),
+            Ok(SchedulerEvent {
+                name: Some("morning".to_string()),
+                at: at("2021-06-02 07:00:00"),
+            })
+        );
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn disabled_scheduler_does_not_fire() {
+        let (mut state, shared, clock) = state_at("2021-06-02 06:59:00");
+        state.process(Some(Command::AddReplaceScheduler(
+            "vacation".to_string(),
+            cron("0 7 * * *", 0, 0.5),
+        )));
+        state.process(Some(Command::SetSchedulerEnabled(
+            "vacation".to_string(),
+            false,
+        )));
+        clock.set(at("2021-06-02 07:00:01"));
+        assert_eq!(state.process(None), Action::Wait(SleepTime::Forever));
+
+        state.process(Some(Command::SetSchedulerEnabled(
+            "vacation".to_string(),
+            true,
+        )));
+        clock.set(at("2021-06-03 07:00:01"));
+        assert_eq!(state.process(None), Action::Set(Strength::new(0.5)));
+
+        // Unknown names aren't remembered.
+        let (sender, receiver) = mpsc::channel();
+        state.process(Some(Command::SetSchedulerEnabledWithCallback(
+            "missing".to_string(),
+            false,
+            sender,
+        )));
+        assert_eq!(receiver.try_recv(), Ok(false));
+        assert!(!shared
+            .lock()
+            .unwrap()
+            .ref_disabled_schedulers()
+            .contains("missing"));
+    }
+
+    #[test]
+    fn disabling_during_transition_drops_wake_up() {
+        let (mut state, _, clock) = state_at("2021-06-02 06:59:00");
+        state.process(Some(Command::AddReplaceScheduler(
+            "morning".to_string(),
+            cron("0 7 * * *", 0, 0.5),
+        )));
+        // The wake-up is queued before the transition starts.
+        let transition = linear(Duration::from_secs(600));
+        state.process(Some(Command::SetTransition(transition)));
+        state.process(Some(Command::SetSchedulerEnabled(
+            "morning".to_string(),
+            false,
+        )));
+
+        // Still fading, instead of running the scheduler.
+        clock.set(at("2021-06-02 07:00:01"));
+        match state.process(None) {
+            Action::Set(strength) => assert!(strength.into_inner() < 0.1),
+            action => panic!("expected the transition to continue, got {:?}", action),
+        }
+    }
+
+    #[test]
+    fn bedtime_moves_schedulers() {
+        let bedtime = Bedtime::new(NaiveTime::from_hms_opt(22, 0, 0).unwrap());
+        let command = || ClonableCommand::new(Command::Set(Strength::off())).unwrap();
+        let dim = BedtimeScheduler::new(
+            bedtime.clone(),
+            chrono::Duration::minutes(30),
+            "dim".to_string(),
+            command(),
+        );
+        let before = BedtimeScheduler::new(
+            bedtime.clone(),
+            chrono::Duration::minutes(-60),
+            "before".to_string(),
+            command(),
+        );
+        let next = |scheduler: &BedtimeScheduler, now: &str| match scheduler
+            .get_next(&mut LazyNow::at(at(now)))
+        {
+            Next::At(date_time, _) => date_time,
+            Next::Unknown => panic!("bedtime schedulers always run"),
+        };
+        assert_eq!(next(&dim, "2021-06-02 12:00:00"), at("2021-06-02 22:30:00"));
+        assert_eq!(
+            next(&before, "2021-06-02 12:00:00"),
+            at("2021-06-02 21:00:00")
+        );
+        assert_eq!(
+            next(&before, "2021-06-02 21:00:01"),
+            at("2021-06-03 21:00:00")
+        );
+
+        bedtime.set(NaiveTime::from_hms_opt(23, 45, 0).unwrap());
+        assert_eq!(
+            next(&before, "2021-06-02 12:00:00"),
+            at("2021-06-02 22:45:00")
+        );
+        // Past midnight
+        assert_eq!(next(&dim, "2021-06-02 12:00:00"), at("2021-06-03 00:15:00"));
+        assert_eq!(next(&dim, "2021-06-03 00:10:00"), at("2021-06-03 00:15:00"));
+    }
+
+    #[test]
+    fn skip_next_skips_once() {
+        let (mut state, shared, clock) = state_at("2021-06-02 06:00:00");
+        let instant = Transition::new(
+            Strength::off(),
+            Strength::full(),
+            Duration::new(0, 0),
+            TransitionInterpolation::Constant,
+        )
+        .unwrap();
+        let seven = NaiveTime::from_hms_opt(7, 0, 0).unwrap();
+        *shared.lock().unwrap().mut_week_scheduler() = WeekScheduler::builder(instant)
+            .day(Weekday::Wed, seven)
+            .day(Weekday::Thu, seven)
+            .build();
+
+        assert_eq!(
+            state.process(Some(Command::SkipNext)),
+            Action::Wait(SleepTime::To(at("2021-06-03 07:00:00")))
+        );
+        clock.set(at("2021-06-02 07:00:01"));
+        assert_eq!(
+            state.process(None),
+            Action::Wait(SleepTime::To(at("2021-06-03 07:00:00")))
+        );
+        // Only the one was skipped.
+        clock.set(at("2021-06-03 07:00:01"));
+        assert_eq!(state.process(None), Action::Set(Strength::full()));
+    }
+
+    /// Due every day at `.0`, starting its own transition `.1`.
+    #[derive(Debug)]
+    struct Daily(NaiveTime, Transition);
+    impl Scheduler for Daily {
+        fn advance(&mut self) -> Keep {
+            Keep::Keep
+        }
+        fn get_next(&self, now: &mut LazyNow) -> Next {
+            let now = now.now();
+            let mut next = now.date().and_time(self.0);
+            if next < now {
+                next += chrono::Duration::days(1);
+            }
+            // Replaced by the transition.
+            Next::At(next, Command::Set(Strength::new(0.5)))
+        }
+        fn description(&self) -> &str {
+            "daily"
+        }
+        fn kind(&self) -> &str {
+            "daily"
+        }
+        fn transition(&self) -> Option<Transition> {
+            Some(self.1.clone())
+        }
+    }
+
+    #[test]
+    fn schedulers_start_their_own_transitions() {
+        let (mut state, _, clock) = state_at("2021-06-02 06:00:00");
+        let instant = |to| {
+            Transition::new(
+                Strength::off(),
+                Strength::new(to),
+                Duration::new(0, 0),
+                TransitionInterpolation::Constant,
+            )
+            .unwrap()
+        };
+        let time = |hour| NaiveTime::from_hms_opt(hour, 0, 0).unwrap();
+        state.process(Some(Command::AddReplaceScheduler(
+            "sunrise".to_string(),
+            Box::new(Daily(time(7), instant(0.9))),
+        )));
+        state.process(Some(Command::AddReplaceScheduler(
+            "sunset".to_string(),
+            Box::new(Daily(time(19), instant(0.2))),
+        )));
+
+        clock.set(at("2021-06-02 07:00:01"));
+        assert_eq!(state.process(None), Action::Set(Strength::new(0.9)));
+        // The controller processes again right away, sleeping to the next.
+        assert_eq!(
+            state.process(None),
+            Action::Wait(SleepTime::To(at("2021-06-02 19:00:00")))
+        );
+        clock.set(at("2021-06-02 19:00:01"));
+        assert_eq!(state.process(None), Action::Set(Strength::new(0.2)));
+    }
+
+    #[test]
+    fn excluded_dates_are_skipped() {
+        let date = |date: &str| NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+        let excluded = [date("2021-06-02"), date("2021-06-03"), date("2021-06-08")];
+        let scheduler = ExcludeDatesScheduler::new(
+            cron("0 7 * * 1-5", 0, 1.0),
+            excluded.iter().copied().collect(),
+        );
+        let next = |now: &str| match scheduler.get_next(&mut LazyNow::at(at(now))) {
+            Next::At(date_time, _) => Some(date_time),
+            Next::Unknown => None,
+        };
+        assert_eq!(next("2021-06-01 06:00:00"), Some(at("2021-06-01 07:00:00")));
+        // Wednesday and Thursday are skipped
+        assert_eq!(next("2021-06-01 08:00:00"), Some(at("2021-06-04 07:00:00")));
+        // So is the weekend, by the inner scheduler, and the Tuesday after
+        assert_eq!(next("2021-06-04 08:00:00"), Some(at("2021-06-07 07:00:00")));
+        assert_eq!(next("2021-06-07 08:00:00"), Some(at("2021-06-09 07:00:00")));
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let max_jitter = chrono::Duration::minutes(10);
+        let mut scheduler = JitterScheduler::with_rng(
+            cron("0 7 * * *", 0, 1.0),
+            max_jitter,
+            crate::random::Rng::new(42),
+        );
+        let mut now = at("2021-06-01 12:00:00");
+        let mut offsets = HashSet::new();
+        for day in 2..30 {
+            let scheduled = at(&format!("2021-06-{:02} 07:00:00", day));
+            let next = match scheduler.get_next(&mut LazyNow::at(now)) {
+                Next::At(next, _) => next,
+                Next::Unknown => panic!("cron schedules always run"),
+            };
+            // Only reading it changes nothing.
+            match scheduler.get_next(&mut LazyNow::at(now)) {
+                Next::At(again, _) => assert_eq!(again, next),
+                Next::Unknown => panic!("cron schedules always run"),
+            }
+            assert!(next >= scheduled - max_jitter && next <= scheduled + max_jitter);
+            assert_eq!(next - scheduled, scheduler.offset());
+            offsets.insert(scheduler.offset());
+
+            scheduler.advance_past(next);
+            now = next + chrono::Duration::seconds(1);
+        }
+        assert!(offsets.len() > 1, "a new offset is picked every time");
+    }
+
+    #[test]
+    fn jitter_is_never_before_now() {
+        let mut rng = crate::random::Rng::new(7);
+        for _ in 0..20 {
+            let scheduler = JitterScheduler::with_rng(
+                cron("0 7 * * *", 0, 1.0),
+                chrono::Duration::minutes(10),
+                rng.clone(),
+            );
+            rng.next_u64();
+            let now = at("2021-06-02 06:59:00");
+            match scheduler.get_next(&mut LazyNow::at(now)) {
+                Next::At(next, _) => assert!(next >= now),
+                Next::Unknown => panic!("cron schedules always run"),
+            }
+        }
+    }
+
+    #[test]
+    fn weekend_has_its_own_transition() {
+        let seven = NaiveTime::from_hms_opt(7, 0, 0).unwrap();
+        let mut builder = WeekScheduler::builder(linear(Duration::from_secs(10)));
+        for day in [
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+        ] {
+            builder = builder.day(day, seven);
+        }
+        let scheduler = builder
+            .day_with_transition(Weekday::Sat, seven, linear(Duration::from_secs(60)))
+            .day_with_transition(Weekday::Sun, seven, linear(Duration::from_secs(60)))
+            .build();
+        let next = |now| match scheduler.get_next(&mut LazyNow::at(at(now))) {
+            Next::At(start, Command::SetTransition(transition)) => (start, transition.time),
+            _ => panic!("every day has a time"),
+        };
+
+        // 2021-06-04 is a friday.
+        assert_eq!(
+            next("2021-06-04 00:00:00"),
+            (at("2021-06-04 06:59:50"), Duration::from_secs(10))
+        );
+        assert_eq!(
+            next("2021-06-05 00:00:00"),
+            (at("2021-06-05 06:59:00"), Duration::from_secs(60))
+        );
+        assert_eq!(
+            next("2021-06-06 12:00:00"),
+            (at("2021-06-07 06:59:50"), Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn schedule_diff_changes_only_given_days() {
+        let (mut state, shared, _) = state_at("2021-06-02 12:00:00");
+        let seven = NaiveTime::from_hms_opt(7, 0, 0).unwrap();
+        *shared.lock().unwrap().mut_week_scheduler() =
+            WeekScheduler::same(seven, Transition::default());
+
+        let half_past_six = NaiveTime::from_hms_opt(6, 30, 0).unwrap();
+        let mut diff = HashMap::new();
+        diff.insert(Weekday::Mon, Some(half_past_six));
+        diff.insert(Weekday::Sat, None);
+        let (sender, receiver) = mpsc::channel();
+        state.process(Some(Command::ScheduleDiff(diff.clone(), sender.clone())));
+        assert_eq!(receiver.try_recv(), Ok(vec![Weekday::Mon, Weekday::Sat]));
+
+        let lock = shared.lock().unwrap();
+        let week = lock.ref_week_schedule();
+        assert_eq!(week.mon, Some(half_past_six));
+        assert_eq!(week.sat, None);
+        for day in [
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+            Weekday::Sun,
+        ] {
+            assert_eq!(week.get(day), &Some(seven));
+        }
+        drop(lock);
+
+        // Applying it again changes nothing.
+        state.process(Some(Command::ScheduleDiff(diff, sender)));
+        assert_eq!(receiver.try_recv(), Ok(vec![]));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("httpwm-week-{}.ron", std::process::id()));
+        let scheduler = WeekScheduler::builder(linear(Duration::from_secs(30)))
+            .day(Weekday::Tue, NaiveTime::from_hms_opt(6, 15, 0).unwrap())
+            .day_with_transition(
+                Weekday::Sat,
+                NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                Transition::default(),
+            )
+            .build();
+        scheduler.save(&path).unwrap();
+        assert_eq!(WeekScheduler::load(&path).unwrap(), scheduler);
+
+        std::fs::write(&path, "not a schedule").unwrap();
+        let err = WeekScheduler::load(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+        let err = WeekScheduler::load(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    /// Due once, at `at`. Logs its name when it's advanced.
+    #[derive(Debug)]
+    struct Once {
+        name: &'static str,
+        at: NaiveDateTime,
+        advanced: Arc<Mutex<Vec<&'static str>>>,
+    }
+    impl Scheduler for Once {
+        fn advance(&mut self) -> Keep {
+            self.advanced.lock().unwrap().push(self.name);
+            Keep::Remove
+        }
+        fn get_next(&self, now: &mut LazyNow) -> Next {
+            match now.now() <= self.at {
+                true => Next::At(self.at, Command::Set(Strength::full())),
+                false => Next::Unknown,
+            }
+        }
+        fn description(&self) -> &str {
+            self.name
+        }
+        fn kind(&self) -> &str {
+            "once"
+        }
+    }
+
+    #[test]
+    fn earliest_of_three() {
+        let advanced = Arc::new(Mutex::new(Vec::new()));
+        let once = |name, time| -> Box<dyn Scheduler> {
+            Box::new(Once {
+                name,
+                at: at(time),
+                advanced: Arc::clone(&advanced),
+            })
+        };
+        let mut earliest = EarliestScheduler::new(
+            vec![
+                once("nine", "2021-06-02 09:00:00"),
+                once("seven", "2021-06-02 07:00:00"),
+                once("half past eight", "2021-06-02 08:30:00"),
+            ],
+            "mornings".to_string(),
+        );
+        let next = |earliest: &EarliestScheduler, now| match earliest
+            .get_next(&mut LazyNow::at(at(now)))
+        {
+            Next::At(date_time, _) => date_time - at(now),
+            Next::Unknown => panic!("none of them is due"),
+        };
+        assert_eq!(
+            next(&earliest, "2021-06-02 06:00:00"),
+            chrono::Duration::hours(1)
+        );
+        // Showing the schedulers later doesn't change which one runs.
+        assert_eq!(
+            next(&earliest, "2021-06-02 08:00:00"),
+            chrono::Duration::minutes(30)
+        );
+
+        let keep = earliest.advance_past(at("2021-06-02 07:00:00"));
+        assert!(matches!(keep, Keep::Keep));
+        assert_eq!(*advanced.lock().unwrap(), ["seven"]);
+        assert_eq!(earliest.ref_schedulers().len(), 2);
+        assert_eq!(
+            next(&earliest, "2021-06-02 07:00:00"),
+            chrono::Duration::minutes(90)
+        );
+
+        earliest.advance_past(at("2021-06-02 08:30:00"));
+        let keep = earliest.advance_past(at("2021-06-02 09:00:00"));
+        assert!(matches!(keep, Keep::Remove));
+        assert_eq!(
+            *advanced.lock().unwrap(),
+            ["seven", "half past eight", "nine"]
+        );
+    }
+
+    #[test]
+    fn list_schedulers() {
+        let (mut state, _, _) = state_at("2021-06-02 12:00:00");
+        state.process(Some(Command::AddReplaceScheduler(
+            "morning".to_string(),
+            cron("0 7 * * *", 0, 1.0),
+        )));
+        state.process(Some(Command::AddReplaceScheduler(
+            "evening".to_string(),
+            cron("30 21 * * *", 2, 0.2),
+        )));
+        let (sender, receiver) = mpsc::channel();
+        state.process(Some(Command::ListSchedulers(sender)));
+        let infos = receiver.try_recv().unwrap();
+        assert_eq!(infos.len(), 2);
+
+        let info = |name| infos.iter().find(|info| info.name == name).unwrap();
+        assert_eq!(info("morning").next, Some(at("2021-06-03 07:00:00")));
+        assert_eq!(info("morning").kind, "Cron");
+        assert_eq!(info("evening").next, Some(at("2021-06-02 21:30:00")));
+        assert_eq!(info("evening").priority, 2);
+        assert!(infos.iter().all(|info| info.enabled));
+    }
+
+    #[test]
+    fn removing_during_transition_drops_wake_up() {
+        let (mut state, _, clock) = state_at("2021-06-02 06:59:00");
+        state.process(Some(Command::AddReplaceScheduler(
+            "morning".to_string(),
+            cron("0 7 * * *", 0, 0.5),
+        )));
+        state.process(Some(Command::SetTransition(linear(Duration::from_secs(
+            600,
+        )))));
+        state.process(Some(Command::RemoveScheduler("morning".to_string())));
+
+        clock.set(at("2021-06-02 07:00:01"));
+        match state.process(None) {
+            Action::Set(strength) => assert!(strength.into_inner() < 0.1),
+            action => panic!("expected the transition to continue, got {:?}", action),
+        }
+    }
+
+    #[test]
+    fn replacing_config_during_transition_drops_wake_up() {
+        let (mut state, _, clock) = state_at("2021-06-02 06:59:00");
+        state.process(Some(Command::AddReplaceScheduler(
+            "morning".to_string(),
+            cron("0 7 * * *", 0, 0.5),
+        )));
+        state.process(Some(Command::SetTransition(linear(Duration::from_secs(
+            600,
+        )))));
+        state.process(Some(Command::ReplaceConfig(
+            Box::new(WeekScheduler::empty(Transition::default())),
+            HashMap::new(),
+            HashSet::new(),
+        )));
+
+        clock.set(at("2021-06-02 07:00:01"));
+        match state.process(None) {
+            Action::Set(strength) => assert!(strength.into_inner() < 0.1),
+            action => panic!("expected the transition to continue, got {:?}", action),
+        }
+    }
+
+    #[test]
+    fn replacing_scheduler_with_earlier_one_while_waiting() {
+        let (mut state, _, clock) = state_at("2021-06-02 06:00:00");
+        state.process(Some(Command::AddReplaceScheduler(
+            "morning".to_string(),
+            cron("0 7 * * *", 0, 0.5),
+        )));
+        state.process(Some(Command::SetTransition(linear(Duration::from_secs(
+            3600,
+        )))));
+        state.process(Some(Command::AddReplaceScheduler(
+            "morning".to_string(),
+            cron("30 6 * * *", 0, 0.3),
+        )));
+
+        clock.set(at("2021-06-02 06:30:01"));
+        assert_eq!(state.process(None), Action::Set(Strength::new(0.3)));
+        // The old time doesn't run.
+        clock.set(at("2021-06-02 07:00:01"));
+        assert_eq!(
+            state.process(None),
+            Action::Wait(SleepTime::To(at("2021-06-03 06:30:00")))
+        );
+    }
+
+    #[test]
+    fn changing_the_day_while_waiting() {
+        let (mut state, _, clock) = state_at("2021-06-02 06:00:00");
+        let instant = Transition::new(
+            Strength::off(),
+            Strength::full(),
+            Duration::ZERO,
+            TransitionInterpolation::Constant,
+        )
+        .unwrap();
+        state.process(Some(Command::ChangeDayTimerTransition(instant)));
+        state.process(Some(Command::ChangeDayTimer(
+            Weekday::Wed,
+            NaiveTime::from_hms_opt(7, 0, 0),
+        )));
+        assert_eq!(
+            state.process(None),
+            Action::Wait(SleepTime::To(at("2021-06-02 07:00:00")))
+        );
+        state.process(Some(Command::SetTransition(linear(Duration::from_secs(
+            3600,
+        )))));
+        state.process(Some(Command::ChangeDayTimer(Weekday::Wed, None)));
+
+        clock.set(at("2021-06-02 07:00:01"));
+        match state.process(None) {
+            Action::Set(strength) => assert!(strength.into_inner() < 0.5),
+            action => panic!("expected the transition to continue, got {:?}", action),
+        }
+    }
+
+    #[test]
+    fn scheduler_due_during_transition_fires() {
+        let (mut state, _, clock) = state_at("2021-06-02 06:59:00");
+        let fade = Box::new(crate::CronScheduler::new(
+            crate::cron::CronSchedule::parse("0 7 * * *").unwrap(),
+            "fade".to_string(),
+            ClonableCommand::new(Command::SetTransition(linear(Duration::from_secs(600)))).unwrap(),
+        ));
+        state.process(Some(Command::AddReplaceScheduler("fade".to_string(), fade)));
+        state.process(Some(Command::AddReplaceScheduler(
+            "dim".to_string(),
+            cron("5 7 * * *", 0, 0.2),
+        )));
+
+        clock.set(at("2021-06-02 07:00:01"));
+        match state.process(None) {
+            Action::Set(strength) => assert!(strength.into_inner() < 0.1),
+            action => panic!("expected the fade to start, got {:?}", action),
         }
+        // The next update of the fade, as the controller does every transition interval.
+        clock.set(at("2021-06-02 07:00:02"));
+        state.process(None);
+        // Before the fade is done.
+        clock.set(at("2021-06-02 07:05:01"));
+        assert_eq!(state.process(None), Action::Set(Strength::new(0.2)));
     }
 }
@@ -0,0 +1,257 @@
+//! Helpers for (de)serializing [`chrono`] and [`std`] types in a human friendly format,
+//! used with `#[serde(with = "...")]`,
+//! and the representations of the types in this crate which don't map directly.
+
+use crate::{
+    InterpolationParseError, Strength, Transition, TransitionError, TransitionInterpolation,
+};
+use chrono::{NaiveDateTime, NaiveTime};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::TryFrom;
+use std::time::Duration;
+
+const TIME_FORMAT: &str = "%H:%M:%S";
+
+fn parse_time<E: Error>(string: &str) -> Result<NaiveTime, E> {
+    NaiveTime::parse_from_str(string, TIME_FORMAT)
+        .or_else(|_| NaiveTime::parse_from_str(string, "%H:%M"))
+        .map_err(|_| E::custom(format!("invalid time {:?}, expected HH:MM[:SS]", string)))
+}
+
+/// [`Option`]al [`NaiveTime`] as `"HH:MM:SS"` or `null`. `"HH:MM"` is also accepted when deserializing.
+pub mod opt_time {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        time: &Option<NaiveTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => serializer.serialize_some(&time.format(TIME_FORMAT).to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<NaiveTime>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(time) => parse_time(&time).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// [`Option`]al [`NaiveDateTime`] as `"YYYY-MM-DD HH:MM:SS"` or `null`. Only serializes.
+pub mod opt_date_time {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        date_time: &Option<NaiveDateTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match date_time {
+            Some(date_time) => serializer.serialize_some(
+                &date_time
+                    .format(&format!("%Y-%m-%d {}", TIME_FORMAT))
+                    .to_string(),
+            ),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+/// [`Duration`] as seconds in a `f64`.
+pub mod duration_secs {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        if !secs.is_finite() || secs < 0.0 {
+            return Err(D::Error::custom(format!(
+                "invalid duration {} seconds",
+                secs
+            )));
+        }
+        Ok(Duration::from_secs_f64(secs))
+    }
+}
+
+impl Serialize for Strength {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.0)
+    }
+}
+impl<'de> Deserialize<'de> for Strength {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = f64::deserialize(deserializer)?;
+        Self::try_new(value).map_err(D::Error::custom)
+    }
+}
+
+/// See [`TransitionInterpolation`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InterpolationRepr {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    multiplier: Option<f64>,
+}
+impl From<TransitionInterpolation> for InterpolationRepr {
+    fn from(interpolation: TransitionInterpolation) -> Self {
+        Self {
+            name: interpolation.as_str().to_string(),
+            multiplier: interpolation.multiplier(),
+        }
+    }
+}
+impl TryFrom<InterpolationRepr> for TransitionInterpolation {
+    type Error = InterpolationParseError;
+    fn try_from(repr: InterpolationRepr) -> Result<Self, Self::Error> {
+        Self::from_name(&repr.name, repr.multiplier)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum InterpolationField {
+    Repr(InterpolationRepr),
+    /// The name, with the multiplier in [`TransitionRepr::extras`].
+    Name(String),
+}
+/// See [`Transition`].
+#[derive(Debug, Deserialize)]
+pub struct TransitionRepr {
+    from: Strength,
+    to: Strength,
+    #[serde(with = "duration_secs")]
+    time: Duration,
+    interpolation: InterpolationField,
+    #[serde(default)]
+    extras: Vec<String>,
+}
+impl TryFrom<TransitionRepr> for Transition {
+    type Error = TransitionError;
+    fn try_from(repr: TransitionRepr) -> Result<Self, Self::Error> {
+        let interpolation = match repr.interpolation {
+            InterpolationField::Repr(repr) => TransitionInterpolation::try_from(repr)?,
+            InterpolationField::Name(name) => {
+                TransitionInterpolation::from_str(&name, &repr.extras)?
+            }
+        };
+        Self::new(repr.from, repr.to, repr.time, interpolation)
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use crate::scheduler::WeekScheduler;
+    use crate::{Strength, Transition, TransitionInterpolation};
+    use chrono::{NaiveTime, Weekday};
+    use std::time::Duration;
+
+    #[test]
+    fn week_scheduler_round_trip() {
+        let slow = Transition::new(
+            Strength::off(),
+            Strength::new(0.6),
+            Duration::from_secs(20 * 60),
+            TransitionInterpolation::Sine,
+        )
+        .unwrap();
+        let scheduler = WeekScheduler::builder(Transition::default())
+            .day(Weekday::Mon, NaiveTime::from_hms_opt(7, 0, 0).unwrap())
+            .day(Weekday::Wed, NaiveTime::from_hms_opt(6, 45, 30).unwrap())
+            .day_with_transition(
+                Weekday::Sun,
+                NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                slow,
+            )
+            .build();
+
+        let json = serde_json::to_string(&scheduler).unwrap();
+        assert!(json.contains(r#""wed":"06:45:30""#), "{}", json);
+        assert!(json.contains(r#""tue":null"#), "{}", json);
+        let parsed: WeekScheduler = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, scheduler);
+
+        // Times without seconds are accepted too.
+        let json = json.replace(r#""06:45:30""#, r#""06:45""#);
+        let parsed: WeekScheduler = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.wed, NaiveTime::from_hms_opt(6, 45, 0));
+    }
+
+    #[derive(Debug)]
+    struct Squared;
+    impl crate::easing::Easing for Squared {
+        fn apply(&self, t: f64) -> f64 {
+            t * t
+        }
+        fn name(&self) -> &str {
+            "squared"
+        }
+    }
+
+    #[test]
+    fn transition_round_trip() {
+        crate::easing::register(Squared);
+        let interpolations = [
+            TransitionInterpolation::Linear,
+            TransitionInterpolation::Sine,
+            TransitionInterpolation::LinearToAndBack(0.25),
+            TransitionInterpolation::SineToAndBack(2.0),
+            TransitionInterpolation::Constant,
+            TransitionInterpolation::from_name("squared", None).unwrap(),
+        ];
+        for interpolation in interpolations {
+            let transition = Transition::new(
+                Strength::new(0.1),
+                Strength::new(0.9),
+                Duration::from_millis(1500),
+                interpolation,
+            )
+            .unwrap();
+            let json = serde_json::to_string(&transition).unwrap();
+            let parsed: Transition = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed, transition, "{}", json);
+        }
+
+        let json = r#"{"from":0,"to":1,"time":1.5,"interpolation":"sine-extra","extras":["0.5"]}"#;
+        let parsed: Transition = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            parsed.interpolation,
+            TransitionInterpolation::SineToAndBack(0.5)
+        );
+        assert_eq!(parsed.time, Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn strength_bounds() {
+        for (json, value) in [
+            ("0.5", 0.5),
+            ("0", 0.0),
+            ("0.0", 0.0),
+            ("1", 1.0),
+            ("1.0", 1.0),
+        ] {
+            let strength: Strength = serde_json::from_str(json).unwrap();
+            assert_eq!(strength, Strength::new(value));
+            assert_eq!(
+                serde_json::to_string(&strength).unwrap(),
+                serde_json::to_string(&value).unwrap()
+            );
+        }
+        for (json, message) in [
+            ("1.5", "strength 1.5 out of range"),
+            ("-0.1", "strength -0.1 out of range"),
+            ("1e300", "out of range"),
+        ] {
+            let err = serde_json::from_str::<Strength>(json).unwrap_err();
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+        assert!(serde_json::from_str::<Strength>("\"full\"").is_err());
+        assert!(serde_json::from_str::<Strength>("null").is_err());
+    }
+}
@@ -0,0 +1,319 @@
+//! A [`Scheduler`] firing relative to a solar event, such as sunset or civil twilight.
+//!
+//! The times are calculated using the [sunrise equation](https://en.wikipedia.org/wiki/Sunrise_equation),
+//! which is accurate to within a minute or so, away from the polar circles.
+
+use crate::scheduler::{Keep, LazyNow, Next, Scheduler};
+use crate::ClonableCommand;
+use chrono::prelude::*;
+use std::fmt::{self, Debug};
+
+/// How many days forward we look for the event before giving up.
+/// Beyond the polar circles, the sun can stay above or below the horizon for half a year.
+const MAX_DAYS_AHEAD: i64 = 190;
+/// The Julian date of 1970-01-01 12:00 UTC.
+const JULIAN_UNIX_NOON: f64 = 2_440_588.0;
+/// The Julian date of J2000.0, 2000-01-01 12:00 UTC.
+const JULIAN_2000: f64 = 2_451_545.0;
+
+/// An event during the day which depends on the position of the sun.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SolarEvent {
+    /// The upper edge of the sun appears on the horizon.
+    Sunrise,
+    /// The upper edge of the sun disappears below the horizon.
+    Sunset,
+    /// The start of morning civil twilight, when the sun is 6° below the horizon.
+    CivilDawn,
+    /// The end of evening civil twilight, when the sun is 6° below the horizon.
+    CivilDusk,
+    /// The start of morning nautical twilight, when the sun is 12° below the horizon.
+    NauticalDawn,
+    /// The end of evening nautical twilight, when the sun is 12° below the horizon.
+    NauticalDusk,
+}
+impl SolarEvent {
+    /// The altitude of the center of the sun at the event, in degrees.
+    pub fn altitude(&self) -> f64 {
+        match self {
+            // Accounts for refraction and the radius of the sun.
+            Self::Sunrise | Self::Sunset => -0.833,
+            Self::CivilDawn | Self::CivilDusk => -6.0,
+            Self::NauticalDawn | Self::NauticalDusk => -12.0,
+        }
+    }
+    /// If the event is in the morning, when the sun rises.
+    pub fn is_morning(&self) -> bool {
+        matches!(self, Self::Sunrise | Self::CivilDawn | Self::NauticalDawn)
+    }
+}
+impl fmt::Display for SolarEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Sunrise => "sunrise",
+            Self::Sunset => "sunset",
+            Self::CivilDawn => "civil dawn",
+            Self::CivilDusk => "civil dusk",
+            Self::NauticalDawn => "nautical dawn",
+            Self::NauticalDusk => "nautical dusk",
+        })
+    }
+}
+
+/// Error from [`SunScheduler::new()`].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SunError {
+    /// The latitude isn't in `[-90, 90]`.
+    LatitudeOutOfRange(f64),
+    /// The longitude isn't in `[-180, 180]`.
+    LongitudeOutOfRange(f64),
+    /// The offset is a day or longer, which would push the event past the next day's.
+    OffsetTooLarge(chrono::Duration),
+}
+impl fmt::Display for SunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LatitudeOutOfRange(latitude) => {
+                write!(f, "latitude {} out of range [-90, 90]", latitude)
+            }
+            Self::LongitudeOutOfRange(longitude) => {
+                write!(f, "longitude {} out of range [-180, 180]", longitude)
+            }
+            Self::OffsetTooLarge(offset) => write!(
+                f,
+                "offset of {}s passes the next day's event",
+                offset.num_seconds()
+            ),
+        }
+    }
+}
+impl std::error::Error for SunError {}
+
+/// Gets the time of `event` on `date` at `latitude` and `longitude` (east is positive), in UTC.
+///
+/// `date` is the date at the location. Returns `None` if the sun doesn't reach the altitude
+/// of the event that day, such as sunset during the midnight sun.
+pub fn event_on(
+    event: SolarEvent,
+    date: NaiveDate,
+    latitude: f64,
+    longitude: f64,
+) -> Option<DateTime<Utc>> {
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1)?;
+    let days = (date - epoch).num_days() as f64;
+    // Days since J2000.0 at the mean solar noon of `longitude`.
+    let noon = days + JULIAN_UNIX_NOON - JULIAN_2000 - longitude / 360.0;
+
+    let anomaly = (357.5291 + 0.985_600_28 * noon)
+        .rem_euclid(360.0)
+        .to_radians();
+    let center =
+        1.9148 * anomaly.sin() + 0.0200 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic_longitude = (anomaly.to_degrees() + center + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let transit =
+        JULIAN_2000 + noon + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+    let declination = (ecliptic_longitude.sin() * 23.4397_f64.to_radians().sin()).asin();
+
+    let latitude = latitude.to_radians();
+    let hour_angle_cos = (event.altitude().to_radians().sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&hour_angle_cos) {
+        return None;
+    }
+    let hour_angle = hour_angle_cos.acos().to_degrees() / 360.0;
+    let julian = if event.is_morning() {
+        transit - hour_angle
+    } else {
+        transit + hour_angle
+    };
+
+    let seconds = ((julian - JULIAN_UNIX_NOON + 0.5) * 86_400.0).round() as i64;
+    Utc.timestamp_opt(seconds, 0).single()
+}
+
+/// A scheduler firing at a [`SolarEvent`] each day, optionally offset before or after it.
+///
+/// `Tz` is the time zone of the location, used to convert the event to local time.
+/// Days when the event doesn't happen are skipped.
+pub struct SunScheduler<Tz: TimeZone> {
+    event: SolarEvent,
+    latitude: f64,
+    longitude: f64,
+    offset: chrono::Duration,
+    time_zone: Tz,
+    description: String,
+    command: ClonableCommand,
+    priority: i32,
+}
+impl<Tz: TimeZone> SunScheduler<Tz> {
+    /// `offset` is added to the time of the event; negative means before it.
+    ///
+    /// # Errors
+    ///
+    /// If the coordinates are out of range or if the offset is a day or longer in either direction.
+    pub fn new(
+        event: SolarEvent,
+        latitude: f64,
+        longitude: f64,
+        offset: chrono::Duration,
+        time_zone: Tz,
+        description: String,
+        command: ClonableCommand,
+    ) -> Result<Self, SunError> {
+        if !(-90.0..=90.0).contains(&latitude) {
+            return Err(SunError::LatitudeOutOfRange(latitude));
+        }
+        if !(-180.0..=180.0).contains(&longitude) {
+            return Err(SunError::LongitudeOutOfRange(longitude));
+        }
+        let day = chrono::Duration::days(1);
+        if offset >= day || offset <= -day {
+            return Err(SunError::OffsetTooLarge(offset));
+        }
+        Ok(Self {
+            event,
+            latitude,
+            longitude,
+            offset,
+            time_zone,
+            description,
+            command,
+            priority: 0,
+        })
+    }
+    /// See [`Scheduler::priority()`].
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+    pub fn event(&self) -> SolarEvent {
+        self.event
+    }
+    pub fn offset(&self) -> chrono::Duration {
+        self.offset
+    }
+    /// The local time this runs at on `date`, including the offset.
+    ///
+    /// Returns `None` if the event doesn't happen on `date`.
+    /// The offset can make the returned time fall on the previous or next date.
+    pub fn time_on(&self, date: NaiveDate) -> Option<NaiveDateTime> {
+        let utc = event_on(self.event, date, self.latitude, self.longitude)?;
+        Some(utc.with_timezone(&self.time_zone).naive_local() + self.offset)
+    }
+    /// Gets the first time this runs at or after `now`.
+    pub fn next_from(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        // The offset can move an event to the adjacent date, so start a day early.
+        let mut date = now.date().pred_opt()?;
+        for _ in 0..MAX_DAYS_AHEAD {
+            if let Some(time) = self.time_on(date) {
+                if time >= now {
+                    return Some(time);
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+}
+impl<Tz: TimeZone> Debug for SunScheduler<Tz> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SunScheduler")
+            .field("event", &self.event)
+            .field("latitude", &self.latitude)
+            .field("longitude", &self.longitude)
+            .field("offset", &self.offset)
+            .field("description", &self.description)
+            .field("command", &self.command)
+            .field("priority", &self.priority)
+            .finish()
+    }
+}
+impl<Tz: TimeZone + Send + Sync> Scheduler for SunScheduler<Tz> {
+    fn advance(&mut self) -> Keep {
+        Keep::Keep
+    }
+    fn get_next(&self, now: &mut LazyNow) -> Next {
+        match self.next_from(now.now()) {
+            Some(date_time) => Next::At(
+                date_time,
+                ClonableCommand::clone(&self.command).into_inner(),
+            ),
+            None => Next::Unknown,
+        }
+    }
+    fn description(&self) -> &str {
+        self.description.as_str()
+    }
+    fn kind(&self) -> &str {
+        "Sun"
+    }
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, Strength};
+
+    /// Stockholm, in summer time.
+    fn stockholm_sunset(offset: chrono::Duration) -> SunScheduler<FixedOffset> {
+        SunScheduler::new(
+            SolarEvent::Sunset,
+            59.3293,
+            18.0686,
+            offset,
+            FixedOffset::east_opt(2 * 60 * 60).unwrap(),
+            "sunset".to_string(),
+            ClonableCommand::new(Command::Set(Strength::full())).unwrap(),
+        )
+        .unwrap()
+    }
+    fn at(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    /// Within a couple of minutes of `expected`, as the equation is approximate.
+    fn assert_near(time: NaiveDateTime, expected: &str) {
+        let difference = (time - at(expected)).num_seconds().abs();
+        assert!(difference <= 120, "{} isn't near {}", time, expected);
+    }
+
+    #[test]
+    fn sunset_with_offsets() {
+        // Midsummer in Stockholm, when the sun sets at 22:08.
+        let date = NaiveDate::from_ymd_opt(2021, 6, 21).unwrap();
+        let sunset = stockholm_sunset(chrono::Duration::zero());
+        assert_near(sunset.time_on(date).unwrap(), "2021-06-21 22:08:00");
+
+        let after = stockholm_sunset(chrono::Duration::minutes(30));
+        assert_near(after.time_on(date).unwrap(), "2021-06-21 22:38:00");
+        let before = stockholm_sunset(chrono::Duration::minutes(-30));
+        assert_near(before.time_on(date).unwrap(), "2021-06-21 21:38:00");
+
+        // Once it's passed, the next is the day after.
+        let next = before.next_from(at("2021-06-21 21:50:00")).unwrap();
+        assert_eq!(next.date(), date.succ_opt().unwrap());
+        assert_near(next, "2021-06-22 21:38:00");
+    }
+
+    #[test]
+    fn offset_of_a_day_is_rejected() {
+        let day = chrono::Duration::days(1);
+        for offset in [day, -day] {
+            let result = SunScheduler::new(
+                SolarEvent::Sunset,
+                59.3293,
+                18.0686,
+                offset,
+                Utc,
+                "sunset".to_string(),
+                ClonableCommand::new(Command::Set(Strength::full())).unwrap(),
+            );
+            assert_eq!(result.unwrap_err(), SunError::OffsetTooLarge(offset));
+        }
+    }
+}
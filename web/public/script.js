@@ -1,6 +1,16 @@
 !> cache dynamic
 let toSend = null;
 
+// If the server requires a token, set it with `localStorage.setItem("token", "...")`.
+const token = localStorage.getItem("token");
+const unauthorizedFetch = window.fetch;
+window.fetch = (resource, options = {}) => {
+    if (token !== null) {
+        options.headers = { ...options.headers, Authorization: `Bearer ${token}` };
+    }
+    return unauthorizedFetch(resource, options);
+};
+
 let mainStrength = document.getElementById("strength");
 
 let from = document.getElementById("fromStrength");
@@ -18,6 +28,7 @@ let schedulerKind = document.getElementById("schedulerKind");
 let schedulerDate = document.getElementById("schedulerDate");
 let schedulerWeekday = document.getElementById("schedulerWeekday");
 let schedulerTime = document.getElementById("schedulerTime");
+let schedulerCron = document.getElementById("schedulerCron");
 let schedulerName = document.getElementById("schedulerName");
 let schedulerDescription = document.getElementById("schedulerDescription");
 
@@ -28,6 +39,22 @@ window.setInterval(() => {
     }
 }, 25);
 
+// Keeps the slider in sync with changes made by transitions, schedulers, and other clients.
+function watchStrength() {
+    let protocol = location.protocol === "https:" ? "wss:" : "ws:";
+    let socket = new WebSocket(`${protocol}//${location.host}/ws/strength`);
+    socket.addEventListener("message", (message) => {
+        let strength = parseFloat(message.data);
+        // Don't move the slider under the user.
+        if (toSend === null && !isNaN(strength)) {
+            mainStrength.value = strength;
+        }
+    });
+    // Offline or restarting; don't hammer the server.
+    socket.addEventListener("close", () => setTimeout(watchStrength, 5000));
+}
+watchStrength();
+
 window.addEventListener("unhandledrejection", (message) => {
     if (message.reason.message === "Failed to fetch") {
         message.preventDefault();
@@ -121,8 +148,10 @@ function getTransition() {
         from: Number(from.value),
         to: Number(to.value),
         time: Number(time.value),
-        interpolation: interpolation.value,
-        extras: [interpolationExtras.value]
+        interpolation: {
+            name: interpolation.value,
+            multiplier: interpolation.value.endsWith("-extra") ? Number(interpolationExtras.value) : undefined
+        }
     };
 }
 async function getAndSetTransition(action) {
@@ -209,22 +238,27 @@ async function overrideSchedulerList() {
 }
 
 function checkSchedulerAddExtras() {
-    let { date, day } = getSchedulerExtras();
+    let { date, day, cron } = getSchedulerExtras();
 
     schedulerDate.style.display = date ? "" : "none";
     schedulerWeekday.style.display = day ? "" : "none";
+    schedulerCron.style.display = cron ? "" : "none";
+    schedulerTime.style.display = cron ? "none" : "";
 }
 function getSchedulerExtras() {
     let kind = schedulerKind.value;
     let date = false;
     let day = false;
+    let cron = false;
 
     if (kind === "at") {
         date = true;
     } else if (kind === "every-week") {
         day = true;
+    } else if (kind === "cron") {
+        cron = true;
     }
-    return { date: date, day: day };
+    return { date: date, day: day, cron: cron };
 }
 async function getAndAddScheduler() {
     let name = schedulerName.value;
@@ -238,13 +272,16 @@ async function getAndAddScheduler() {
     let kind = schedulerKind.value;
     let time = schedulerTime.value;
     let extras = [];
-    let { date: send_date, day: send_day } = getSchedulerExtras();
+    let { date: send_date, day: send_day, cron: send_cron } = getSchedulerExtras();
     if (send_date) {
         extras.push(schedulerDate.value);
     }
     if (send_day) {
         extras.push(schedulerWeekday.value);
     }
+    if (send_cron) {
+        extras.push(schedulerCron.value);
+    }
 
     const body = { kind: kind, time: time, name: name, description: description, extras: extras, transition: getTransition() };
 